@@ -0,0 +1,49 @@
+#![allow(dead_code)]
+
+//! Bounds checked integer and string reads used by the hand rolled format parsers.
+
+pub fn array<const N: usize>(buf: &[u8], offset: usize) -> Option<[u8; N]> {
+    buf.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+pub fn u16_le(buf: &[u8], offset: usize) -> Option<u16> {
+    array(buf, offset).map(u16::from_le_bytes)
+}
+
+pub fn u32_le(buf: &[u8], offset: usize) -> Option<u32> {
+    array(buf, offset).map(u32::from_le_bytes)
+}
+
+pub fn u64_le(buf: &[u8], offset: usize) -> Option<u64> {
+    array(buf, offset).map(u64::from_le_bytes)
+}
+
+pub fn u16_be(buf: &[u8], offset: usize) -> Option<u16> {
+    array(buf, offset).map(u16::from_be_bytes)
+}
+
+pub fn u32_be(buf: &[u8], offset: usize) -> Option<u32> {
+    array(buf, offset).map(u32::from_be_bytes)
+}
+
+pub fn u64_be(buf: &[u8], offset: usize) -> Option<u64> {
+    array(buf, offset).map(u64::from_be_bytes)
+}
+
+/// NUL terminated string starting at `offset`, lossily decoded.
+pub fn cstr(buf: &[u8], offset: usize) -> Option<String> {
+    let bytes = buf.get(offset..)?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+/// Fixed width field padded with NULs or spaces.
+pub fn padded_str(buf: &[u8]) -> String {
+    let end = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).trim_end().to_string()
+}
+
+/// Hex string of `bytes`, lower case with no separators.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
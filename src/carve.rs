@@ -0,0 +1,160 @@
+//! Objects embedded anywhere in a file, found by searching for every
+//! signature in the extension tables at once.
+use crate::magic::{SIGNATURES, Signature};
+use crate::polyglot;
+use aho_corasick::AhoCorasick;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Signatures this long, and with this many bytes other than zero, are
+/// believable on their own. Others need the object's structure to check
+/// out too, or every small integer in a binary would look like an icon.
+const MIN_UNVERIFIED_BYTES: usize = 4;
+const MIN_UNVERIFIED_NONZERO_BYTES: usize = 2;
+/// Objects reported per file, so a blob of repeating signatures stays cheap.
+const MAX_OBJECTS: usize = 10_000;
+
+/// The longest literal of each signature, pattern ids being indexes into
+/// `SIGNATURES`.
+static MATCHER: LazyLock<AhoCorasick> = LazyLock::new(|| {
+    AhoCorasick::new(SIGNATURES.iter().map(|s| s.literal().1)).expect("valid signature literals")
+});
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Carved {
+    pub offset: usize,
+    pub formats: Vec<String>,
+    /// Known for formats whose structure says where they end.
+    pub length: Option<usize>,
+}
+
+pub fn carve(data: &[u8]) -> Vec<Carved> {
+    let mut starts: BTreeMap<usize, Vec<&Signature>> = BTreeMap::new();
+    for found in MATCHER.find_overlapping_iter(data) {
+        let signature = &SIGNATURES[found.pattern().as_usize()];
+        let (literal_offset, _) = signature.literal();
+        let Some(base) = found.start().checked_sub(literal_offset + signature.offset) else {
+            continue;
+        };
+        if signature.matches_at(data, base) {
+            starts.entry(base).or_default().push(signature);
+        }
+    }
+
+    let ends = polyglot::Ends::new(data);
+    let mut carved: Vec<Carved> = Vec::new();
+    for (offset, signatures) in starts {
+        // what's inside an object already carved, like the files of a zip,
+        // comes out with it and isn't measured again
+        if let Some(last) = carved.last()
+            && last
+                .length
+                .is_some_and(|length| offset < last.offset + length)
+        {
+            continue;
+        }
+        let length = ends
+            .length(data, offset)
+            .filter(|&l| l <= data.len() - offset);
+        let mut formats: Vec<String> = Vec::new();
+        for signature in signatures {
+            if length.is_none() && !believable(signature) {
+                continue;
+            }
+            for format in &signature.formats {
+                let name = format.to_string();
+                if !formats.contains(&name) {
+                    formats.push(name);
+                }
+            }
+        }
+        if !formats.is_empty() {
+            carved.push(Carved {
+                offset,
+                formats,
+                length,
+            });
+        }
+        if carved.len() == MAX_OBJECTS {
+            break;
+        }
+    }
+    carved
+}
+
+fn believable(signature: &Signature) -> bool {
+    let bytes = signature.significant_bytes().collect::<Vec<_>>();
+    bytes.len() >= MIN_UNVERIFIED_BYTES
+        && bytes.iter().filter(|b| **b != 0).count() >= MIN_UNVERIFIED_NONZERO_BYTES
+}
+
+/// Write each object to `dir`, named by its offset and first format.
+/// Objects of unknown length run up to the next object or the end of the
+/// file.
+pub fn extract(data: &[u8], carved: &[Carved], dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut paths = Vec::with_capacity(carved.len());
+    for (i, object) in carved.iter().enumerate() {
+        let end = match (object.length, carved.get(i + 1)) {
+            (Some(length), _) => object.offset + length,
+            (None, Some(next)) => next.offset,
+            (None, None) => data.len(),
+        };
+        let path = dir.join(format!("{:08x}.{}", object.offset, object.formats[0]));
+        std::fs::write(&path, &data[object.offset..end])?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Carved, carve};
+
+    #[test]
+    fn embedded_objects() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\0IEND\xAE\x42\x60\x82";
+        let mut data = b"firmware header MZ BM ".to_vec();
+        data.extend_from_slice(png);
+        data.extend_from_slice(b" padding ");
+        let xz = data.len();
+        data.extend_from_slice(b"\xFD7zXZ\0 compressed");
+        let carved = carve(&data);
+        assert_eq!(
+            carved,
+            [
+                Carved {
+                    offset: 22,
+                    formats: vec!["png".to_string()],
+                    length: Some(png.len()),
+                },
+                Carved {
+                    offset: xz,
+                    formats: vec!["xz".to_string()],
+                    length: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_objects() {
+        // a stored zip holding a PNG, with a central directory of one entry
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\0IEND\xAE\x42\x60\x82";
+        let mut data = b"PK\x03\x04".to_vec();
+        data.resize(30, 0);
+        data.extend_from_slice(png);
+        let directory = data.len();
+        data.extend_from_slice(b"PK\x01\x02");
+        data.resize(directory + 46, 0);
+        data.extend_from_slice(b"PK\x05\x06");
+        data.resize(directory + 46 + 22, 0);
+        data[directory + 46 + 12..directory + 46 + 16].copy_from_slice(&46u32.to_le_bytes());
+        data[directory + 46 + 16..directory + 46 + 20]
+            .copy_from_slice(&(directory as u32).to_le_bytes());
+        let carved = carve(&data);
+        assert_eq!(carved.len(), 1);
+        assert_eq!((carved[0].offset, carved[0].length), (0, Some(data.len())));
+    }
+}
@@ -0,0 +1,519 @@
+//! Columnar, array and scientific data files, with the schema where the
+//! format keeps it somewhere cheap to read.
+pub mod arrow;
+pub mod netcdf;
+pub mod orc;
+pub mod parquet;
+
+use crate::bytes::{u16_le, u32_le};
+use crate::disk::read_at;
+use crate::zip;
+use nu_protocol::{Record, Span, Value, record};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+const AVRO_MAGIC: &[u8] = b"Obj\x01";
+const HDF5_SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+const HDF4_SIGNATURE: &[u8] = b"\x0e\x03\x13\x01";
+/// Array headers are padded to 64 bytes, a huge one is not an array.
+const MAX_NPY_HEADER: usize = 64 * 1024;
+/// Avro metadata sits before the first block, schemas are rarely larger.
+const MAX_AVRO_HEADER: u64 = 1024 * 1024;
+/// An HDF5 superblock follows a user block of 0, 512, 1024, 2048... bytes.
+const MAX_HDF5_USERBLOCK: u64 = 1 << 30;
+/// The pickle opcodes for the protocol header and the end of the stream.
+const PICKLE_PROTO: u8 = 0x80;
+const PICKLE_STOP: u8 = b'.';
+
+pub enum Dataset {
+    Parquet(parquet::Metadata),
+    Arrow(arrow::Metadata),
+    /// The original Feather format, before it became Arrow IPC.
+    FeatherV1,
+    Avro {
+        codec: Option<String>,
+        schema: String,
+        columns: Vec<Column>,
+    },
+    Orc(orc::Metadata),
+    Hdf5 {
+        superblock_version: u8,
+        userblock: u64,
+    },
+    Hdf4,
+    NetCdf(netcdf::Metadata),
+    Npy(Array),
+    Npz(Vec<(String, Option<Array>)>),
+    Pickle {
+        protocol: u8,
+    },
+}
+
+pub struct Column {
+    pub name: String,
+    pub kind: String,
+    pub nullable: Option<bool>,
+}
+
+pub struct Array {
+    pub dtype: String,
+    pub shape: Vec<u64>,
+    pub fortran_order: bool,
+}
+
+impl Column {
+    fn into_value(self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "name" => Value::string(self.name, span),
+                "type" => Value::string(self.kind, span),
+                "nullable" => self.nullable.map_or(Value::nothing(span), |n| Value::bool(n, span)),
+            ),
+            span,
+        )
+    }
+}
+
+pub fn columns_value(columns: Vec<Column>, span: Span) -> Value {
+    Value::list(
+        columns.into_iter().map(|c| c.into_value(span)).collect(),
+        span,
+    )
+}
+
+impl Array {
+    fn push_fields(self, record: &mut Record, span: Span) {
+        record.push("dtype", Value::string(self.dtype, span));
+        record.push(
+            "shape",
+            Value::list(
+                self.shape
+                    .into_iter()
+                    .map(|n| Value::int(n as _, span))
+                    .collect(),
+                span,
+            ),
+        );
+        record.push("fortran_order", Value::bool(self.fortran_order, span));
+    }
+}
+
+impl Dataset {
+    pub fn format(&self) -> &'static str {
+        match self {
+            Dataset::Parquet(_) => "parquet",
+            Dataset::Arrow(_) => "arrow",
+            Dataset::FeatherV1 => "feather",
+            Dataset::Avro { .. } => "avro",
+            Dataset::Orc(_) => "orc",
+            Dataset::Hdf5 { .. } => "hdf5",
+            Dataset::Hdf4 => "hdf",
+            Dataset::NetCdf(_) => "nc",
+            Dataset::Npy(_) => "npy",
+            Dataset::Npz(_) => "npz",
+            Dataset::Pickle { .. } => "pickle",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Dataset::Parquet(_) => "application/vnd.apache.parquet",
+            Dataset::Arrow(_) | Dataset::FeatherV1 => "application/vnd.apache.arrow.file",
+            Dataset::Avro { .. } => "application/avro",
+            Dataset::Orc(_) => "application/vnd.apache.orc",
+            Dataset::Hdf5 { .. } => "application/x-hdf5",
+            Dataset::Hdf4 => "application/x-hdf",
+            Dataset::NetCdf(_) => "application/x-netcdf",
+            Dataset::Npy(_) => "application/x-npy",
+            Dataset::Npz(_) => "application/zip",
+            Dataset::Pickle { .. } => "application/x-python-pickle",
+        }
+    }
+
+    pub fn into_record(self, span: Span) -> Record {
+        let mut value = Record::new();
+        match self {
+            Dataset::Parquet(metadata) => metadata.push_fields(&mut value, span),
+            Dataset::Arrow(metadata) => metadata.push_fields(&mut value, span),
+            Dataset::FeatherV1 => value.push("version", Value::int(1, span)),
+            Dataset::Avro {
+                codec,
+                schema,
+                columns,
+            } => {
+                value.push(
+                    "codec",
+                    Value::string(codec.unwrap_or_else(|| "null".to_string()), span),
+                );
+                value.push("schema", Value::string(schema, span));
+                value.push("columns", columns_value(columns, span));
+            }
+            Dataset::Orc(metadata) => metadata.push_fields(&mut value, span),
+            Dataset::Hdf5 {
+                superblock_version,
+                userblock,
+            } => {
+                value.push(
+                    "superblock_version",
+                    Value::int(superblock_version as _, span),
+                );
+                value.push("userblock", Value::int(userblock as _, span));
+            }
+            Dataset::Hdf4 => {}
+            Dataset::NetCdf(metadata) => metadata.push_fields(&mut value, span),
+            Dataset::Npy(array) => array.push_fields(&mut value, span),
+            Dataset::Npz(arrays) => {
+                let arrays = arrays
+                    .into_iter()
+                    .map(|(name, array)| {
+                        let mut entry = record!("name" => Value::string(name, span));
+                        if let Some(array) = array {
+                            array.push_fields(&mut entry, span);
+                        }
+                        Value::record(entry, span)
+                    })
+                    .collect();
+                value.push("arrays", Value::list(arrays, span));
+            }
+            Dataset::Pickle { protocol } => {
+                value.push("protocol", Value::int(protocol as _, span));
+            }
+        }
+        value
+    }
+}
+
+pub fn detect_file(path: &Path) -> Option<Dataset> {
+    let mut file = File::open(path).ok()?;
+    if head(&mut file)?.starts_with(b"PK\x03\x04") {
+        // any zip starts like this, only ones named as arrays are looked into
+        let named = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("npz"));
+        return named.then(|| npz(&mut file)).flatten();
+    }
+    detect(&mut file)
+}
+
+pub fn detect<R: Read + Seek>(file: &mut R) -> Option<Dataset> {
+    let start = head(file)?;
+    match start.as_slice() {
+        [b'P', b'A', b'R', b'1', ..] => parquet::read(file).map(Dataset::Parquet),
+        [b'A', b'R', b'R', b'O', b'W', b'1', ..] => arrow::read(file).map(Dataset::Arrow),
+        [b'F', b'E', b'A', b'1', ..] => Some(Dataset::FeatherV1),
+        [b'O', b'R', b'C', ..] => orc::read(file).map(Dataset::Orc),
+        [b'C', b'D', b'F', 1 | 2 | 5, ..] => netcdf::read(file).map(Dataset::NetCdf),
+        _ if start.starts_with(AVRO_MAGIC) => avro(file),
+        _ if start.starts_with(NPY_MAGIC) => npy(file).map(Dataset::Npy),
+        _ if start.starts_with(HDF4_SIGNATURE) => Some(Dataset::Hdf4),
+        [PICKLE_PROTO, 2..=5, ..] => pickle(file),
+        _ => hdf5(file),
+    }
+}
+
+/// The first bytes, enough for every signature, fewer in tiny files.
+fn head<R: Read + Seek>(file: &mut R) -> Option<Vec<u8>> {
+    let mut start = Vec::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.take(8).read_to_end(&mut start).ok()?;
+    Some(start)
+}
+
+/// `.npy` files start with a Python dict literal describing the array.
+fn npy<R: Read + Seek>(file: &mut R) -> Option<Array> {
+    let prefix = read_at(file, 0, 12)?;
+    let (length, start) = match prefix[6] {
+        1 => (u16_le(&prefix, 8)? as usize, 10),
+        _ => (u32_le(&prefix, 8)? as usize, 12),
+    };
+    let header = read_at(file, start as u64, length.min(MAX_NPY_HEADER))?;
+    npy_header(&String::from_utf8_lossy(&header))
+}
+
+/// `{'descr': '<f8', 'fortran_order': False, 'shape': (3, 4), }`
+fn npy_header(header: &str) -> Option<Array> {
+    let value = |key: &str| {
+        let at = header.find(&format!("'{key}'"))? + key.len() + 2;
+        Some(header[at..].trim_start().strip_prefix(':')?.trim_start())
+    };
+    let descr = value("descr")?;
+    // structured dtypes are a list of fields, kept as written
+    let dtype = match descr.strip_prefix('\'') {
+        Some(rest) => rest[..rest.find('\'')?].to_string(),
+        None => {
+            let mut depth = 0;
+            let (end, c) = descr.char_indices().find(|&(_, c)| {
+                depth += match c {
+                    '[' | '(' => 1,
+                    ']' | ')' => -1,
+                    _ => 0,
+                };
+                depth == 0
+            })?;
+            descr[..end + c.len_utf8()].to_string()
+        }
+    };
+    let shape = value("shape")?.strip_prefix('(')?;
+    let shape = shape[..shape.find(')')?]
+        .split(',')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(|n| n.trim_end_matches('L').parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    Some(Array {
+        dtype,
+        shape,
+        fortran_order: value("fortran_order")?.starts_with("True"),
+    })
+}
+
+/// A zip of `.npy` files, as written by `numpy.savez`.
+/// Only the central directory and the start of each array are read.
+fn npz<R: Read + Seek>(file: &mut R) -> Option<Dataset> {
+    let entries = zip::read_entries(file)?;
+    if entries.is_empty() || !entries.iter().all(|e| e.name.ends_with(".npy")) {
+        return None;
+    }
+    let arrays = entries
+        .iter()
+        .map(|entry| {
+            let array = zip::read_prefix_from(file, entry, MAX_NPY_HEADER)
+                .and_then(|prefix| npy(&mut std::io::Cursor::new(prefix)));
+            (entry.name.trim_end_matches(".npy").to_string(), array)
+        })
+        .collect();
+    Some(Dataset::Npz(arrays))
+}
+
+/// Avro object container files open with a map of metadata, holding the
+/// schema as JSON and the block codec.
+fn avro<R: Read + Seek>(file: &mut R) -> Option<Dataset> {
+    file.seek(SeekFrom::Start(AVRO_MAGIC.len() as u64)).ok()?;
+    let mut header = Vec::new();
+    file.take(MAX_AVRO_HEADER).read_to_end(&mut header).ok()?;
+    let mut reader = AvroReader {
+        data: &header,
+        pos: 0,
+    };
+    let (mut schema, mut codec) = (None, None);
+    loop {
+        let mut count = reader.long()?;
+        if count == 0 {
+            break;
+        }
+        if count < 0 {
+            // a negative count is followed by the block's size in bytes
+            count = count.checked_neg()?;
+            reader.long()?;
+        }
+        for _ in 0..count {
+            let key = reader.bytes()?;
+            let value = reader.bytes()?;
+            match key {
+                b"avro.schema" => schema = Some(String::from_utf8_lossy(value).into_owned()),
+                b"avro.codec" => codec = Some(String::from_utf8_lossy(value).into_owned()),
+                _ => {}
+            }
+        }
+    }
+    let schema = schema?;
+    Some(Dataset::Avro {
+        columns: avro_columns(&schema),
+        codec,
+        schema,
+    })
+}
+
+struct AvroReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> AvroReader<'a> {
+    /// Zigzag encoded variable length integer.
+    fn long(&mut self) -> Option<i64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        None
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let length = usize::try_from(self.long()?).ok()?;
+        let bytes = self.data.get(self.pos..self.pos.checked_add(length)?)?;
+        self.pos += length;
+        Some(bytes)
+    }
+}
+
+/// The fields of a record schema. Unions with null are nullable.
+fn avro_columns(schema: &str) -> Vec<Column> {
+    let Ok(schema) = serde_json::from_str::<serde_json::Value>(schema) else {
+        return Vec::new();
+    };
+    let Some(fields) = schema.get("fields").and_then(|f| f.as_array()) else {
+        return Vec::new();
+    };
+    let name = |kind: &serde_json::Value| match kind {
+        serde_json::Value::String(name) => name.clone(),
+        serde_json::Value::Object(object) => object
+            .get("logicalType")
+            .or_else(|| object.get("type"))
+            .and_then(|t| t.as_str())
+            .unwrap_or("unknown")
+            .to_string(),
+        other => other.to_string(),
+    };
+    fields
+        .iter()
+        .map(|field| {
+            let kind = &field["type"];
+            let (kind, nullable) = match kind.as_array() {
+                Some(union) => {
+                    let types = union.iter().filter(|t| t.as_str() != Some("null"));
+                    (
+                        types.map(name).collect::<Vec<_>>().join(" | "),
+                        union.iter().any(|t| t.as_str() == Some("null")),
+                    )
+                }
+                None => (name(kind), false),
+            };
+            Column {
+                name: field["name"].as_str().unwrap_or_default().to_string(),
+                kind,
+                nullable: Some(nullable),
+            }
+        })
+        .collect()
+}
+
+/// The superblock version after the signature, which may follow a user block.
+fn hdf5<R: Read + Seek>(file: &mut R) -> Option<Dataset> {
+    let mut offset = 0;
+    while offset <= MAX_HDF5_USERBLOCK {
+        let superblock = read_at(file, offset, 9)?;
+        if superblock.starts_with(HDF5_SIGNATURE) {
+            return Some(Dataset::Hdf5 {
+                superblock_version: superblock[8],
+                userblock: offset,
+            });
+        }
+        offset = if offset == 0 { 512 } else { offset * 2 };
+    }
+    None
+}
+
+/// Protocol 2 and later pickles start with PROTO, and every pickle ends
+/// with STOP.
+fn pickle<R: Read + Seek>(file: &mut R) -> Option<Dataset> {
+    let protocol = read_at(file, 0, 2)?[1];
+    file.seek(SeekFrom::End(-1)).ok()?;
+    let mut last = [0u8];
+    file.read_exact(&mut last).ok()?;
+    (last[0] == PICKLE_STOP).then_some(Dataset::Pickle { protocol })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn arrays() {
+        let header = "{'descr': '<f8', 'fortran_order': False, 'shape': (3, 4), }";
+        let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+        npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        npy.extend_from_slice(header.as_bytes());
+        let Some(Dataset::Npy(array)) = detect(&mut Cursor::new(&npy)) else {
+            panic!("not npy");
+        };
+        assert_eq!((array.dtype.as_str(), array.shape), ("<f8", vec![3, 4]));
+
+        // the same array stored in a zip, found through the central directory
+        let mut npz = b"PK\x03\x04".to_vec();
+        npz.resize(30, 0);
+        npz[26] = 5;
+        npz.extend_from_slice(b"x.npy");
+        npz.extend_from_slice(&npy);
+        let directory = npz.len();
+        npz.extend_from_slice(b"PK\x01\x02");
+        npz.resize(directory + 46, 0);
+        npz[directory + 20..directory + 24].copy_from_slice(&(npy.len() as u32).to_le_bytes());
+        npz[directory + 24..directory + 28].copy_from_slice(&(npy.len() as u32).to_le_bytes());
+        npz[directory + 28] = 5;
+        npz.extend_from_slice(b"x.npy");
+        let end = npz.len();
+        npz.extend_from_slice(b"PK\x05\x06");
+        npz.resize(end + 22, 0);
+        npz[end + 10] = 1;
+        npz[end + 12..end + 16].copy_from_slice(&((end - directory) as u32).to_le_bytes());
+        npz[end + 16..end + 20].copy_from_slice(&(directory as u32).to_le_bytes());
+        let Some(Dataset::Npz(arrays)) = super::npz(&mut Cursor::new(&npz)) else {
+            panic!("not npz");
+        };
+        assert_eq!(arrays[0].0, "x");
+        assert_eq!(arrays[0].1.as_ref().unwrap().shape, [3, 4]);
+
+        let structured = npy_header(
+            "{'descr': [('x', '<i4'), ('y', '<f4')], 'fortran_order': True, 'shape': (10,), }",
+        )
+        .unwrap();
+        assert_eq!(structured.dtype, "[('x', '<i4'), ('y', '<f4')]");
+        assert_eq!(structured.shape, [10]);
+        assert!(structured.fortran_order);
+
+        let scalar = npy_header("{'descr': '|b1', 'fortran_order': False, 'shape': (), }");
+        assert!(scalar.unwrap().shape.is_empty());
+
+        // lossily decoded headers must not split a character
+        let garbled = npy_header("{'descr': \u{FFFD}, 'fortran_order': False, 'shape': (), }");
+        assert_eq!(garbled.unwrap().dtype, "\u{FFFD}");
+    }
+
+    #[test]
+    fn avro_schema() {
+        let schema = r#"{"type":"record","name":"User","fields":[{"name":"id","type":"long"},{"name":"email","type":["null","string"]}]}"#;
+        let mut avro = AVRO_MAGIC.to_vec();
+        // a map block of two entries, zigzag encoded lengths
+        avro.push(4);
+        for (key, value) in [("avro.schema", schema), ("avro.codec", "deflate")] {
+            for bytes in [key, value] {
+                let mut length = (bytes.len() as u64) << 1;
+                while length >= 0x80 {
+                    avro.push(length as u8 | 0x80);
+                    length >>= 7;
+                }
+                avro.push(length as u8);
+                avro.extend_from_slice(bytes.as_bytes());
+            }
+        }
+        avro.push(0);
+        let Some(Dataset::Avro {
+            codec,
+            schema: parsed,
+            columns,
+        }) = detect(&mut Cursor::new(&avro))
+        else {
+            panic!("not avro");
+        };
+        assert_eq!(
+            (codec.as_deref(), parsed.as_str()),
+            (Some("deflate"), schema)
+        );
+        assert_eq!(
+            columns
+                .iter()
+                .map(|c| (c.name.as_str(), c.kind.as_str(), c.nullable))
+                .collect::<Vec<_>>(),
+            [("id", "long", Some(false)), ("email", "string", Some(true))]
+        );
+    }
+}
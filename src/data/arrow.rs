@@ -0,0 +1,242 @@
+//! Arrow IPC files, also written as Feather v2: the schema and record
+//! batch locations are a FlatBuffers footer at the end of the file.
+use super::{Column, columns_value};
+use crate::bytes::{u16_le, u32_le, u64_le};
+use crate::disk::read_at;
+use nu_protocol::{Record, Span, Value};
+use std::io::{Read, Seek, SeekFrom};
+
+const MAGIC: &[u8] = b"ARROW1";
+const MAX_FOOTER: u64 = 64 * 1024 * 1024;
+/// Record batches read for their row counts, each one is a small read.
+const MAX_BATCHES: usize = 10_000;
+/// Messages start with this since Arrow 0.15.
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+const MESSAGE_RECORD_BATCH: u8 = 3;
+
+const TYPES: &[&str] = &[
+    "none",
+    "null",
+    "int",
+    "float",
+    "binary",
+    "utf8",
+    "bool",
+    "decimal",
+    "date",
+    "time",
+    "timestamp",
+    "interval",
+    "list",
+    "struct",
+    "union",
+    "fixed_size_binary",
+    "fixed_size_list",
+    "map",
+    "duration",
+    "large_binary",
+    "large_utf8",
+    "large_list",
+    "run_end_encoded",
+    "binary_view",
+    "utf8_view",
+    "list_view",
+    "large_list_view",
+];
+
+pub struct Metadata {
+    /// The IPC metadata version, V5 since Arrow 1.0.
+    pub version: i16,
+    pub record_batches: usize,
+    /// `None` when a batch could not be read.
+    pub rows: Option<u64>,
+    pub columns: Vec<Column>,
+}
+
+impl Metadata {
+    pub fn push_fields(self, record: &mut Record, span: Span) {
+        record.push(
+            "version",
+            Value::string(format!("V{}", self.version + 1), span),
+        );
+        record.push("record_batches", Value::int(self.record_batches as _, span));
+        record.push(
+            "rows",
+            self.rows
+                .map_or(Value::nothing(span), |r| Value::int(r as _, span)),
+        );
+        record.push("columns", columns_value(self.columns, span));
+    }
+}
+
+pub fn read<R: Read + Seek>(file: &mut R) -> Option<Metadata> {
+    let length = file.seek(SeekFrom::End(0)).ok()?;
+    let tail = read_at(file, length.checked_sub(10)?, 10)?;
+    let footer_length = u32_le(&tail, 0)? as u64;
+    if &tail[4..] != MAGIC || footer_length > MAX_FOOTER {
+        return None;
+    }
+    let footer = read_at(
+        file,
+        length.checked_sub(10 + footer_length)?,
+        footer_length as usize,
+    )?;
+    let footer = Table::root(&footer)?;
+
+    let columns = footer
+        .table(1)
+        .map(|schema| {
+            schema
+                .tables(1)
+                .into_iter()
+                .map(|field| Column {
+                    name: field.string(0).unwrap_or_default(),
+                    kind: type_name(&field),
+                    nullable: Some(field.scalar::<1>(1).is_some_and(|b| b[0] != 0)),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // record batches are `Block` structs: offset, metadata length, body length
+    let (start, count) = footer.vector(3).unwrap_or((0, 0));
+    let mut rows = Some(0u64);
+    for i in 0..count.min(MAX_BATCHES) {
+        let block = footer.buf.get(start + i * 24..start + (i + 1) * 24);
+        let batch = block.and_then(|b| batch_rows(file, length, u64_le(b, 0)?, u32_le(b, 8)?));
+        rows = rows
+            .zip(batch)
+            .and_then(|(total, batch)| total.checked_add(batch));
+    }
+    if count > MAX_BATCHES {
+        rows = None;
+    }
+
+    Some(Metadata {
+        version: footer.scalar::<2>(0).map_or(0, i16::from_le_bytes),
+        record_batches: count,
+        rows,
+        columns,
+    })
+}
+
+/// The `length` of the `RecordBatch` message at `offset`.
+fn batch_rows<R: Read + Seek>(
+    file: &mut R,
+    file_length: u64,
+    offset: u64,
+    length: u32,
+) -> Option<u64> {
+    if offset.checked_add(length as u64)? > file_length {
+        return None;
+    }
+    let message = read_at(file, offset, length as usize)?;
+    let skip = if u32_le(&message, 0)? == CONTINUATION {
+        8
+    } else {
+        4
+    };
+    let message = Table::root(message.get(skip..)?)?;
+    if message.scalar::<1>(1)?[0] != MESSAGE_RECORD_BATCH {
+        return None;
+    }
+    message
+        .table(2)?
+        .scalar::<8>(0)
+        .map(u64::from_le_bytes)
+        .or(Some(0))
+}
+
+/// Integer and floating point types get their width, others their name.
+fn type_name(field: &Table) -> String {
+    let kind = field.scalar::<1>(2).map_or(0, |k| k[0]);
+    let name = TYPES.get(kind as usize).copied().unwrap_or("unknown");
+    let detail = field.table(3);
+    match (name, detail) {
+        ("int", Some(int)) => {
+            let bits = int.scalar::<4>(0).map_or(0, i32::from_le_bytes);
+            let signed = int.scalar::<1>(1).is_some_and(|s| s[0] != 0);
+            format!("{}int{bits}", if signed { "" } else { "u" })
+        }
+        ("float", Some(float)) => match float.scalar::<2>(0).map_or(0, i16::from_le_bytes) {
+            0 => "float16",
+            1 => "float32",
+            _ => "float64",
+        }
+        .to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// A FlatBuffers table: fields are found through a vtable of offsets.
+struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Table<'a> {
+    fn root(buf: &'a [u8]) -> Option<Table<'a>> {
+        Some(Table {
+            buf,
+            pos: u32_le(buf, 0)? as usize,
+        })
+    }
+
+    /// Where field `index` is stored, `None` if it has its default value.
+    fn field(&self, index: usize) -> Option<usize> {
+        let back = u32_le(self.buf, self.pos)? as i32;
+        let vtable = usize::try_from(self.pos as i64 - back as i64).ok()?;
+        let entry = 4 + 2 * index;
+        if entry >= u16_le(self.buf, vtable)? as usize {
+            return None;
+        }
+        match u16_le(self.buf, vtable + entry)? {
+            0 => None,
+            offset => Some(self.pos + offset as usize),
+        }
+    }
+
+    fn scalar<const N: usize>(&self, index: usize) -> Option<[u8; N]> {
+        crate::bytes::array(self.buf, self.field(index)?)
+    }
+
+    /// Follow the offset stored in field `index`.
+    fn indirect(&self, index: usize) -> Option<usize> {
+        let at = self.field(index)?;
+        at.checked_add(u32_le(self.buf, at)? as usize)
+    }
+
+    fn table(&self, index: usize) -> Option<Table<'a>> {
+        Some(Table {
+            buf: self.buf,
+            pos: self.indirect(index)?,
+        })
+    }
+
+    fn string(&self, index: usize) -> Option<String> {
+        let (start, length) = self.vector(index)?;
+        let bytes = self.buf.get(start..start.checked_add(length)?)?;
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// Start and element count of a vector.
+    fn vector(&self, index: usize) -> Option<(usize, usize)> {
+        let at = self.indirect(index)?;
+        Some((at + 4, u32_le(self.buf, at)? as usize))
+    }
+
+    fn tables(&self, index: usize) -> Vec<Table<'a>> {
+        let Some((start, count)) = self.vector(index) else {
+            return Vec::new();
+        };
+        (0..count)
+            .map_while(|i| {
+                let at = start + 4 * i;
+                Some(Table {
+                    buf: self.buf,
+                    pos: at.checked_add(u32_le(self.buf, at)? as usize)?,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,287 @@
+//! NetCDF classic headers: dimensions, global attributes and variables,
+//! all big endian and padded to 4 bytes. NetCDF-4 files are HDF5.
+use crate::bytes::{u32_be, u64_be};
+use nu_protocol::{Record, Span, Value, record};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Headers come before the data, a few KiB unless there are thousands of
+/// variables.
+const MAX_HEADER: u64 = 4 * 1024 * 1024;
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+const NC_CHAR: u32 = 2;
+/// The record count of a file still being written.
+const STREAMING: u64 = 0xFFFF_FFFF;
+/// Type names and sizes by `nc_type`, starting at 1.
+const TYPES: &[(&str, usize)] = &[
+    ("byte", 1),
+    ("char", 1),
+    ("short", 2),
+    ("int", 4),
+    ("float", 4),
+    ("double", 8),
+    ("ubyte", 1),
+    ("ushort", 2),
+    ("uint", 4),
+    ("int64", 8),
+    ("uint64", 8),
+];
+
+pub struct Metadata {
+    pub version: &'static str,
+    pub records: Option<u64>,
+    pub dimensions: Vec<(String, u64)>,
+    /// Global attributes with text values, like `title` and `Conventions`.
+    pub attributes: Vec<(String, String)>,
+    pub variables: Vec<Variable>,
+}
+
+pub struct Variable {
+    pub name: String,
+    pub kind: &'static str,
+    pub dimensions: Vec<String>,
+}
+
+impl Metadata {
+    pub fn push_fields(self, record: &mut Record, span: Span) {
+        record.push("version", Value::string(self.version, span));
+        record.push(
+            "records",
+            self.records
+                .map_or(Value::nothing(span), |r| Value::int(r as _, span)),
+        );
+        let dimensions = self
+            .dimensions
+            .into_iter()
+            .map(|(name, size)| {
+                Value::record(
+                    record!(
+                        "name" => Value::string(name, span),
+                        // zero marks the unlimited record dimension
+                        "size" => if size == 0 { Value::nothing(span) } else { Value::int(size as _, span) },
+                    ),
+                    span,
+                )
+            })
+            .collect();
+        record.push("dimensions", Value::list(dimensions, span));
+        record.push(
+            "attributes",
+            Value::record(
+                self.attributes
+                    .into_iter()
+                    .map(|(name, value)| (name, Value::string(value, span)))
+                    .collect(),
+                span,
+            ),
+        );
+        let variables = self
+            .variables
+            .into_iter()
+            .map(|variable| {
+                Value::record(
+                    record!(
+                        "name" => Value::string(variable.name, span),
+                        "type" => Value::string(variable.kind, span),
+                        "dimensions" => Value::list(
+                            variable
+                                .dimensions
+                                .into_iter()
+                                .map(|d| Value::string(d, span))
+                                .collect(),
+                            span,
+                        ),
+                    ),
+                    span,
+                )
+            })
+            .collect();
+        record.push("variables", Value::list(variables, span));
+    }
+}
+
+pub fn read<R: Read + Seek>(file: &mut R) -> Option<Metadata> {
+    let mut header = Vec::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.take(MAX_HEADER).read_to_end(&mut header).ok()?;
+    let version = *header.get(3)?;
+    let mut reader = Header {
+        data: &header,
+        pos: 4,
+        // CDF-5 widens every count to 64 bits
+        wide: version == 5,
+    };
+    let records = reader.count()?;
+    let mut metadata = Metadata {
+        version: match version {
+            1 => "classic",
+            2 => "64-bit offset",
+            _ => "64-bit data",
+        },
+        records: (records != STREAMING).then_some(records),
+        dimensions: Vec::new(),
+        attributes: Vec::new(),
+        variables: Vec::new(),
+    };
+    // a truncated header still says what the file is
+    let _ = body(&mut reader, version, &mut metadata);
+    Some(metadata)
+}
+
+fn body(reader: &mut Header, version: u8, metadata: &mut Metadata) -> Option<()> {
+    for _ in 0..reader.list(NC_DIMENSION)? {
+        let name = reader.name()?;
+        metadata.dimensions.push((name, reader.count()?));
+    }
+    metadata.attributes = reader.attributes()?;
+    for _ in 0..reader.list(NC_VARIABLE)? {
+        let name = reader.name()?;
+        let dimensions = (0..reader.count()?)
+            .map(|_| {
+                let id = reader.count()?;
+                Some(
+                    metadata
+                        .dimensions
+                        .get(id as usize)
+                        .map_or_else(|| id.to_string(), |(name, _)| name.clone()),
+                )
+            })
+            .collect::<Option<Vec<_>>>()?;
+        reader.attributes()?;
+        let kind = reader.u32()?;
+        reader.count()?;
+        // the data offset is 32 bits only in the classic format
+        if version == 1 {
+            reader.u32()?;
+        } else {
+            reader.u64()?;
+        }
+        metadata.variables.push(Variable {
+            name,
+            kind: TYPES
+                .get((kind as usize).wrapping_sub(1))
+                .map_or("unknown", |(name, _)| name),
+            dimensions,
+        });
+    }
+    Some(())
+}
+
+struct Header<'a> {
+    data: &'a [u8],
+    pos: usize,
+    wide: bool,
+}
+
+impl<'a> Header<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(length)?)?;
+        // everything is padded to a multiple of 4 bytes
+        self.pos += length.next_multiple_of(4);
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        u32_be(self.take(4)?, 0)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        u64_be(self.take(8)?, 0)
+    }
+
+    fn count(&mut self) -> Option<u64> {
+        if self.wide {
+            self.u64()
+        } else {
+            self.u32().map(u64::from)
+        }
+    }
+
+    /// Element count of a tagged list, which is absent when zeroed.
+    fn list(&mut self, tag: u32) -> Option<u64> {
+        let found = self.u32()?;
+        let count = self.count()?;
+        // each element takes at least 4 bytes
+        (found == tag || (found == 0 && count == 0))
+            .then_some(count)
+            .filter(|&c| c <= (self.data.len() / 4) as u64)
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let length = usize::try_from(self.count()?).ok()?;
+        Some(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    /// Attributes, keeping the text valued ones.
+    fn attributes(&mut self) -> Option<Vec<(String, String)>> {
+        let mut text = Vec::new();
+        for _ in 0..self.list(NC_ATTRIBUTE)? {
+            let name = self.name()?;
+            let kind = self.u32()?;
+            let count = usize::try_from(self.count()?).ok()?;
+            let (_, size) = TYPES.get((kind as usize).wrapping_sub(1))?;
+            let values = self.take(count.checked_mul(*size)?)?;
+            if kind == NC_CHAR {
+                text.push((name, crate::bytes::padded_str(values)));
+            }
+        }
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn classic_header() {
+        let mut header = b"CDF\x01".to_vec();
+        let u32 = |header: &mut Vec<u8>, n: u32| header.extend_from_slice(&n.to_be_bytes());
+        let name = |header: &mut Vec<u8>, s: &str| {
+            header.extend_from_slice(&(s.len() as u32).to_be_bytes());
+            header.extend_from_slice(s.as_bytes());
+            header.resize(header.len().next_multiple_of(4), 0);
+        };
+        u32(&mut header, 12);
+        // time is the unlimited record dimension
+        u32(&mut header, NC_DIMENSION);
+        u32(&mut header, 2);
+        name(&mut header, "time");
+        u32(&mut header, 0);
+        name(&mut header, "lat");
+        u32(&mut header, 180);
+        u32(&mut header, NC_ATTRIBUTE);
+        u32(&mut header, 1);
+        name(&mut header, "title");
+        u32(&mut header, NC_CHAR);
+        u32(&mut header, 7);
+        header.extend_from_slice(b"sea ice\0");
+        u32(&mut header, NC_VARIABLE);
+        u32(&mut header, 1);
+        name(&mut header, "ice");
+        u32(&mut header, 2);
+        u32(&mut header, 0);
+        u32(&mut header, 1);
+        u32(&mut header, 0);
+        u32(&mut header, 0);
+        u32(&mut header, 5);
+        u32(&mut header, 720);
+        u32(&mut header, 200);
+
+        let metadata = read(&mut Cursor::new(&header)).unwrap();
+        assert_eq!((metadata.version, metadata.records), ("classic", Some(12)));
+        assert_eq!(
+            metadata.dimensions,
+            [("time".to_string(), 0), ("lat".to_string(), 180)]
+        );
+        assert_eq!(
+            metadata.attributes,
+            [("title".to_string(), "sea ice".to_string())]
+        );
+        let ice = &metadata.variables[0];
+        assert_eq!((ice.name.as_str(), ice.kind), ("ice", "float"));
+        assert_eq!(ice.dimensions, ["time", "lat"]);
+    }
+}
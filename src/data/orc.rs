@@ -0,0 +1,321 @@
+//! ORC files: a protobuf postscript in the last bytes says how the footer
+//! before it is compressed, and the footer holds the row count and types.
+use super::{Column, columns_value};
+use crate::disk::read_at;
+use flate2::read::DeflateDecoder;
+use nu_protocol::{Record, Span, Value};
+use std::io::{Read, Seek, SeekFrom};
+
+const MAGIC: &[u8] = b"ORC";
+const MAX_FOOTER: u64 = 16 * 1024 * 1024;
+const COMPRESSION: &[&str] = &["none", "zlib", "snappy", "lzo", "lz4", "zstd"];
+const KINDS: &[&str] = &[
+    "boolean",
+    "tinyint",
+    "smallint",
+    "int",
+    "bigint",
+    "float",
+    "double",
+    "string",
+    "binary",
+    "timestamp",
+    "array",
+    "map",
+    "struct",
+    "uniontype",
+    "decimal",
+    "date",
+    "varchar",
+    "char",
+    "timestamp with local time zone",
+];
+const KIND_STRUCT: u64 = 12;
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED32: u8 = 5;
+
+pub struct Metadata {
+    pub version: String,
+    pub compression: &'static str,
+    /// Only known when the footer is stored plain or with zlib.
+    pub rows: Option<u64>,
+    pub stripes: Option<usize>,
+    pub columns: Vec<Column>,
+}
+
+impl Metadata {
+    pub fn push_fields(self, record: &mut Record, span: Span) {
+        let optional =
+            |n: Option<u64>| n.map_or(Value::nothing(span), |n| Value::int(n as _, span));
+        record.push("version", Value::string(self.version, span));
+        record.push("compression", Value::string(self.compression, span));
+        record.push("rows", optional(self.rows));
+        record.push("stripes", optional(self.stripes.map(|s| s as _)));
+        record.push("columns", columns_value(self.columns, span));
+    }
+}
+
+pub fn read<R: Read + Seek>(file: &mut R) -> Option<Metadata> {
+    let length = file.seek(SeekFrom::End(0)).ok()?;
+    let postscript_length = read_at(file, length.checked_sub(1)?, 1)?[0] as u64;
+    let postscript = read_at(
+        file,
+        length.checked_sub(1 + postscript_length)?,
+        postscript_length as usize,
+    )?;
+
+    let (mut footer_length, mut compression, mut version) = (0, 0, Vec::new());
+    let mut reader = Protobuf {
+        data: &postscript,
+        pos: 0,
+    };
+    while let Some((id, wire)) = reader.key() {
+        match (id, wire) {
+            (1, VARINT) => footer_length = reader.varint()?,
+            (2, VARINT) => compression = reader.varint()?,
+            (4, VARINT) => version.push(reader.varint()?),
+            (4, LENGTH_DELIMITED) => version.extend(reader.packed()?),
+            (8000, LENGTH_DELIMITED) => {
+                if reader.bytes()? != MAGIC {
+                    return None;
+                }
+            }
+            _ => reader.skip(wire)?,
+        }
+    }
+
+    let mut metadata = Metadata {
+        version: version
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join("."),
+        compression: COMPRESSION
+            .get(compression as usize)
+            .copied()
+            .unwrap_or("unknown"),
+        rows: None,
+        stripes: None,
+        columns: Vec::new(),
+    };
+    if footer_length <= MAX_FOOTER
+        && let Some(footer) = read_at(
+            file,
+            length.checked_sub(1 + postscript_length + footer_length)?,
+            footer_length as usize,
+        )
+        && let Some(footer) = decompress(footer, metadata.compression)
+    {
+        footer_fields(&footer, &mut metadata);
+    }
+    Some(metadata)
+}
+
+/// Compressed streams are chunks with a 3 byte header holding the chunk
+/// length and whether it was stored as is.
+fn decompress(data: Vec<u8>, compression: &str) -> Option<Vec<u8>> {
+    match compression {
+        "none" => Some(data),
+        "zlib" => {
+            let mut out = Vec::new();
+            let mut pos = 0;
+            while pos < data.len() {
+                let header = data.get(pos..pos + 3)?;
+                let header =
+                    header[0] as usize | (header[1] as usize) << 8 | (header[2] as usize) << 16;
+                let chunk = data.get(pos + 3..(pos + 3).checked_add(header >> 1)?)?;
+                // the limit is on the whole footer, not each chunk
+                let remaining = MAX_FOOTER - out.len() as u64;
+                if header & 1 == 1 {
+                    if chunk.len() as u64 > remaining {
+                        return None;
+                    }
+                    out.extend_from_slice(chunk);
+                } else {
+                    DeflateDecoder::new(chunk)
+                        .take(remaining)
+                        .read_to_end(&mut out)
+                        .ok()?;
+                }
+                pos += 3 + chunk.len();
+            }
+            Some(out)
+        }
+        _ => None,
+    }
+}
+
+/// Stripe count, row count and the columns of the root struct.
+fn footer_fields(footer: &[u8], metadata: &mut Metadata) -> Option<()> {
+    let mut types = Vec::new();
+    let (mut stripes, mut rows) = (0, 0);
+    let mut reader = Protobuf {
+        data: footer,
+        pos: 0,
+    };
+    while let Some((id, wire)) = reader.key() {
+        match (id, wire) {
+            (3, LENGTH_DELIMITED) => {
+                reader.bytes()?;
+                stripes += 1;
+            }
+            (4, LENGTH_DELIMITED) => types.push(orc_type(reader.bytes()?)?),
+            (6, VARINT) => rows = reader.varint()?,
+            _ => reader.skip(wire)?,
+        }
+    }
+    metadata.rows = Some(rows);
+    metadata.stripes = Some(stripes);
+    if let Some((KIND_STRUCT, subtypes, names)) = types.first() {
+        metadata.columns = names
+            .iter()
+            .zip(subtypes)
+            .map(|(name, subtype)| Column {
+                name: name.clone(),
+                kind: types
+                    .get(*subtype as usize)
+                    .and_then(|(kind, ..)| KINDS.get(*kind as usize))
+                    .copied()
+                    .unwrap_or("unknown")
+                    .to_string(),
+                nullable: None,
+            })
+            .collect();
+    }
+    Some(())
+}
+
+/// A `Type` message: its kind, the ids of its children and their names.
+fn orc_type(data: &[u8]) -> Option<(u64, Vec<u64>, Vec<String>)> {
+    let (mut kind, mut subtypes, mut names) = (0, Vec::new(), Vec::new());
+    let mut reader = Protobuf { data, pos: 0 };
+    while let Some((id, wire)) = reader.key() {
+        match (id, wire) {
+            (1, VARINT) => kind = reader.varint()?,
+            (2, VARINT) => subtypes.push(reader.varint()?),
+            (2, LENGTH_DELIMITED) => subtypes.extend(reader.packed()?),
+            (3, LENGTH_DELIMITED) => {
+                names.push(String::from_utf8_lossy(reader.bytes()?).into_owned())
+            }
+            _ => reader.skip(wire)?,
+        }
+    }
+    Some((kind, subtypes, names))
+}
+
+/// Just enough protobuf to read varints and strings and skip the rest.
+struct Protobuf<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Protobuf<'a> {
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// The next field number and wire type, `None` at the end.
+    fn key(&mut self) -> Option<(u64, u8)> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let key = self.varint()?;
+        Some((key >> 3, (key & 7) as u8))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let length = usize::try_from(self.varint()?).ok()?;
+        let bytes = self.data.get(self.pos..self.pos.checked_add(length)?)?;
+        self.pos += length;
+        Some(bytes)
+    }
+
+    fn packed(&mut self) -> Option<Vec<u64>> {
+        let mut packed = Protobuf {
+            data: self.bytes()?,
+            pos: 0,
+        };
+        let mut values = Vec::new();
+        while packed.pos < packed.data.len() {
+            values.push(packed.varint()?);
+        }
+        Some(values)
+    }
+
+    fn skip(&mut self, wire: u8) -> Option<()> {
+        let width = match wire {
+            VARINT => return self.varint().map(drop),
+            LENGTH_DELIMITED => return self.bytes().map(drop),
+            FIXED64 => 8,
+            FIXED32 => 4,
+            _ => return None,
+        };
+        self.pos = self
+            .pos
+            .checked_add(width)
+            .filter(|&p| p <= self.data.len())?;
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn message(out: &mut Vec<u8>, id: u8, bytes: &[u8]) {
+        out.extend_from_slice(&[id << 3 | LENGTH_DELIMITED, bytes.len() as u8]);
+        out.extend_from_slice(bytes);
+    }
+
+    #[test]
+    fn footer() {
+        // a struct of a bigint and a string column
+        let mut root = vec![1 << 3, 12];
+        message(&mut root, 2, &[1, 2]);
+        message(&mut root, 3, b"id");
+        message(&mut root, 3, b"name");
+        let mut footer = Vec::new();
+        message(&mut footer, 3, &[]);
+        message(&mut footer, 4, &root);
+        message(&mut footer, 4, &[1 << 3, 4]);
+        message(&mut footer, 4, &[1 << 3, 7]);
+        footer.extend_from_slice(&[6 << 3, 42]);
+
+        let mut postscript = vec![1 << 3, footer.len() as u8, 2 << 3, 0];
+        message(&mut postscript, 4, &[0, 12]);
+        // field 8000 holds the magic again
+        postscript.extend_from_slice(&[0x82, 0xF4, 0x03, 3, b'O', b'R', b'C']);
+
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(&footer);
+        file.extend_from_slice(&postscript);
+        file.push(postscript.len() as u8);
+        let metadata = read(&mut Cursor::new(&file)).unwrap();
+        assert_eq!(
+            (metadata.version.as_str(), metadata.compression),
+            ("0.12", "none")
+        );
+        assert_eq!((metadata.rows, metadata.stripes), (Some(42), Some(1)));
+        assert_eq!(
+            metadata
+                .columns
+                .iter()
+                .map(|c| (c.name.as_str(), c.kind.as_str()))
+                .collect::<Vec<_>>(),
+            [("id", "bigint"), ("name", "string")]
+        );
+    }
+}
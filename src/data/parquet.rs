@@ -0,0 +1,462 @@
+//! Parquet file metadata, a Thrift compact protocol struct in the footer
+//! followed by its length and `PAR1`.
+use super::{Column, columns_value};
+use crate::bytes::u32_le;
+use crate::disk::read_at;
+use nu_protocol::{Record, Span, Value};
+use std::io::{Read, Seek, SeekFrom};
+
+const MAGIC: &[u8] = b"PAR1";
+/// Footers of very wide tables run to megabytes, beyond this is corrupt.
+const MAX_FOOTER: u64 = 64 * 1024 * 1024;
+/// Nesting allowed in the schema and in skipped Thrift structs.
+const MAX_DEPTH: usize = 64;
+
+const PHYSICAL_TYPES: &[&str] = &[
+    "boolean",
+    "int32",
+    "int64",
+    "int96",
+    "float",
+    "double",
+    "byte_array",
+    "fixed_len_byte_array",
+];
+/// Converted types, the original way of annotating physical types.
+const CONVERTED_TYPES: &[&str] = &[
+    "string",
+    "map",
+    "map_key_value",
+    "list",
+    "enum",
+    "decimal",
+    "date",
+    "time_millis",
+    "time_micros",
+    "timestamp_millis",
+    "timestamp_micros",
+    "uint8",
+    "uint16",
+    "uint32",
+    "uint64",
+    "int8",
+    "int16",
+    "int32",
+    "int64",
+    "json",
+    "bson",
+    "interval",
+];
+/// Logical types by their field id in the `LogicalType` union.
+const LOGICAL_TYPES: &[&str] = &[
+    "",
+    "string",
+    "map",
+    "list",
+    "enum",
+    "decimal",
+    "date",
+    "time",
+    "timestamp",
+    "",
+    "integer",
+    "null",
+    "json",
+    "bson",
+    "uuid",
+    "float16",
+    "variant",
+    "geometry",
+    "geography",
+];
+
+pub struct Metadata {
+    pub version: i64,
+    pub rows: i64,
+    pub row_groups: usize,
+    pub created_by: Option<String>,
+    pub columns: Vec<Column>,
+}
+
+impl Metadata {
+    pub fn push_fields(self, record: &mut Record, span: Span) {
+        record.push("version", Value::int(self.version, span));
+        record.push("rows", Value::int(self.rows, span));
+        record.push("row_groups", Value::int(self.row_groups as _, span));
+        record.push(
+            "created_by",
+            self.created_by
+                .map_or(Value::nothing(span), |c| Value::string(c, span)),
+        );
+        record.push("columns", columns_value(self.columns, span));
+    }
+}
+
+pub fn read<R: Read + Seek>(file: &mut R) -> Option<Metadata> {
+    let length = file.seek(SeekFrom::End(0)).ok()?;
+    let tail = read_at(file, length.checked_sub(8)?, 8)?;
+    let footer_length = u32_le(&tail, 0)? as u64;
+    if &tail[4..] != MAGIC || footer_length > MAX_FOOTER {
+        return None;
+    }
+    let footer = read_at(
+        file,
+        length.checked_sub(8 + footer_length)?,
+        footer_length as usize,
+    )?;
+    parse(&footer)
+}
+
+struct SchemaElement {
+    name: String,
+    physical: Option<i64>,
+    repetition: Option<i64>,
+    children: usize,
+    converted: Option<i64>,
+    logical: Option<i16>,
+}
+
+/// `FileMetaData`: version, schema, row count, row groups and writer.
+fn parse(footer: &[u8]) -> Option<Metadata> {
+    let mut reader = Compact {
+        data: footer,
+        pos: 0,
+    };
+    let mut metadata = Metadata {
+        version: 0,
+        rows: 0,
+        row_groups: 0,
+        created_by: None,
+        columns: Vec::new(),
+    };
+    let mut schema = Vec::new();
+    let mut last = 0;
+    while let Some((id, kind)) = reader.field(&mut last)? {
+        match (id, kind) {
+            (1, I32) => metadata.version = reader.zigzag()?,
+            (2, LIST) => {
+                let (count, _) = reader.list()?;
+                for _ in 0..count {
+                    schema.push(schema_element(&mut reader)?);
+                }
+            }
+            (3, I64) => metadata.rows = reader.zigzag()?,
+            (4, LIST) => {
+                let (count, element) = reader.list()?;
+                metadata.row_groups = count;
+                for _ in 0..count {
+                    reader.skip(element, 0)?;
+                }
+            }
+            (6, BINARY) => {
+                metadata.created_by = Some(String::from_utf8_lossy(reader.binary()?).into_owned())
+            }
+            _ => reader.skip(kind, 0)?,
+        }
+    }
+    // the first element is the root, the rest a depth first tree of which
+    // only the leaves are columns
+    let mut next = 1;
+    let children = schema.first()?.children;
+    for _ in 0..children {
+        leaves(&schema, &mut next, "", 0, &mut metadata.columns)?;
+    }
+    Some(metadata)
+}
+
+fn leaves(
+    schema: &[SchemaElement],
+    next: &mut usize,
+    prefix: &str,
+    depth: usize,
+    columns: &mut Vec<Column>,
+) -> Option<()> {
+    let element = schema.get(*next)?;
+    *next += 1;
+    let name = if prefix.is_empty() {
+        element.name.clone()
+    } else {
+        format!("{prefix}.{}", element.name)
+    };
+    if element.children > 0 {
+        if depth == MAX_DEPTH {
+            return None;
+        }
+        for _ in 0..element.children {
+            leaves(schema, next, &name, depth + 1, columns)?;
+        }
+        return Some(());
+    }
+    let physical = element
+        .physical
+        .and_then(|t| PHYSICAL_TYPES.get(t as usize))
+        .copied()
+        .unwrap_or("unknown");
+    let annotation = element
+        .converted
+        .and_then(|t| CONVERTED_TYPES.get(t as usize))
+        .or_else(|| LOGICAL_TYPES.get(element.logical? as usize))
+        .copied()
+        .filter(|t| !t.is_empty());
+    columns.push(Column {
+        name,
+        kind: annotation.unwrap_or(physical).to_string(),
+        // required, optional or repeated
+        nullable: element.repetition.map(|r| r == 1),
+    });
+    Some(())
+}
+
+fn schema_element(reader: &mut Compact) -> Option<SchemaElement> {
+    let mut element = SchemaElement {
+        name: String::new(),
+        physical: None,
+        repetition: None,
+        children: 0,
+        converted: None,
+        logical: None,
+    };
+    let mut last = 0;
+    while let Some((id, kind)) = reader.field(&mut last)? {
+        match (id, kind) {
+            (1, I32) => element.physical = Some(reader.zigzag()?),
+            (3, I32) => element.repetition = Some(reader.zigzag()?),
+            (4, BINARY) => element.name = String::from_utf8_lossy(reader.binary()?).into_owned(),
+            (5, I32) => element.children = usize::try_from(reader.zigzag()?).ok()?,
+            (6, I32) => element.converted = Some(reader.zigzag()?),
+            (10, STRUCT) => {
+                // a union, the set field says which logical type it is
+                let mut union_last = 0;
+                while let Some((id, kind)) = reader.field(&mut union_last)? {
+                    element.logical.get_or_insert(id);
+                    reader.skip(kind, 0)?;
+                }
+            }
+            _ => reader.skip(kind, 0)?,
+        }
+    }
+    Some(element)
+}
+
+const BOOL_TRUE: u8 = 1;
+const BOOL_FALSE: u8 = 2;
+const BYTE: u8 = 3;
+const I16: u8 = 4;
+const I32: u8 = 5;
+const I64: u8 = 6;
+const DOUBLE: u8 = 7;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const SET: u8 = 10;
+const MAP: u8 = 11;
+const STRUCT: u8 = 12;
+
+/// Just enough of the Thrift compact protocol to read and skip fields.
+struct Compact<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Compact<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn zigzag(&mut self) -> Option<i64> {
+        let value = self.varint()?;
+        Some((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn binary(&mut self) -> Option<&'a [u8]> {
+        let length = usize::try_from(self.varint()?).ok()?;
+        let bytes = self.data.get(self.pos..self.pos.checked_add(length)?)?;
+        self.pos += length;
+        Some(bytes)
+    }
+
+    /// The id and type of the next field of a struct, `None` at its end.
+    /// Ids are stored as a delta from `last`.
+    fn field(&mut self, last: &mut i16) -> Option<Option<(i16, u8)>> {
+        let header = self.byte()?;
+        if header == 0 {
+            return Some(None);
+        }
+        *last = match header >> 4 {
+            0 => i16::try_from(self.zigzag()?).ok()?,
+            delta => last.checked_add(delta as i16)?,
+        };
+        Some(Some((*last, header & 0x0F)))
+    }
+
+    /// Element count and type of a list or set.
+    fn list(&mut self) -> Option<(usize, u8)> {
+        let header = self.byte()?;
+        let count = match header >> 4 {
+            15 => usize::try_from(self.varint()?).ok()?,
+            count => count as usize,
+        };
+        // every element takes at least a byte
+        (count <= self.data.len() - self.pos).then_some((count, header & 0x0F))
+    }
+
+    /// Skip a list, set or map element, which keeps a boolean in a byte.
+    fn skip_element(&mut self, kind: u8, depth: usize) -> Option<()> {
+        match kind {
+            BOOL_TRUE | BOOL_FALSE => self.byte().map(drop),
+            _ => self.skip(kind, depth),
+        }
+    }
+
+    fn skip(&mut self, kind: u8, depth: usize) -> Option<()> {
+        if depth == MAX_DEPTH {
+            return None;
+        }
+        match kind {
+            // struct fields keep booleans in their type
+            BOOL_TRUE | BOOL_FALSE => {}
+            BYTE => {
+                self.byte()?;
+            }
+            I16 | I32 | I64 => {
+                self.varint()?;
+            }
+            DOUBLE => {
+                self.pos = self.pos.checked_add(8).filter(|&p| p <= self.data.len())?;
+            }
+            BINARY => {
+                self.binary()?;
+            }
+            LIST | SET => {
+                let (count, element) = self.list()?;
+                for _ in 0..count {
+                    self.skip_element(element, depth + 1)?;
+                }
+            }
+            MAP => {
+                let count = usize::try_from(self.varint()?).ok()?;
+                // every entry takes at least a byte for its key and its value
+                if count > (self.data.len() - self.pos) / 2 {
+                    return None;
+                }
+                if count > 0 {
+                    let types = self.byte()?;
+                    for _ in 0..count {
+                        self.skip_element(types >> 4, depth + 1)?;
+                        self.skip_element(types & 0x0F, depth + 1)?;
+                    }
+                }
+            }
+            STRUCT => {
+                let mut last = 0;
+                while let Some((_, kind)) = self.field(&mut last)? {
+                    self.skip(kind, depth + 1)?;
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A compact protocol string field, `delta` after the previous one.
+    fn string(out: &mut Vec<u8>, delta: u8, value: &str) {
+        out.push(delta << 4 | BINARY);
+        out.push(value.len() as u8);
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    fn int(out: &mut Vec<u8>, delta: u8, value: i64) {
+        out.push(delta << 4 | I32);
+        out.push(((value << 1) ^ (value >> 63)) as u8);
+    }
+
+    #[test]
+    fn footer() {
+        let mut footer = Vec::new();
+        int(&mut footer, 1, 2);
+        // schema: root with two children, an int64 and an optional string
+        footer.push(1 << 4 | LIST);
+        footer.push(3 << 4 | STRUCT);
+        string(&mut footer, 4, "schema");
+        int(&mut footer, 1, 2);
+        footer.push(0);
+        int(&mut footer, 1, 2);
+        int(&mut footer, 2, 0);
+        string(&mut footer, 1, "id");
+        footer.push(0);
+        int(&mut footer, 1, 6);
+        int(&mut footer, 2, 1);
+        string(&mut footer, 1, "name");
+        int(&mut footer, 2, 0);
+        footer.push(0);
+        // 1000 rows in one empty row group
+        footer.push(1 << 4 | I64);
+        footer.extend_from_slice(&[0xD0, 0x0F]);
+        footer.push(1 << 4 | LIST);
+        footer.push(1 << 4 | STRUCT);
+        footer.push(0);
+        string(&mut footer, 2, "parquet-cpp-arrow version 17.0.0");
+        footer.push(0);
+
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(&footer);
+        file.extend_from_slice(&(footer.len() as u32).to_le_bytes());
+        file.extend_from_slice(MAGIC);
+        let metadata = read(&mut Cursor::new(&file)).unwrap();
+        assert_eq!(
+            (metadata.version, metadata.rows, metadata.row_groups),
+            (2, 1000, 1)
+        );
+        assert_eq!(
+            metadata.created_by.as_deref(),
+            Some("parquet-cpp-arrow version 17.0.0")
+        );
+        assert_eq!(
+            metadata
+                .columns
+                .iter()
+                .map(|c| (c.name.as_str(), c.kind.as_str(), c.nullable))
+                .collect::<Vec<_>>(),
+            [("id", "int64", Some(false)), ("name", "string", Some(true))]
+        );
+    }
+
+    #[test]
+    fn boolean_maps() {
+        // two entries of a map<bool, bool>, a byte for every key and value
+        let data = [2, BOOL_TRUE << 4 | BOOL_TRUE, 1, 2, 2, 1];
+        let mut reader = Compact {
+            data: &data,
+            pos: 0,
+        };
+        assert_eq!(reader.skip(MAP, 0), Some(()));
+        assert_eq!(reader.pos, data.len());
+
+        // a count of 2^62 must not spin
+        let data = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x40, 0x11];
+        let mut reader = Compact {
+            data: &data,
+            pos: 0,
+        };
+        assert_eq!(reader.skip(MAP, 0), None);
+    }
+}
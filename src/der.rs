@@ -0,0 +1,367 @@
+//! A tiny DER reader, just enough to walk X.509 certificates and PKCS#7 blobs
+//! without pulling in a full ASN.1 stack.
+
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const OID: u8 = 0x06;
+pub const UTF8_STRING: u8 = 0x0C;
+pub const PRINTABLE_STRING: u8 = 0x13;
+pub const T61_STRING: u8 = 0x14;
+pub const IA5_STRING: u8 = 0x16;
+pub const UTC_TIME: u8 = 0x17;
+pub const GENERALIZED_TIME: u8 = 0x18;
+pub const BMP_STRING: u8 = 0x1E;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+}
+
+/// Read one TLV from the front of `bytes`, returning it and the remaining bytes.
+pub fn read(bytes: &[u8]) -> Option<(Tlv<'_>, &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7F) as usize;
+        // indefinite lengths (BER) and absurd sizes are not supported
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, &rest[count..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((
+        Tlv {
+            tag,
+            content: &rest[..len],
+        },
+        &rest[len..],
+    ))
+}
+
+impl<'a> Tlv<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        read(bytes).map(|(tlv, _)| tlv)
+    }
+    pub fn is_constructed(&self) -> bool {
+        self.tag & 0x20 != 0
+    }
+    /// Context specific tag number, if this is a `[n]` tagged value.
+    pub fn context(&self) -> Option<u8> {
+        (self.tag & 0xC0 == 0x80).then_some(self.tag & 0x1F)
+    }
+    pub fn children(&self) -> Children<'a> {
+        Children(self.content)
+    }
+    pub fn child(&self, index: usize) -> Option<Tlv<'a>> {
+        self.children().nth(index)
+    }
+    pub fn oid(&self) -> Option<String> {
+        (self.tag == OID).then(|| oid_to_string(self.content))
+    }
+    pub fn string(&self) -> Option<String> {
+        match self.tag {
+            UTF8_STRING | PRINTABLE_STRING | T61_STRING | IA5_STRING | 0x12 | 0x1A => {
+                Some(String::from_utf8_lossy(self.content).into_owned())
+            }
+            BMP_STRING => Some(String::from_utf16_lossy(
+                &self
+                    .content
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect::<Vec<_>>(),
+            )),
+            _ => None,
+        }
+    }
+    /// Big-endian unsigned value of an INTEGER, if it fits.
+    pub fn uint(&self) -> Option<u64> {
+        if self.tag != INTEGER || self.content.is_empty() {
+            return None;
+        }
+        let bytes = match self.content {
+            [0, rest @ ..] if !rest.is_empty() => rest,
+            bytes => bytes,
+        };
+        (bytes.len() <= 8).then(|| bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+    /// Render a UTCTime or GeneralizedTime as an RFC 3339 string.
+    pub fn time(&self) -> Option<String> {
+        let text = std::str::from_utf8(self.content).ok()?;
+        let text = text.strip_suffix('Z').unwrap_or(text);
+        let (year, rest) = match self.tag {
+            UTC_TIME => {
+                let yy: u32 = text.get(0..2)?.parse().ok()?;
+                (if yy >= 50 { 1900 + yy } else { 2000 + yy }, text.get(2..)?)
+            }
+            GENERALIZED_TIME => (text.get(0..4)?.parse().ok()?, text.get(4..)?),
+            _ => return None,
+        };
+        let field = |i: usize| rest.get(i..i + 2);
+        Some(format!(
+            "{year:04}-{}-{}T{}:{}:{}Z",
+            field(0)?,
+            field(2)?,
+            field(4)?,
+            field(6)?,
+            field(8).unwrap_or("00"),
+        ))
+    }
+}
+
+pub struct Children<'a>(&'a [u8]);
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Tlv<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (tlv, rest) = read(self.0)?;
+        self.0 = rest;
+        Some(tlv)
+    }
+}
+
+pub fn oid_to_string(content: &[u8]) -> String {
+    let mut parts = Vec::new();
+    let mut value = 0u64;
+    for (i, byte) in content.iter().enumerate() {
+        value = (value << 7) | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            if parts.is_empty() {
+                let first = (value / 40).min(2);
+                parts.push(first);
+                parts.push(value - first * 40);
+            } else {
+                parts.push(value);
+            }
+            value = 0;
+        } else if i == content.len() - 1 {
+            break;
+        }
+    }
+    parts
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn attribute_name(oid: &str) -> Option<&'static str> {
+    Some(match oid {
+        "2.5.4.3" => "CN",
+        "2.5.4.5" => "serialNumber",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.9" => "street",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "2.5.4.12" => "title",
+        "2.5.4.97" => "organizationIdentifier",
+        "0.9.2342.19200300.100.1.25" => "DC",
+        "1.2.840.113549.1.9.1" => "emailAddress",
+        _ => return None,
+    })
+}
+
+/// Format an X.501 `Name` (RDNSequence) as `CN=..., O=..., C=...`.
+pub fn name_to_string(name: &Tlv) -> String {
+    name.children()
+        .flat_map(|rdn| rdn.children())
+        .filter_map(|atv| {
+            let oid = atv.child(0)?.oid()?;
+            let value = atv.child(1)?;
+            let value = value
+                .string()
+                .unwrap_or_else(|| String::from_utf8_lossy(value.content).into_owned());
+            Some(format!(
+                "{}={}",
+                attribute_name(&oid).unwrap_or(&oid),
+                value
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The handful of X.509 fields we care about.
+pub struct Certificate<'a> {
+    pub serial: &'a [u8],
+    pub issuer: String,
+    pub subject: String,
+    pub not_before: Option<String>,
+    pub not_after: Option<String>,
+    pub public_key: Option<Tlv<'a>>,
+    pub subject_alt_names: Vec<String>,
+}
+
+impl<'a> Certificate<'a> {
+    pub fn parse(cert: &Tlv<'a>) -> Option<Self> {
+        let tbs = cert.child(0)?;
+        let mut fields = tbs.children().peekable();
+        // skip the optional [0] version
+        if fields.peek()?.context() == Some(0) {
+            fields.next();
+        }
+        let serial = fields.next()?.content;
+        let _signature = fields.next()?;
+        let issuer = name_to_string(&fields.next()?);
+        let validity = fields.next()?;
+        let subject = name_to_string(&fields.next()?);
+        let public_key = fields.next();
+        // issuerUniqueID [1] and subjectUniqueID [2] may sit before [3]
+        let subject_alt_names = fields
+            .find(|f| f.context() == Some(3))
+            .and_then(|extensions| extensions.child(0))
+            .and_then(|extensions| {
+                extensions
+                    .children()
+                    .find(|e| e.child(0).and_then(|oid| oid.oid()).as_deref() == Some("2.5.29.17"))
+            })
+            .map(|extension| general_names(&extension))
+            .unwrap_or_default();
+        Some(Certificate {
+            serial,
+            issuer,
+            subject,
+            not_before: validity.child(0).and_then(|t| t.time()),
+            not_after: validity.child(1).and_then(|t| t.time()),
+            public_key,
+            subject_alt_names,
+        })
+    }
+}
+
+/// The `GeneralNames` inside a subjectAltName extension, as `DNS:...`,
+/// `IP:...` and so on. The value is the last child, after the optional
+/// critical flag.
+fn general_names(extension: &Tlv) -> Vec<String> {
+    let Some(value) = extension
+        .children()
+        .last()
+        .filter(|v| v.tag == OCTET_STRING)
+    else {
+        return Vec::new();
+    };
+    let Some(names) = Tlv::parse(value.content) else {
+        return Vec::new();
+    };
+    names
+        .children()
+        .filter_map(|name| {
+            let text = || String::from_utf8_lossy(name.content).into_owned();
+            Some(match name.context()? {
+                1 => format!("email:{}", text()),
+                2 => format!("DNS:{}", text()),
+                4 => format!("DirName:{}", name_to_string(&name.child(0)?)),
+                6 => format!("URI:{}", text()),
+                7 => match name.content.len() {
+                    4 => format!(
+                        "IP:{}",
+                        std::net::Ipv4Addr::from(<[u8; 4]>::try_from(name.content).ok()?)
+                    ),
+                    16 => format!(
+                        "IP:{}",
+                        std::net::Ipv6Addr::from(<[u8; 16]>::try_from(name.content).ok()?)
+                    ),
+                    _ => return None,
+                },
+                _ => return None,
+            })
+        })
+        .collect()
+}
+
+/// How deep `find` descends, well past anything a real signature nests.
+const MAX_DEPTH: usize = 32;
+
+/// Depth first search for the first TLV matching `predicate`, descending into
+/// constructed values and OCTET STRINGs that wrap DER.
+pub fn find<'a>(tlv: Tlv<'a>, predicate: &dyn Fn(&Tlv<'a>) -> bool) -> Option<Tlv<'a>> {
+    find_within(tlv, predicate, 0)
+}
+
+fn find_within<'a>(
+    tlv: Tlv<'a>,
+    predicate: &dyn Fn(&Tlv<'a>) -> bool,
+    depth: usize,
+) -> Option<Tlv<'a>> {
+    if predicate(&tlv) {
+        return Some(tlv);
+    }
+    if depth == MAX_DEPTH {
+        return None;
+    }
+    if tlv.is_constructed() {
+        return tlv
+            .children()
+            .find_map(|child| find_within(child, predicate, depth + 1));
+    }
+    if tlv.tag == OCTET_STRING {
+        let (inner, rest) = read(tlv.content)?;
+        if rest.is_empty() && inner.is_constructed() {
+            return find_within(inner, predicate, depth + 1);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn oid_and_name() {
+        assert_eq!(
+            oid_to_string(&[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02]),
+            "1.2.840.113549.1.7.2"
+        );
+        // SEQUENCE { SET { SEQUENCE { OID 2.5.4.3, UTF8String "nu" } } }
+        let name = [
+            0x30, 0x0D, 0x31, 0x0B, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0C, 0x02, b'n',
+            b'u',
+        ];
+        assert_eq!(name_to_string(&Tlv::parse(&name).unwrap()), "CN=nu");
+    }
+
+    #[test]
+    fn times() {
+        let utc = [
+            0x17, 0x0D, b'2', b'4', b'0', b'1', b'0', b'2', b'0', b'3', b'0', b'4', b'0', b'5',
+            b'Z',
+        ];
+        assert_eq!(
+            Tlv::parse(&utc).unwrap().time().as_deref(),
+            Some("2024-01-02T03:04:05Z")
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let nested = |depth: usize| {
+            let mut bytes = vec![INTEGER, 1, 1];
+            for _ in 0..depth {
+                let len = bytes.len() as u16;
+                bytes.splice(0..0, [SEQUENCE, 0x82, (len >> 8) as u8, len as u8]);
+            }
+            bytes
+        };
+        let is_integer = |t: &Tlv| t.tag == INTEGER;
+        let shallow = nested(3);
+        assert!(find(Tlv::parse(&shallow).unwrap(), &is_integer).is_some());
+        // far deeper than any real signature nests
+        let deep = nested(10_000);
+        assert!(find(Tlv::parse(&deep).unwrap(), &is_integer).is_none());
+    }
+}
@@ -0,0 +1,904 @@
+//! Disk and filesystem images: MBR and GPT partition tables, and the
+//! filesystem at the start of the image or of each partition.
+use crate::bytes::{padded_str, u16_be, u16_le, u32_le, u64_le};
+use flate2::read::GzDecoder;
+use nu_protocol::{Record, Span, Value, record};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const SECTOR: u64 = 512;
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+/// Partitions listed from a GPT, most tools create 128 entry slots.
+const MAX_GPT_ENTRIES: u32 = 256;
+const CD_SECTOR: u64 = 2048;
+/// ISO 9660 and UDF volume descriptors start after a 32 KiB system area.
+const VOLUME_DESCRIPTORS: u64 = 16 * CD_SECTOR;
+/// Volume descriptors read before giving up on a terminator.
+const MAX_VOLUME_DESCRIPTORS: u64 = 32;
+const UDF_ANCHOR_SECTOR: u64 = 256;
+/// cpio entries counted, so a huge initramfs stays cheap.
+const MAX_CPIO_ENTRIES: usize = 100_000;
+/// Longest cpio entry name, Linux's `PATH_MAX`.
+const PATH_MAX: u64 = 4096;
+
+pub struct DiskImage {
+    pub table: Option<PartitionTable>,
+    pub filesystem: Option<Filesystem>,
+}
+
+pub enum PartitionTable {
+    Mbr(Vec<Partition>),
+    Gpt {
+        disk_guid: String,
+        partitions: Vec<Partition>,
+    },
+}
+
+pub struct Partition {
+    /// 1-based, like the kernel names them.
+    pub index: usize,
+    pub kind: String,
+    pub name: Option<String>,
+    pub guid: Option<String>,
+    pub bootable: Option<bool>,
+    /// In bytes.
+    pub start: u64,
+    pub size: u64,
+    pub filesystem: Option<Filesystem>,
+}
+
+pub enum Filesystem {
+    Ext {
+        version: u8,
+        label: String,
+        uuid: String,
+        block_size: u64,
+        size: u64,
+    },
+    Squashfs {
+        version: String,
+        compression: &'static str,
+        block_size: u32,
+        inodes: u32,
+        size: u64,
+    },
+    Iso9660 {
+        label: String,
+        system: String,
+        size: u64,
+    },
+    Udf {
+        label: Option<String>,
+    },
+    Fat {
+        bits: u8,
+        label: String,
+        serial: String,
+        cluster_size: u64,
+        size: u64,
+    },
+    Exfat {
+        serial: String,
+        cluster_size: u64,
+        size: u64,
+    },
+    Ntfs {
+        serial: String,
+        cluster_size: u64,
+        size: u64,
+    },
+    Cpio {
+        format: &'static str,
+        compression: Option<&'static str>,
+        entries: usize,
+        initramfs: bool,
+    },
+}
+
+impl DiskImage {
+    /// The name of what's at the start of the image.
+    pub fn format(&self) -> &'static str {
+        match (&self.table, &self.filesystem) {
+            (Some(PartitionTable::Gpt { .. }), _) => "gpt",
+            (Some(PartitionTable::Mbr(_)), _) => "mbr",
+            (None, Some(fs)) => fs.name(),
+            (None, None) => "img",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match &self.filesystem {
+            Some(Filesystem::Iso9660 { .. } | Filesystem::Udf { .. }) => {
+                "application/x-iso9660-image"
+            }
+            Some(Filesystem::Squashfs { .. }) => "application/vnd.squashfs",
+            Some(Filesystem::Cpio { .. }) => "application/x-cpio",
+            _ => "application/x-raw-disk-image",
+        }
+    }
+
+    pub fn into_record(self, span: Span) -> Record {
+        let (table, disk_guid, partitions) = match self.table {
+            Some(PartitionTable::Mbr(partitions)) => (Some("mbr"), None, partitions),
+            Some(PartitionTable::Gpt {
+                disk_guid,
+                partitions,
+            }) => (Some("gpt"), Some(disk_guid), partitions),
+            None => (None, None, Vec::new()),
+        };
+        record!(
+                "partition_table" => table.map_or(Value::nothing(span), |t| Value::string(t, span)),
+                "disk_guid" => disk_guid.map_or(Value::nothing(span), |g| Value::string(g, span)),
+                "partitions" => Value::list(
+                    partitions.into_iter().map(|p| p.into_value(span)).collect(),
+                    span,
+                ),
+                "filesystem" => self.filesystem.map_or(Value::nothing(span), |f| f.into_value(span)),
+        )
+    }
+}
+
+impl Partition {
+    fn into_value(self, span: Span) -> Value {
+        let optional =
+            |s: Option<String>| s.map_or(Value::nothing(span), |s| Value::string(s, span));
+        Value::record(
+            record!(
+                "index" => Value::int(self.index as _, span),
+                "type" => Value::string(self.kind, span),
+                "name" => optional(self.name),
+                "guid" => optional(self.guid),
+                "bootable" => self.bootable.map_or(Value::nothing(span), |b| Value::bool(b, span)),
+                "start" => Value::int(self.start as _, span),
+                "size" => Value::int(self.size as _, span),
+                "filesystem" => self.filesystem.map_or(Value::nothing(span), |f| f.into_value(span)),
+            ),
+            span,
+        )
+    }
+}
+
+impl Filesystem {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filesystem::Ext { version: 2, .. } => "ext2",
+            Filesystem::Ext { version: 3, .. } => "ext3",
+            Filesystem::Ext { .. } => "ext4",
+            Filesystem::Squashfs { .. } => "squashfs",
+            Filesystem::Iso9660 { .. } => "iso9660",
+            Filesystem::Udf { .. } => "udf",
+            Filesystem::Fat { bits: 12, .. } => "fat12",
+            Filesystem::Fat { bits: 16, .. } => "fat16",
+            Filesystem::Fat { .. } => "fat32",
+            Filesystem::Exfat { .. } => "exfat",
+            Filesystem::Ntfs { .. } => "ntfs",
+            Filesystem::Cpio { .. } => "cpio",
+        }
+    }
+
+    fn into_value(self, span: Span) -> Value {
+        let string = |s: String| Value::string(s, span);
+        let size = |s: u64| Value::int(s as _, span);
+        let mut value = record!("type" => Value::string(self.name(), span));
+        match self {
+            Filesystem::Ext {
+                label,
+                uuid,
+                block_size,
+                size: total,
+                ..
+            } => {
+                value.push("label", string(label));
+                value.push("uuid", string(uuid));
+                value.push("block_size", size(block_size));
+                value.push("size", size(total));
+            }
+            Filesystem::Squashfs {
+                version,
+                compression,
+                block_size,
+                inodes,
+                size: total,
+            } => {
+                value.push("version", string(version));
+                value.push("compression", Value::string(compression, span));
+                value.push("block_size", size(block_size as _));
+                value.push("inodes", Value::int(inodes as _, span));
+                value.push("size", size(total));
+            }
+            Filesystem::Iso9660 {
+                label,
+                system,
+                size: total,
+            } => {
+                value.push("label", string(label));
+                value.push("system", string(system));
+                value.push("size", size(total));
+            }
+            Filesystem::Udf { label } => {
+                value.push("label", label.map_or(Value::nothing(span), string));
+            }
+            Filesystem::Fat {
+                label,
+                serial,
+                cluster_size,
+                size: total,
+                ..
+            } => {
+                value.push("label", string(label));
+                value.push("serial", string(serial));
+                value.push("cluster_size", size(cluster_size));
+                value.push("size", size(total));
+            }
+            Filesystem::Exfat {
+                serial,
+                cluster_size,
+                size: total,
+            }
+            | Filesystem::Ntfs {
+                serial,
+                cluster_size,
+                size: total,
+            } => {
+                value.push("serial", string(serial));
+                value.push("cluster_size", size(cluster_size));
+                value.push("size", size(total));
+            }
+            Filesystem::Cpio {
+                format,
+                compression,
+                entries,
+                initramfs,
+            } => {
+                value.push("format", Value::string(format, span));
+                value.push(
+                    "compression",
+                    compression.map_or(Value::nothing(span), |c| Value::string(c, span)),
+                );
+                value.push("entries", Value::int(entries as _, span));
+                value.push("initramfs", Value::bool(initramfs, span));
+            }
+        }
+        Value::record(value, span)
+    }
+}
+
+pub fn detect_file(path: &Path) -> Option<DiskImage> {
+    detect(&mut File::open(path).ok()?)
+}
+
+/// The partition table, or the filesystem when the image is a single
+/// volume. `None` when neither is recognized.
+pub fn detect<R: Read + Seek>(image: &mut R) -> Option<DiskImage> {
+    let table = gpt(image).or_else(|| mbr(image));
+    let filesystem = if table.is_some() {
+        // hybrid ISOs carry an MBR for USB sticks
+        iso9660(image, 0)
+    } else {
+        filesystem(image, 0).or_else(|| cpio(image))
+    };
+    (table.is_some() || filesystem.is_some()).then_some(DiskImage { table, filesystem })
+}
+
+pub(crate) fn read_at<R: Read + Seek>(
+    image: &mut R,
+    offset: u64,
+    length: usize,
+) -> Option<Vec<u8>> {
+    image.seek(SeekFrom::Start(offset)).ok()?;
+    let mut buf = vec![0; length];
+    image.read_exact(&mut buf).ok()?;
+    Some(buf)
+}
+
+/// GUIDs store their first three fields little endian.
+pub(crate) fn guid(bytes: &[u8]) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{}-{}",
+        u32_le(bytes, 0).unwrap_or_default(),
+        u16_le(bytes, 4).unwrap_or_default(),
+        u16_le(bytes, 6).unwrap_or_default(),
+        crate::bytes::hex(&bytes[8..10]).to_uppercase(),
+        crate::bytes::hex(&bytes[10..16]).to_uppercase()
+    )
+}
+
+fn gpt_type(guid: &str) -> Option<&'static str> {
+    Some(match guid {
+        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B" => "EFI system",
+        "21686148-6449-6E6F-744E-656564454649" => "BIOS boot",
+        "E3C9E316-0B5C-4DB8-817D-F92DF00215AE" => "Microsoft reserved",
+        "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7" => "Microsoft basic data",
+        "DE94BBA4-06D1-4D40-A16A-BFD50179D6AC" => "Windows recovery",
+        "0FC63DAF-8483-4772-8E79-3D69D8477DE4" => "Linux filesystem",
+        "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709" => "Linux root (x86-64)",
+        "B921B045-1DF0-41C3-AF44-4C6F280D3FAE" => "Linux root (ARM64)",
+        "933AC7E1-2EB4-4F13-B844-0E14E2AEF915" => "Linux home",
+        "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F" => "Linux swap",
+        "E6D6D379-F507-44C2-A23C-238F2A3DF928" => "Linux LVM",
+        "A19D880F-05FC-4D3B-A006-743F0F84911E" => "Linux RAID",
+        "BC13C2FF-59E6-4262-A352-B275FD6F7172" => "Linux extended boot",
+        "48465300-0000-11AA-AA11-00306543ECAC" => "Apple HFS+",
+        "7C3457EF-0000-11AA-AA11-00306543ECAC" => "Apple APFS",
+        "516E7CB4-6ECF-11D6-8FF8-00022D09712B" => "FreeBSD",
+        _ => return None,
+    })
+}
+
+fn mbr_type(kind: u8) -> Option<&'static str> {
+    Some(match kind {
+        0x01 => "FAT12",
+        0x04 | 0x06 | 0x0E => "FAT16",
+        0x05 | 0x0F | 0x85 => "Extended",
+        0x07 => "NTFS/exFAT",
+        0x0B | 0x0C => "FAT32",
+        0x27 => "Windows recovery",
+        0x82 => "Linux swap",
+        0x83 => "Linux",
+        0x8E => "Linux LVM",
+        0xA5 => "FreeBSD",
+        0xA6 => "OpenBSD",
+        0xAF => "Apple HFS+",
+        0xEE => "GPT protective",
+        0xEF => "EFI system",
+        0xFD => "Linux RAID",
+        _ => return None,
+    })
+}
+
+fn gpt<R: Read + Seek>(image: &mut R) -> Option<PartitionTable> {
+    // the header is in the second logical block, 512 or 4096 bytes in
+    for sector in [SECTOR, 4096] {
+        let Some(header) = read_at(image, sector, 92) else {
+            continue;
+        };
+        if &header[..8] != GPT_SIGNATURE {
+            continue;
+        }
+        let entries = u64_le(&header, 72)?;
+        let count = u32_le(&header, 80)?.min(MAX_GPT_ENTRIES);
+        let entry_size = u32_le(&header, 84)? as usize;
+        if !entry_size.is_power_of_two() || !(128..=4096).contains(&entry_size) {
+            return None;
+        }
+        let length = image.seek(SeekFrom::End(0)).ok()?;
+        let table_offset = entries.checked_mul(sector)?;
+        let table_size = count as usize * entry_size;
+        if table_offset.checked_add(table_size as u64)? > length {
+            return None;
+        }
+        let table = read_at(image, table_offset, table_size)?;
+        let mut partitions = Vec::new();
+        for (i, entry) in table.chunks_exact(entry_size).enumerate() {
+            if entry[..16].iter().all(|&b| b == 0) {
+                continue;
+            }
+            let type_guid = guid(&entry[..16]);
+            let first = u64_le(entry, 32)?;
+            let last = u64_le(entry, 40)?;
+            let name = entry[56..128]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|&c| c != 0)
+                .collect::<Vec<_>>();
+            let Some(start) = first.checked_mul(sector).filter(|&s| s < length) else {
+                continue;
+            };
+            partitions.push(Partition {
+                index: i + 1,
+                kind: gpt_type(&type_guid).map_or(type_guid, String::from),
+                name: Some(String::from_utf16_lossy(&name)),
+                guid: Some(guid(&entry[16..32])),
+                bootable: None,
+                start,
+                size: last
+                    .saturating_sub(first)
+                    .saturating_add(1)
+                    .saturating_mul(sector),
+                filesystem: filesystem(image, start),
+            });
+        }
+        return Some(PartitionTable::Gpt {
+            disk_guid: guid(&header[56..72]),
+            partitions,
+        });
+    }
+    None
+}
+
+fn mbr<R: Read + Seek>(image: &mut R) -> Option<PartitionTable> {
+    let sector = read_at(image, 0, SECTOR as usize)?;
+    if sector[510..] != MBR_SIGNATURE || boot_sector(&sector).is_some() {
+        return None;
+    }
+    let mut partitions = Vec::new();
+    for (i, entry) in sector[446..510].chunks_exact(16).enumerate() {
+        let (status, kind) = (entry[0], entry[4]);
+        if status & 0x7F != 0 {
+            // not a partition table, maybe boot code that happens to end in 55 AA
+            return None;
+        }
+        if kind == 0 {
+            continue;
+        }
+        let start = u32_le(entry, 8)? as u64 * SECTOR;
+        let size = u32_le(entry, 12)? as u64 * SECTOR;
+        if start == 0 || size == 0 {
+            return None;
+        }
+        partitions.push(Partition {
+            index: i + 1,
+            kind: mbr_type(kind).map_or_else(|| format!("0x{kind:02X}"), String::from),
+            name: None,
+            guid: None,
+            bootable: Some(status == 0x80),
+            start,
+            size,
+            filesystem: filesystem(image, start),
+        });
+    }
+    (!partitions.is_empty()).then_some(PartitionTable::Mbr(partitions))
+}
+
+/// The filesystem of a volume starting at `offset`.
+fn filesystem<R: Read + Seek>(image: &mut R, offset: u64) -> Option<Filesystem> {
+    if let Some(sector) = read_at(image, offset, SECTOR as usize)
+        && let Some(fs) = boot_sector(&sector).or_else(|| squashfs(&sector))
+    {
+        return Some(fs);
+    }
+    ext(image, offset).or_else(|| iso9660(image, offset))
+}
+
+/// FAT, exFAT and NTFS volumes start with a boot sector describing them.
+fn boot_sector(sector: &[u8]) -> Option<Filesystem> {
+    if sector.get(510..512)? != MBR_SIGNATURE {
+        return None;
+    }
+    let oem = sector.get(3..11)?;
+    if oem == b"EXFAT   " {
+        let sector_size = 1u64 << sector[108].min(12);
+        return Some(Filesystem::Exfat {
+            serial: serial(u32_le(sector, 100)?),
+            cluster_size: sector_size << sector[109].min(25),
+            size: u64_le(sector, 72)?.saturating_mul(sector_size),
+        });
+    }
+    let sector_size = u16_le(sector, 11)? as u64;
+    let cluster_sectors = sector[13] as u64;
+    if !sector_size.is_power_of_two()
+        || !(512..=4096).contains(&sector_size)
+        || !cluster_sectors.is_power_of_two()
+    {
+        return None;
+    }
+    if oem == b"NTFS    " {
+        let serial = u64_le(sector, 72)?;
+        return Some(Filesystem::Ntfs {
+            serial: format!("{serial:016X}"),
+            cluster_size: sector_size * cluster_sectors,
+            size: u64_le(sector, 40)?.saturating_mul(sector_size),
+        });
+    }
+    if !matches!(sector[0], 0xEB | 0xE9) {
+        return None;
+    }
+    let reserved = u16_le(sector, 14)? as u64;
+    let fats = sector[16] as u64;
+    let root_entries = u16_le(sector, 17)? as u64;
+    let total = match u16_le(sector, 19)? {
+        0 => u32_le(sector, 32)? as u64,
+        total => total as u64,
+    };
+    let fat_size = match u16_le(sector, 22)? {
+        0 => u32_le(sector, 36)? as u64,
+        size => size as u64,
+    };
+    if reserved == 0 || fats == 0 || total == 0 {
+        return None;
+    }
+    // the FAT type follows from the cluster count, not from any label
+    let root_sectors = (root_entries * 32).div_ceil(sector_size);
+    let data = total.checked_sub(reserved + fats * fat_size + root_sectors)?;
+    let clusters = data / cluster_sectors;
+    let bits = match clusters {
+        0..4085 => 12,
+        4085..65525 => 16,
+        _ => 32,
+    };
+    // the extended boot record moves to make room for FAT32 fields
+    let extended = if bits == 32 { 64 } else { 36 };
+    let (label, serial_number) = if sector[extended + 2] == 0x29 {
+        (
+            padded_str(&sector[extended + 7..extended + 18]),
+            serial(u32_le(sector, extended + 3)?),
+        )
+    } else {
+        (String::new(), String::new())
+    };
+    Some(Filesystem::Fat {
+        bits,
+        label,
+        serial: serial_number,
+        cluster_size: sector_size * cluster_sectors,
+        size: total * sector_size,
+    })
+}
+
+/// Volume serial numbers as `dir` and `blkid` print them.
+fn serial(value: u32) -> String {
+    format!("{:04X}-{:04X}", value >> 16, value & 0xFFFF)
+}
+
+fn squashfs(sector: &[u8]) -> Option<Filesystem> {
+    if sector.get(..4)? != b"hsqs" {
+        return None;
+    }
+    let compression = match u16_le(sector, 20)? {
+        1 => "gzip",
+        2 => "lzma",
+        3 => "lzo",
+        4 => "xz",
+        5 => "lz4",
+        6 => "zstd",
+        _ => "unknown",
+    };
+    Some(Filesystem::Squashfs {
+        version: format!("{}.{}", u16_le(sector, 28)?, u16_le(sector, 30)?),
+        compression,
+        block_size: u32_le(sector, 12)?,
+        inodes: u32_le(sector, 4)?,
+        size: u64_le(sector, 40)?,
+    })
+}
+
+fn ext<R: Read + Seek>(image: &mut R, offset: u64) -> Option<Filesystem> {
+    const HAS_JOURNAL: u32 = 0x4;
+    const INCOMPAT_EXT4: u32 = 0x40 | 0x80 | 0x200;
+    const INCOMPAT_64BIT: u32 = 0x80;
+
+    let superblock = read_at(image, offset.checked_add(1024)?, 1024)?;
+    if u16_le(&superblock, 56)? != 0xEF53 {
+        return None;
+    }
+    let compat = u32_le(&superblock, 92)?;
+    let incompat = u32_le(&superblock, 96)?;
+    let version = if incompat & INCOMPAT_EXT4 != 0 {
+        4
+    } else if compat & HAS_JOURNAL != 0 {
+        3
+    } else {
+        2
+    };
+    let block_size = 1024u64 << u32_le(&superblock, 24)?.min(16);
+    let mut blocks = u32_le(&superblock, 4)? as u64;
+    if incompat & INCOMPAT_64BIT != 0 {
+        blocks |= (u32_le(&superblock, 0x150)? as u64) << 32;
+    }
+    let uuid = &superblock[104..120];
+    Some(Filesystem::Ext {
+        version,
+        label: padded_str(&superblock[120..136]),
+        uuid: format!(
+            "{}-{}-{}-{}-{}",
+            crate::bytes::hex(&uuid[..4]),
+            crate::bytes::hex(&uuid[4..6]),
+            crate::bytes::hex(&uuid[6..8]),
+            crate::bytes::hex(&uuid[8..10]),
+            crate::bytes::hex(&uuid[10..])
+        ),
+        block_size,
+        size: blocks.saturating_mul(block_size),
+    })
+}
+
+/// ISO 9660, or UDF when the volume recognition sequence says so.
+fn iso9660<R: Read + Seek>(image: &mut R, offset: u64) -> Option<Filesystem> {
+    let mut primary = None;
+    let mut udf = false;
+    for i in 0..MAX_VOLUME_DESCRIPTORS {
+        let Some(descriptor) = offset
+            .checked_add(VOLUME_DESCRIPTORS + i * CD_SECTOR)
+            .and_then(|at| read_at(image, at, 2048))
+        else {
+            break;
+        };
+        match &descriptor[1..6] {
+            b"CD001" if descriptor[0] == 1 => primary = Some(descriptor),
+            b"CD001" if descriptor[0] == 255 => {}
+            b"NSR02" | b"NSR03" => udf = true,
+            b"CD001" | b"BEA01" | b"TEA01" | b"BOOT2" | b"CDW02" => {}
+            _ => break,
+        }
+    }
+    if udf {
+        return Some(Filesystem::Udf {
+            label: udf_label(image, offset),
+        });
+    }
+    let primary = primary?;
+    Some(Filesystem::Iso9660 {
+        label: padded_str(&primary[40..72]),
+        system: padded_str(&primary[8..40]),
+        size: u32_le(&primary, 80)? as u64 * u16_le(&primary, 128)? as u64,
+    })
+}
+
+/// The volume identifier of the UDF primary volume descriptor, found from
+/// the anchor at sector 256.
+fn udf_label<R: Read + Seek>(image: &mut R, offset: u64) -> Option<String> {
+    let anchor = read_at(
+        image,
+        offset.checked_add(UDF_ANCHOR_SECTOR * CD_SECTOR)?,
+        32,
+    )?;
+    if u16_le(&anchor, 0)? != 2 {
+        return None;
+    }
+    let length = u32_le(&anchor, 16)? as u64;
+    let location = u32_le(&anchor, 20)? as u64;
+    for i in 0..(length / CD_SECTOR).min(MAX_VOLUME_DESCRIPTORS) {
+        let descriptor = read_at(
+            image,
+            offset.checked_add((location + i).checked_mul(CD_SECTOR)?)?,
+            512,
+        )?;
+        match u16_le(&descriptor, 0)? {
+            1 => return dstring(&descriptor[24..56]),
+            8 => break,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// OSTA compressed Unicode with the length in the last byte.
+fn dstring(field: &[u8]) -> Option<String> {
+    let length = (*field.last()? as usize).min(field.len() - 1);
+    let chars = field.get(1..length)?;
+    match field[0] {
+        8 => Some(chars.iter().map(|&c| c as char).collect()),
+        16 => Some(String::from_utf16_lossy(
+            &chars
+                .chunks_exact(2)
+                .filter_map(|c| u16_be(c, 0))
+                .collect::<Vec<_>>(),
+        )),
+        _ => None,
+    }
+}
+
+/// A cpio archive, possibly gzip compressed like most initramfs images.
+fn cpio<R: Read + Seek>(image: &mut R) -> Option<Filesystem> {
+    let start = read_at(image, 0, 6)?;
+    image.seek(SeekFrom::Start(0)).ok()?;
+    let (compression, mut reader): (_, Box<dyn Read + '_>) = if start.starts_with(&[0x1F, 0x8B]) {
+        (Some("gzip"), Box::new(GzDecoder::new(image)))
+    } else {
+        (None, Box::new(image))
+    };
+    let mut magic = [0u8; 6];
+    reader.read_exact(&mut magic).ok()?;
+    let format = match &magic {
+        b"070701" => "newc",
+        b"070702" => "crc",
+        b"070707" => "odc",
+        [0xC7, 0x71, ..] => "binary",
+        _ => return None,
+    };
+    let mut entries = 0;
+    let mut initramfs = false;
+    // entry names tell an initramfs from other archives, only the portable
+    // ASCII formats are walked
+    if format != "binary" {
+        while let Some(name) = cpio_entry(&mut reader, format, entries > 0, &magic) {
+            if name == "TRAILER!!!" {
+                break;
+            }
+            initramfs |= name == "init" || name == "./init";
+            entries += 1;
+            if entries == MAX_CPIO_ENTRIES {
+                break;
+            }
+        }
+    }
+    Some(Filesystem::Cpio {
+        format,
+        compression,
+        entries,
+        initramfs,
+    })
+}
+
+/// Read one entry header and skip its data, returning the entry name.
+/// The first header's magic has already been read.
+fn cpio_entry(
+    reader: &mut dyn Read,
+    format: &str,
+    read_magic: bool,
+    magic: &[u8; 6],
+) -> Option<String> {
+    if read_magic {
+        let mut next = [0u8; 6];
+        reader.read_exact(&mut next).ok()?;
+        if &next != magic {
+            return None;
+        }
+    }
+    let hex =
+        |field: &[u8], radix| u64::from_str_radix(std::str::from_utf8(field).ok()?, radix).ok();
+    let (name_size, file_size, align) = if format == "odc" {
+        let mut header = [0u8; 70];
+        reader.read_exact(&mut header).ok()?;
+        (hex(&header[53..59], 8)?, hex(&header[59..70], 8)?, 1)
+    } else {
+        let mut header = [0u8; 104];
+        reader.read_exact(&mut header).ok()?;
+        (hex(&header[88..96], 16)?, hex(&header[48..56], 16)?, 4)
+    };
+    if name_size > PATH_MAX {
+        return None;
+    }
+    let header_size = if format == "odc" { 76 } else { 110 };
+    let mut name = vec![0; name_size as usize];
+    reader.read_exact(&mut name).ok()?;
+    let padding = |size: u64| (align - size % align) % align;
+    let skip = padding(header_size + name_size)
+        .checked_add(file_size)?
+        .checked_add(padding(file_size))?;
+    std::io::copy(&mut reader.take(skip), &mut std::io::sink()).ok()?;
+    let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    Some(String::from_utf8_lossy(&name[..end]).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn ext4(label: &str) -> Vec<u8> {
+        let mut image = vec![0u8; 4096];
+        let superblock = &mut image[1024..2048];
+        superblock[4..8].copy_from_slice(&1000u32.to_le_bytes());
+        superblock[24..28].copy_from_slice(&2u32.to_le_bytes());
+        superblock[56..58].copy_from_slice(&0xEF53u16.to_le_bytes());
+        superblock[96..100].copy_from_slice(&0x2C2u32.to_le_bytes());
+        superblock[104..120].copy_from_slice(&[0xAB; 16]);
+        superblock[120..120 + label.len()].copy_from_slice(label.as_bytes());
+        image
+    }
+
+    #[test]
+    fn partition_tables() {
+        // an MBR with one bootable Linux partition at sector 8
+        let mut image = vec![0u8; 8 * 512];
+        image[446] = 0x80;
+        image[446 + 4] = 0x83;
+        image[446 + 8..446 + 12].copy_from_slice(&8u32.to_le_bytes());
+        image[446 + 12..446 + 16].copy_from_slice(&8u32.to_le_bytes());
+        image[510..512].copy_from_slice(&MBR_SIGNATURE);
+        image.extend(ext4("rootfs"));
+        let disk = detect(&mut Cursor::new(&image)).unwrap();
+        assert_eq!(disk.format(), "mbr");
+        let Some(PartitionTable::Mbr(partitions)) = disk.table else {
+            panic!("no MBR");
+        };
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(
+            (
+                partitions[0].kind.as_str(),
+                partitions[0].start,
+                partitions[0].size
+            ),
+            ("Linux", 4096, 4096)
+        );
+        let Some(Filesystem::Ext {
+            version,
+            label,
+            uuid,
+            block_size,
+            size,
+        }) = &partitions[0].filesystem
+        else {
+            panic!("no ext filesystem");
+        };
+        assert_eq!(
+            (*version, label.as_str(), *block_size, *size),
+            (4, "rootfs", 4096, 4_096_000)
+        );
+        assert_eq!(uuid, "abababab-abab-abab-abab-abababababab");
+
+        // a GPT with an EFI system partition
+        let mut image = vec![0u8; 4096 * 512];
+        image[510..512].copy_from_slice(&MBR_SIGNATURE);
+        image[446 + 4] = 0xEE;
+        image[512..520].copy_from_slice(GPT_SIGNATURE);
+        image[512 + 72..512 + 80].copy_from_slice(&2u64.to_le_bytes());
+        image[512 + 80..512 + 84].copy_from_slice(&128u32.to_le_bytes());
+        image[512 + 84..512 + 88].copy_from_slice(&128u32.to_le_bytes());
+        let entry = &mut image[1024..1152];
+        entry[..16].copy_from_slice(&[
+            0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E,
+            0xC9, 0x3B,
+        ]);
+        entry[32..40].copy_from_slice(&2048u64.to_le_bytes());
+        entry[40..48].copy_from_slice(&4095u64.to_le_bytes());
+        for (i, c) in "EFI".encode_utf16().enumerate() {
+            entry[56 + 2 * i..58 + 2 * i].copy_from_slice(&c.to_le_bytes());
+        }
+        let disk = detect(&mut Cursor::new(&image)).unwrap();
+        let Some(PartitionTable::Gpt { partitions, .. }) = disk.table else {
+            panic!("no GPT");
+        };
+        assert_eq!(partitions[0].kind, "EFI system");
+        assert_eq!(partitions[0].name.as_deref(), Some("EFI"));
+        assert_eq!(partitions[0].size, 2048 * 512);
+
+        // partitions starting past the end of a truncated image are skipped
+        image.truncate(34 * 512);
+        let disk = detect(&mut Cursor::new(&image)).unwrap();
+        let Some(PartitionTable::Gpt { partitions, .. }) = disk.table else {
+            panic!("no GPT");
+        };
+        assert!(partitions.is_empty());
+    }
+
+    #[test]
+    fn filesystems() {
+        // a FAT16 boot sector, 512 byte sectors and 4 sector clusters
+        let mut sector = vec![0u8; 512];
+        sector[..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        sector[3..11].copy_from_slice(b"mkfs.fat");
+        sector[11..13].copy_from_slice(&512u16.to_le_bytes());
+        sector[13] = 4;
+        sector[14..16].copy_from_slice(&4u16.to_le_bytes());
+        sector[16] = 2;
+        sector[17..19].copy_from_slice(&512u16.to_le_bytes());
+        sector[19..21].copy_from_slice(&40960u16.to_le_bytes());
+        sector[22..24].copy_from_slice(&40u16.to_le_bytes());
+        sector[38] = 0x29;
+        sector[39..43].copy_from_slice(&0x1234ABCDu32.to_le_bytes());
+        sector[43..54].copy_from_slice(b"BOOT       ");
+        sector[510..512].copy_from_slice(&MBR_SIGNATURE);
+        let disk = detect(&mut Cursor::new(&sector)).unwrap();
+        assert!(disk.table.is_none());
+        assert_eq!(disk.format(), "fat16");
+        let Some(Filesystem::Fat { label, serial, .. }) = disk.filesystem else {
+            panic!("no FAT");
+        };
+        assert_eq!((label.as_str(), serial.as_str()), ("BOOT", "1234-ABCD"));
+
+        // an uncompressed initramfs
+        let mut archive = Vec::new();
+        for (name, data) in [("init", "#!/bin/sh\n"), ("TRAILER!!!", "")] {
+            archive.extend_from_slice(
+                format!(
+                    "070701{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}",
+                    1, 0o100755, 0, 0, 1, 0, data.len(), 0, 0, 0, 0, name.len() + 1, 0
+                )
+                .as_bytes(),
+            );
+            archive.extend_from_slice(name.as_bytes());
+            archive.push(0);
+            while archive.len() % 4 != 0 {
+                archive.push(0);
+            }
+            archive.extend_from_slice(data.as_bytes());
+            while archive.len() % 4 != 0 {
+                archive.push(0);
+            }
+        }
+        let disk = detect(&mut Cursor::new(&archive)).unwrap();
+        assert!(matches!(
+            disk.filesystem,
+            Some(Filesystem::Cpio {
+                format: "newc",
+                compression: None,
+                entries: 1,
+                initramfs: true,
+            })
+        ));
+    }
+}
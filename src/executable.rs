@@ -1,18 +1,275 @@
-use crate::magic::MagicBytesMeta;
+pub mod debug;
+pub mod dotnet;
+pub mod java;
+pub mod ldso;
+pub mod macho;
+pub mod object;
+pub mod packing;
+pub mod pe;
+pub mod provenance;
+pub mod section;
+pub mod wasm;
+
+use crate::{
+    bytes::{u16_le, u32_be, u32_le},
+    extensions::ExecutableExtension,
+    magic::MagicBytesMeta,
+    shebang::Shebang,
+};
 use goblin::{
     Object,
+    elf::program_header::{PF_W, PF_X, PT_LOAD},
     mach::{Mach, SingleArch},
 };
 use nu_protocol::{Span, Value, record};
-use std::{
-    fs::File,
-    io::{Read, Seek, SeekFrom},
-    path::Path,
-};
+use std::{fs::File, io::Read, path::Path};
+
+/// Executable containers, recognised by their leading bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Elf,
+    MachO,
+    FatMachO,
+    Pe,
+    Wasm,
+    JavaClass,
+    Dex,
+    Script,
+    Archive,
+    /// ZIP based packages, only recognised by their extension.
+    Jar,
+    Apk,
+    /// Relocatable COFF objects have no magic, only a machine type.
+    Coff,
+}
+
+impl Container {
+    pub fn sniff(path: impl AsRef<Path>) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let length = file.metadata().ok()?.len();
+        let mut buf = Vec::with_capacity(20);
+        file.take(20).read_to_end(&mut buf).ok()?;
+        // with no magic to go on, COFF is the last guess
+        Self::from_bytes(&buf).or_else(|| is_coff_header(&buf, length).then_some(Container::Coff))
+    }
+
+    /// Containers that can only be told apart by their extension.
+    pub fn from_extension(extension: ExecutableExtension) -> Option<Self> {
+        match extension {
+            ExecutableExtension::Jar => Some(Container::Jar),
+            ExecutableExtension::Apk => Some(Container::Apk),
+            _ => None,
+        }
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        use goblin::mach::{fat, header};
+
+        if buf.starts_with(goblin::elf::header::ELFMAG) {
+            return Some(Container::Elf);
+        }
+        if buf.starts_with(b"MZ") {
+            return Some(Container::Pe);
+        }
+        if buf.starts_with(wasm::WASM_MAGIC) {
+            return Some(Container::Wasm);
+        }
+        if buf.starts_with(java::DEX_MAGIC) {
+            return Some(Container::Dex);
+        }
+        if buf.starts_with(b"#!") {
+            return Some(Container::Script);
+        }
+        if buf.starts_with(object::AR_MAGIC) {
+            return Some(Container::Archive);
+        }
+        match u32_be(buf, 0)? {
+            header::MH_MAGIC | header::MH_CIGAM | header::MH_MAGIC_64 | header::MH_CIGAM_64 => {
+                Some(Container::MachO)
+            }
+            // CAFEBABE is shared with Java class files; a fat header is
+            // followed by a small arch count where a class file has its
+            // version, whose major part is at least 45
+            fat::FAT_MAGIC => match u32_be(buf, 4) {
+                Some(count) if count < 45 => Some(Container::FatMachO),
+                _ => Some(Container::JavaClass),
+            },
+            fat::FAT_CIGAM => Some(Container::FatMachO),
+            _ => None,
+        }
+    }
+}
+
+/// Machine types goblin parses COFF objects for, with no optional header,
+/// a plausible section count, and section headers and symbol table that
+/// fit in a file of `length` bytes.
+fn is_coff_header(buf: &[u8], length: u64) -> bool {
+    use goblin::pe::header::{COFF_MACHINE_ARM64, COFF_MACHINE_X86, COFF_MACHINE_X86_64};
+    const HEADER_SIZE: u64 = 20;
+    const SECTION_HEADER_SIZE: u64 = 40;
+    const SYMBOL_SIZE: u64 = 18;
+
+    let (Some(machine), Some(sections), Some(symbols), Some(symbol_count), Some(optional)) = (
+        u16_le(buf, 0),
+        u16_le(buf, 2),
+        u32_le(buf, 8),
+        u32_le(buf, 12),
+        u16_le(buf, 16),
+    ) else {
+        return false;
+    };
+    let headers_end = HEADER_SIZE + sections as u64 * SECTION_HEADER_SIZE;
+    let symbols_fit = match (symbols as u64, symbol_count as u64) {
+        (0, 0) => true,
+        (0, _) | (_, 0) => false,
+        (symbols, count) => symbols >= headers_end && symbols + count * SYMBOL_SIZE <= length,
+    };
+    matches!(
+        machine,
+        COFF_MACHINE_X86 | COFF_MACHINE_X86_64 | COFF_MACHINE_ARM64
+    ) && (1..=96).contains(&sections)
+        && optional == 0
+        && headers_end <= length
+        && symbols_fit
+}
+
+/// Every kind of executable we know how to take apart.
+pub enum Executable {
+    Binary(Binary),
+    Script(Shebang),
+    Wasm(wasm::Module),
+    JavaClass(java::ClassFile),
+    Dex(java::Dex),
+    Package(java::Package),
+    Archive(object::StaticLibrary),
+}
+
+impl Executable {
+    pub fn parse(path: impl AsRef<Path>, container: Container) -> Result<Self, String> {
+        match container {
+            Container::Elf
+            | Container::MachO
+            | Container::FatMachO
+            | Container::Pe
+            | Container::Coff => Binary::parse(path).map(Executable::Binary),
+            Container::Script => {
+                let mut line = Vec::new();
+                File::open(path)
+                    .and_then(|f| f.take(4096).read_to_end(&mut line))
+                    .map_err(|e| e.to_string())?;
+                Shebang::parse(&line)
+                    .map(Executable::Script)
+                    .ok_or_else(|| "Invalid interpreter line".to_string())
+            }
+            container => {
+                let buffer = std::fs::read(path).map_err(|e| e.to_string())?;
+                match container {
+                    Container::Wasm => wasm::Module::parse(&buffer).map(Executable::Wasm),
+                    Container::JavaClass => {
+                        java::ClassFile::parse(&buffer).map(Executable::JavaClass)
+                    }
+                    Container::Jar => java::Package::parse(&buffer, false).map(Executable::Package),
+                    Container::Apk => java::Package::parse(&buffer, true).map(Executable::Package),
+                    Container::Archive => {
+                        object::StaticLibrary::parse(&buffer).map(Executable::Archive)
+                    }
+                    _ => java::Dex::parse(&buffer).map(Executable::Dex),
+                }
+            }
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Executable::Binary(bin) => bin.description(),
+            Executable::Script(script) => format!("{} script", script.interpreter),
+            Executable::Wasm(module) => module.description(),
+            Executable::JavaClass(class) => class.description(),
+            Executable::Dex(dex) => dex.description(),
+            Executable::Package(package) => package.description(),
+            Executable::Archive(archive) => archive.description(),
+        }
+    }
+
+    /// Magic bytes of the container and, for fat binaries, of each arch.
+    pub fn magic_bytes(&self) -> Vec<MagicBytesMeta> {
+        let leading = |bytes: &[u8]| {
+            vec![MagicBytesMeta {
+                offset: 0,
+                length: bytes.len(),
+                bytes: bytes.to_vec(),
+            }]
+        };
+        match self {
+            Executable::Binary(bin) => bin
+                .magic_bytes
+                .iter()
+                .chain(bin.arches.iter().map(|arch| &arch.magic_bytes))
+                .map(|m| MagicBytesMeta {
+                    offset: m.offset,
+                    length: m.length,
+                    bytes: m.bytes.clone(),
+                })
+                .collect(),
+            Executable::Script(_) => leading(b"#!"),
+            Executable::Wasm(_) => leading(wasm::WASM_MAGIC),
+            Executable::JavaClass(_) => leading(java::CLASS_MAGIC),
+            Executable::Dex(_) => leading(java::DEX_MAGIC),
+            Executable::Package(_) => leading(b"PK\x03\x04"),
+            Executable::Archive(_) => leading(object::AR_MAGIC),
+        }
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        match self {
+            Executable::Binary(bin) => bin.into_value(span),
+            Executable::Script(script) => Value::record(
+                record!(
+                    "format" => Value::string("script", span),
+                    "path" => Value::string(&script.path, span),
+                    "interpreter" => Value::string(&script.interpreter, span),
+                    "language" => crate::language::from_interpreter(&script.interpreter)
+                        .map_or(Value::nothing(span), |l| Value::string(l.to_string(), span)),
+                    "args" => Value::list(
+                        script.args.iter().map(|a| Value::string(a, span)).collect(),
+                        span,
+                    ),
+                ),
+                span,
+            ),
+            Executable::Wasm(module) => module.into_value(span),
+            Executable::JavaClass(class) => class.into_value(span),
+            Executable::Dex(dex) => dex.into_value(span),
+            Executable::Package(package) => package.into_value(span),
+            Executable::Archive(archive) => archive.into_value(span),
+        }
+    }
+
+    /// Packing heuristics for native binaries, nothing for everything else.
+    pub fn packing_value(&self, span: Span) -> Value {
+        match self {
+            Executable::Binary(bin) => bin.packing_value(span),
+            _ => Value::nothing(span),
+        }
+    }
+
+    pub fn resolve_dependencies(
+        &mut self,
+        path: impl AsRef<Path>,
+        ld_library_path: Vec<String>,
+    ) -> Result<(), String> {
+        match self {
+            Executable::Binary(bin) => bin.resolve_dependencies(path, ld_library_path),
+            _ => Ok(()),
+        }
+    }
+}
 
 pub struct Binary {
     pub arches: Vec<BinaryArch>,
     pub magic_bytes: Option<MagicBytesMeta>,
+    /// Entropy of the whole file.
+    pub entropy: f64,
 }
 pub struct BinaryArch {
     pub magic_bytes: MagicBytesMeta,
@@ -20,6 +277,14 @@ pub struct BinaryArch {
     pub arch: String,
     pub dependencies: Vec<String>,
     pub rpaths: Vec<String>,
+    pub pe: Option<pe::PeDetails>,
+    pub macho: Option<macho::MachDetails>,
+    pub symbols: Option<object::Symbols>,
+    pub provenance: Option<provenance::Provenance>,
+    pub debug: Option<debug::DebugInfo>,
+    /// Not part of `details`, see [`Binary::packing_value`].
+    pub packing: Option<packing::Packing>,
+    pub resolved: Option<Vec<ldso::Dependency>>,
 }
 impl BinaryArch {
     pub fn into_value(&self, span: Span) -> Value {
@@ -44,6 +309,27 @@ impl BinaryArch {
                 ),
             );
         }
+        if let Some(pe) = &self.pe {
+            pe.push_values(&mut value, span);
+        }
+        if let Some(macho) = &self.macho {
+            macho.push_values(&mut value, span);
+        }
+        if let Some(symbols) = &self.symbols {
+            symbols.push_values(&mut value, span);
+        }
+        if let Some(provenance) = &self.provenance {
+            provenance.push_values(&mut value, span);
+        }
+        if let Some(debug) = &self.debug {
+            value.push("debug_info", debug.into_value(span));
+        }
+        if let Some(resolved) = &self.resolved {
+            value.push(
+                "resolved",
+                Value::list(resolved.iter().map(|x| x.into_value(span)).collect(), span),
+            );
+        }
         Value::record(value, span)
     }
 }
@@ -73,6 +359,9 @@ impl Binary {
         match object {
             Object::Mach(Mach::Binary(prg)) => {
                 let magic_bytes = prg.header.magic.to_le_bytes().to_vec();
+                let sections = section::Section::from_macho(&prg);
+                let details = macho::MachDetails::parse(&prg, &buffer);
+                let debug = debug::DebugInfo::from_macho(&prg, details.uuid.as_deref());
                 Ok(Binary {
                     arches: vec![BinaryArch {
                         magic_bytes: MagicBytesMeta {
@@ -87,9 +376,17 @@ impl Binary {
                         )
                         .map_or(String::new(), |x| x.to_lowercase()),
                         dependencies: prg.libs.iter().map(|x| x.to_string()).skip(1).collect(),
-                        rpaths: prg.rpaths.into_iter().map(|x| x.to_string()).collect(),
+                        rpaths: prg.rpaths.iter().map(|x| x.to_string()).collect(),
+                        pe: None,
+                        macho: Some(details),
+                        symbols: object::Symbols::from_macho(&prg),
+                        provenance: provenance::Provenance::parse(&buffer, &sections),
+                        debug: Some(debug),
+                        packing: Some(packing::Packing::analyze(&buffer, &sections, None)),
+                        resolved: None,
                     }],
                     magic_bytes: None,
+                    entropy: packing::entropy(&buffer),
                 })
             }
             Object::Mach(Mach::Fat(arches)) => {
@@ -109,6 +406,10 @@ impl Binary {
                                 Err(e) => return Some(Err(e.to_string())),
                             };
                             let magic_bytes = prg.header.magic.to_le_bytes().to_vec();
+                            let data = arch.slice(&buffer);
+                            let sections = section::Section::from_macho(&prg);
+                            let details = macho::MachDetails::parse(&prg, data);
+                            let debug = debug::DebugInfo::from_macho(&prg, details.uuid.as_deref());
                             Some(Ok(BinaryArch {
                                 magic_bytes: MagicBytesMeta {
                                     offset: arch.offset as _,
@@ -127,7 +428,14 @@ impl Binary {
                                     .map(|x| x.to_string())
                                     .skip(1)
                                     .collect(),
-                                rpaths: prg.rpaths.into_iter().map(|x| x.to_string()).collect(),
+                                rpaths: prg.rpaths.iter().map(|x| x.to_string()).collect(),
+                                pe: None,
+                                macho: Some(details),
+                                symbols: object::Symbols::from_macho(&prg),
+                                provenance: provenance::Provenance::parse(data, &sections),
+                                debug: Some(debug),
+                                packing: Some(packing::Packing::analyze(data, &sections, None)),
+                                resolved: None,
                             }))
                         })
                         .collect::<Result<Vec<_>, String>>()?,
@@ -136,11 +444,16 @@ impl Binary {
                         length: magic_bytes.len(),
                         bytes: magic_bytes,
                     }),
+                    entropy: packing::entropy(&buffer),
                 })
             }
             Object::PE(prg) => {
                 let dos_magic_bytes = prg.header.dos_header.signature.to_le_bytes().to_vec();
                 let pe_magic_bytes = prg.header.signature.to_le_bytes().to_vec();
+                let details = pe::PeDetails::parse(&prg, &buffer);
+                let sections = section::Section::from_pe(&prg);
+                // .NET assemblies only import _CorExeMain or _CorDllMain
+                let import_count = details.clr.is_none().then_some(prg.imports.len());
                 Ok(Binary {
                     arches: vec![BinaryArch {
                         magic_bytes: MagicBytesMeta {
@@ -153,16 +466,36 @@ impl Binary {
                             .to_lowercase(),
                         dependencies: prg.libraries.iter().map(|x| x.to_string()).collect(),
                         rpaths: Vec::new(),
+                        pe: Some(details),
+                        macho: None,
+                        symbols: None,
+                        provenance: provenance::Provenance::parse(&buffer, &sections),
+                        debug: Some(debug::DebugInfo::from_pe(&prg, &buffer)),
+                        packing: Some(packing::Packing::analyze(&buffer, &sections, import_count)),
+                        resolved: None,
                     }],
                     magic_bytes: Some(MagicBytesMeta {
                         offset: 0,
                         length: dos_magic_bytes.len(),
                         bytes: dos_magic_bytes,
                     }),
+                    entropy: packing::entropy(&buffer),
                 })
             }
             Object::Elf(prg) => {
                 let magic_bytes = prg.header.e_ident[0..4].to_vec();
+                let sections = section::Section::from_elf(&prg);
+                let mut analysis = packing::Packing::analyze(&buffer, &sections, None);
+                // section headers are optional, segments are what gets mapped
+                if prg
+                    .program_headers
+                    .iter()
+                    .any(|ph| ph.p_type == PT_LOAD && ph.p_flags & (PF_W | PF_X) == PF_W | PF_X)
+                {
+                    analysis
+                        .indicators
+                        .push("writable and executable segment".to_string());
+                }
                 Ok(Binary {
                     arches: vec![BinaryArch {
                         magic_bytes: MagicBytesMeta {
@@ -174,46 +507,109 @@ impl Binary {
                         arch: goblin::elf::header::machine_to_str(prg.header.e_machine)
                             .to_lowercase(),
                         dependencies: prg.libraries.iter().map(|x| x.to_string()).collect(),
-                        rpaths: prg.rpaths.into_iter().map(|x| x.to_string()).collect(),
+                        rpaths: prg.rpaths.iter().map(|x| x.to_string()).collect(),
+                        pe: None,
+                        macho: None,
+                        symbols: object::Symbols::from_elf(&prg),
+                        provenance: provenance::Provenance::parse(&buffer, &sections),
+                        debug: Some(debug::DebugInfo::from_elf(&prg, &buffer)),
+                        packing: Some(analysis),
+                        resolved: None,
+                    }],
+                    magic_bytes: None,
+                    entropy: packing::entropy(&buffer),
+                })
+            }
+            Object::COFF(prg) => {
+                let magic_bytes = prg.header.machine.to_le_bytes().to_vec();
+                Ok(Binary {
+                    arches: vec![BinaryArch {
+                        magic_bytes: MagicBytesMeta {
+                            offset: 0,
+                            length: magic_bytes.len(),
+                            bytes: magic_bytes,
+                        },
+                        format: "coff",
+                        arch: goblin::pe::header::machine_to_str(prg.header.machine).to_lowercase(),
+                        dependencies: Vec::new(),
+                        rpaths: Vec::new(),
+                        pe: None,
+                        macho: None,
+                        symbols: Some(object::Symbols::from_coff(&prg)),
+                        provenance: None,
+                        debug: None,
+                        packing: None,
+                        resolved: None,
                     }],
                     magic_bytes: None,
+                    entropy: packing::entropy(&buffer),
                 })
             }
             _ => Err("Unsupported file format".to_string()),
         }
     }
-    pub fn has_magic_bytes(path: impl AsRef<Path>) -> bool {
-        let Ok(ref mut file) = File::open(&path) else {
-            return false;
-        };
-        let mut buf: [u8; 4] = [0_u8; 4];
-
-        if file.seek(SeekFrom::Start(0)).is_err() || file.read_exact(&mut buf).is_err() {
-            return false;
-        }
-        if buf.eq(goblin::elf::header::ELFMAG) {
-            return true;
-        }
-        let magic32 = u32::from_le_bytes(buf);
-        if magic32 == goblin::mach::header::MH_MAGIC
-            || magic32 == goblin::mach::header::MH_CIGAM
-            || magic32 == goblin::mach::header::MH_MAGIC_64
-            || magic32 == goblin::mach::header::MH_CIGAM_64
-            || magic32 == goblin::mach::fat::FAT_MAGIC
-            || magic32 == goblin::mach::fat::FAT_CIGAM
-        {
-            return true;
+    /// Whole file entropy and the packing heuristics of each arch.
+    pub fn packing_value(&self, span: Span) -> Value {
+        let fat = self.arches.len() > 1;
+        let mut packer = None;
+        let mut indicators = Vec::new();
+        let mut sections = Vec::new();
+        let mut packed = false;
+        for arch in &self.arches {
+            let Some(packing) = &arch.packing else {
+                continue;
+            };
+            packer = packer.or(packing.packer);
+            packed |= packing.is_packed();
+            for indicator in &packing.indicators {
+                indicators.push(if fat {
+                    Value::string(format!("{}: {indicator}", arch.arch), span)
+                } else {
+                    Value::string(indicator, span)
+                });
+            }
+            for section in &packing.sections {
+                let mut value = packing::Packing::section_value(section, span);
+                if fat && let Value::Record { val, .. } = &mut value {
+                    val.to_mut().insert("arch", Value::string(&arch.arch, span));
+                }
+                sections.push(value);
+            }
         }
-        let magic16 = u16::from_le_bytes([buf[0], buf[1]]);
-        if magic16 == goblin::pe::optional_header::MAGIC_32
-            || magic16 == goblin::pe::optional_header::MAGIC_64
-        {
-            return true;
+        Value::record(
+            record!(
+                "entropy" => Value::float(self.entropy, span),
+                "packed" => Value::bool(packed, span),
+                "packer" => packer.map_or(Value::nothing(span), |p| Value::string(p, span)),
+                "indicators" => Value::list(indicators, span),
+                "sections" => Value::list(sections, span),
+            ),
+            span,
+        )
+    }
+    /// Walk the shared library tree of ELF binaries, see [`ldso`].
+    pub fn resolve_dependencies(
+        &mut self,
+        path: impl AsRef<Path>,
+        ld_library_path: Vec<String>,
+    ) -> Result<(), String> {
+        let mut resolver = ldso::Resolver::new(ld_library_path);
+        for arch in self.arches.iter_mut() {
+            if arch.format.starts_with("elf") {
+                arch.resolved = Some(resolver.resolve(path.as_ref())?);
+            }
         }
-        false
+        Ok(())
     }
     pub fn description(&self) -> String {
-        let desc_arch = |arch: &BinaryArch| format!("{} binary, {}", arch.format, arch.arch);
+        let desc_arch = |arch: &BinaryArch| {
+            let kind = match (&arch.pe, &arch.symbols) {
+                (Some(pe), _) if pe.clr.is_some() => ".net assembly",
+                (_, Some(_)) => "object",
+                _ => "binary",
+            };
+            format!("{} {}, {}", arch.format, kind, arch.arch)
+        };
         match self.arches.len() {
             0 => String::new(),
             1 => desc_arch(&self.arches[0]),
@@ -231,3 +627,55 @@ impl Binary {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Container, is_coff_header};
+
+    #[test]
+    fn sniff_containers() {
+        assert_eq!(Container::from_bytes(b"MZ\x90\0"), Some(Container::Pe));
+        assert_eq!(Container::from_bytes(b"\x7fELF\x02"), Some(Container::Elf));
+        assert_eq!(
+            Container::from_bytes(b"\0asm\x01\0\0\0"),
+            Some(Container::Wasm)
+        );
+        assert_eq!(Container::from_bytes(b"dex\n035\0"), Some(Container::Dex));
+        assert_eq!(Container::from_bytes(b"#!/bin/sh"), Some(Container::Script));
+        assert_eq!(
+            Container::from_bytes(&[0xCF, 0xFA, 0xED, 0xFE, 0x0C, 0, 0, 0x01]),
+            Some(Container::MachO)
+        );
+        assert_eq!(
+            Container::from_bytes(&[0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 2]),
+            Some(Container::FatMachO)
+        );
+        assert_eq!(
+            Container::from_bytes(&[0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52]),
+            Some(Container::JavaClass)
+        );
+        assert_eq!(Container::from_bytes(b"\x0b\x01"), None);
+    }
+
+    #[test]
+    fn coff_headers() {
+        // an x86-64 object with two sections and four symbols after them
+        let mut header = vec![0x64, 0x86, 2, 0];
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&200u32.to_le_bytes());
+        header.extend_from_slice(&4u32.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        assert!(is_coff_header(&header, 300));
+        // the symbol table runs past the end of the file
+        assert!(!is_coff_header(&header, 250));
+        // the symbol table overlaps the section headers
+        header[8..12].copy_from_slice(&60u32.to_le_bytes());
+        assert!(!is_coff_header(&header, 300));
+        // images have an optional header, objects don't
+        header[8..12].copy_from_slice(&200u32.to_le_bytes());
+        header[16] = 0xF0;
+        assert!(!is_coff_header(&header, 300));
+        // a machine type followed by text
+        assert!(!is_coff_header(b"\x4c\x01\x01\x00 plain text here", 4096));
+    }
+}
@@ -0,0 +1,301 @@
+//! What an image carries, or points at, for symbolication: DWARF sections,
+//! CodeView PDB references, GNU build ids and debug links, dSYM UUIDs.
+use crate::bytes::{cstr, hex, u16_be, u16_le, u32_be, u32_le};
+use flate2::read::ZlibDecoder;
+use goblin::{
+    elf::{
+        Elf,
+        compression_header::ELFCOMPRESS_ZLIB,
+        note::NT_GNU_BUILD_ID,
+        section_header::{SHF_COMPRESSED, SHT_NOBITS},
+    },
+    mach::{MachO, header::MH_DSYM},
+    pe::PE,
+};
+use nu_protocol::{Span, Value, record};
+use std::io::Read;
+
+/// `N_OSO` stabs name the object files a linked Mach-O's debug map points at.
+const N_OSO: u8 = 0x66;
+
+#[derive(Default)]
+pub struct DebugInfo {
+    pub dwarf: Option<Dwarf>,
+    pub pdb: Option<Pdb>,
+    pub build_id: Option<String>,
+    /// Separate debug file named by `.gnu_debuglink`.
+    pub debuglink: Option<String>,
+    /// The UUID a dSYM bundle has to carry to match this image.
+    pub dsym_uuid: Option<String>,
+    /// Object files referenced by the Mach-O debug map.
+    pub debug_map_objects: usize,
+    /// The image is itself a dSYM companion file.
+    pub dsym: bool,
+}
+
+#[derive(Default)]
+pub struct Dwarf {
+    pub sections: Vec<String>,
+    pub compressed: bool,
+    pub version: Option<u16>,
+}
+
+pub struct Pdb {
+    /// GUID for PDB 7.0 (`RSDS`), 32-bit signature for PDB 2.0 (`NB10`).
+    pub signature: String,
+    pub age: u32,
+    pub path: String,
+}
+
+impl DebugInfo {
+    pub fn from_elf(elf: &Elf, data: &[u8]) -> Self {
+        let mut info = DebugInfo::default();
+        let mut dwarf = Dwarf::default();
+        for sh in &elf.section_headers {
+            let name = elf.shdr_strtab.get_at(sh.sh_name).unwrap_or_default();
+            let contents = if sh.sh_type == SHT_NOBITS {
+                None
+            } else {
+                let start = sh.sh_offset as usize;
+                data.get(start..start.saturating_add(sh.sh_size as usize))
+            };
+            if name.starts_with(".debug_") || name.starts_with(".zdebug_") {
+                dwarf.sections.push(name.to_string());
+                let compressed = sh.sh_flags & SHF_COMPRESSED as u64 != 0;
+                dwarf.compressed |= compressed || name.starts_with(".zdebug_");
+                if name.ends_with("debug_info") {
+                    let contents = contents.unwrap_or_default();
+                    let header = if compressed {
+                        elf_compressed_prefix(contents, elf.is_64, elf.little_endian)
+                    } else if let Some(zlib) = contents.strip_prefix(b"ZLIB") {
+                        // GNU style: magic and big endian size, then the stream
+                        inflate_prefix(zlib.get(8..).unwrap_or_default())
+                    } else {
+                        Some(contents.to_vec())
+                    };
+                    dwarf.version = header.and_then(|h| dwarf_version(&h, elf.little_endian));
+                }
+            } else if name == ".gnu_debuglink" {
+                info.debuglink = contents.and_then(|c| cstr(c, 0));
+            }
+        }
+        if !dwarf.sections.is_empty() {
+            info.dwarf = Some(dwarf);
+        }
+        info.build_id = elf
+            .iter_note_sections(data, None)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .find(|note| note.n_type == NT_GNU_BUILD_ID && note.name == "GNU")
+            .map(|note| hex(note.desc));
+        info
+    }
+
+    pub fn from_pe(pe: &PE, data: &[u8]) -> Self {
+        let mut info = DebugInfo::default();
+        if let Some(debug) = &pe.debug_data {
+            if let Some(cv) = &debug.codeview_pdb70_debug_info {
+                info.pdb = Some(Pdb {
+                    signature: format_guid(&cv.signature),
+                    age: cv.age,
+                    path: cstr(cv.filename, 0).unwrap_or_default(),
+                });
+            } else if let Some(cv) = &debug.codeview_pdb20_debug_info {
+                info.pdb = Some(Pdb {
+                    signature: format!("{:08X}", cv.signature),
+                    age: cv.age,
+                    path: cstr(cv.filename, 0).unwrap_or_default(),
+                });
+            }
+        }
+        // MinGW keeps DWARF in long named sections
+        let mut dwarf = Dwarf::default();
+        for section in &pe.sections {
+            let name = section.name().unwrap_or_default();
+            if !name.starts_with(".debug_") {
+                continue;
+            }
+            dwarf.sections.push(name.to_string());
+            if name == ".debug_info" {
+                let start = section.pointer_to_raw_data as usize;
+                dwarf.version = data
+                    .get(start..start.saturating_add(section.size_of_raw_data as usize))
+                    .and_then(|contents| dwarf_version(contents, true));
+            }
+        }
+        if !dwarf.sections.is_empty() {
+            info.dwarf = Some(dwarf);
+        }
+        info
+    }
+
+    pub fn from_macho(macho: &MachO, uuid: Option<&str>) -> Self {
+        let mut info = DebugInfo {
+            dsym_uuid: uuid.map(String::from),
+            dsym: macho.header.filetype == MH_DSYM,
+            ..Default::default()
+        };
+        let mut dwarf = Dwarf::default();
+        for segment in &macho.segments {
+            for (section, contents) in segment.sections().unwrap_or_default() {
+                let name = section.name().unwrap_or_default();
+                if !name.starts_with("__debug_") && !name.starts_with("__zdebug_") {
+                    continue;
+                }
+                dwarf.sections.push(name.to_string());
+                dwarf.compressed |= name.starts_with("__zdebug_");
+                if name == "__debug_info" {
+                    dwarf.version = dwarf_version(contents, macho.little_endian);
+                }
+            }
+        }
+        if !dwarf.sections.is_empty() {
+            info.dwarf = Some(dwarf);
+        }
+        info.debug_map_objects = macho
+            .symbols()
+            .flatten()
+            .filter(|(_, nlist)| nlist.n_type == N_OSO)
+            .count();
+        info
+    }
+
+    /// Whether the image can be symbolicated without anything else.
+    pub fn is_embedded(&self) -> bool {
+        self.dwarf.is_some() || self.dsym
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let optional = |s: &Option<String>| {
+            s.as_ref()
+                .map_or(Value::nothing(span), |s| Value::string(s, span))
+        };
+        let mut value = record!(
+            "embedded" => Value::bool(self.is_embedded(), span),
+            "dwarf" => self.dwarf.as_ref().map_or(Value::nothing(span), |dwarf| {
+                Value::record(
+                    record!(
+                        "version" => dwarf.version.map_or(Value::nothing(span), |v| Value::int(v as _, span)),
+                        "compressed" => Value::bool(dwarf.compressed, span),
+                        "sections" => Value::list(
+                            dwarf.sections.iter().map(|s| Value::string(s, span)).collect(),
+                            span,
+                        ),
+                    ),
+                    span,
+                )
+            }),
+        );
+        if let Some(pdb) = &self.pdb {
+            value.push(
+                "pdb",
+                Value::record(
+                    record!(
+                        "signature" => Value::string(&pdb.signature, span),
+                        "age" => Value::int(pdb.age as _, span),
+                        "path" => Value::string(&pdb.path, span),
+                    ),
+                    span,
+                ),
+            );
+        }
+        if self.build_id.is_some() || self.debuglink.is_some() {
+            value.push("build_id", optional(&self.build_id));
+            value.push("debuglink", optional(&self.debuglink));
+        }
+        if self.dsym_uuid.is_some() {
+            value.push("dsym_uuid", optional(&self.dsym_uuid));
+            value.push(
+                "debug_map_objects",
+                Value::int(self.debug_map_objects as _, span),
+            );
+        }
+        Value::record(value, span)
+    }
+}
+
+/// The first bytes of a `SHF_COMPRESSED` section, past its `Elf_Chdr`.
+fn elf_compressed_prefix(contents: &[u8], is_64: bool, little_endian: bool) -> Option<Vec<u8>> {
+    let compression = if little_endian {
+        u32_le(contents, 0)?
+    } else {
+        u32_be(contents, 0)?
+    };
+    // zstd needs a decoder we don't have
+    if compression != ELFCOMPRESS_ZLIB {
+        return None;
+    }
+    inflate_prefix(contents.get(if is_64 { 24 } else { 12 }..)?)
+}
+
+/// Enough of a zlib stream for a unit header.
+fn inflate_prefix(stream: &[u8]) -> Option<Vec<u8>> {
+    let mut prefix = Vec::with_capacity(16);
+    ZlibDecoder::new(stream)
+        .take(16)
+        .read_to_end(&mut prefix)
+        .ok()?;
+    Some(prefix)
+}
+
+/// Version of the first compilation unit in `.debug_info`.
+fn dwarf_version(info: &[u8], little_endian: bool) -> Option<u16> {
+    let read_u16 = |offset| {
+        if little_endian {
+            u16_le(info, offset)
+        } else {
+            u16_be(info, offset)
+        }
+    };
+    let length = if little_endian {
+        u32_le(info, 0)?
+    } else {
+        u32_be(info, 0)?
+    };
+    // 64-bit DWARF is flagged by an escape value, followed by the real length
+    let version = if length == 0xFFFF_FFFF {
+        read_u16(12)?
+    } else {
+        read_u16(4)?
+    };
+    (2..=5).contains(&version).then_some(version)
+}
+
+/// GUIDs store their first three fields little endian.
+fn format_guid(guid: &[u8; 16]) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{}-{}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        hex(&guid[8..10]).to_uppercase(),
+        hex(&guid[10..16]).to_uppercase()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{dwarf_version, format_guid};
+
+    #[test]
+    fn guid() {
+        let guid = [
+            0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD,
+            0xEE, 0xFF,
+        ];
+        assert_eq!(format_guid(&guid), "00112233-4455-6677-8899-AABBCCDDEEFF");
+    }
+
+    #[test]
+    fn unit_versions() {
+        assert_eq!(dwarf_version(&[0x10, 0, 0, 0, 5, 0], true), Some(5));
+        assert_eq!(
+            dwarf_version(
+                &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 4],
+                false
+            ),
+            Some(4)
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! ECMA-335 metadata for managed (.NET) PE files.
+//!
+//! goblin hands us the COR20 header, but we walk the metadata root and the
+//! `#~` table stream ourselves to get at the Assembly and AssemblyRef rows.
+use crate::bytes::{cstr, hex, u16_le, u32_le, u64_le};
+use goblin::pe::{PE, clr::Cor20Header, options::ParseOptions, utils::find_offset};
+use nu_protocol::{Span, Value, record};
+
+const METADATA_SIGNATURE: u32 = 0x424A_5342; // BSJB
+
+const TABLE_MODULE_REF: usize = 0x1A;
+const TABLE_ASSEMBLY: usize = 0x20;
+const TABLE_ASSEMBLY_REF: usize = 0x23;
+/// `AssemblyFlags.PublicKey`: the reference holds the full key, not its token.
+const AFL_PUBLIC_KEY: u32 = 0x0001;
+
+pub struct ClrDetails {
+    pub runtime_version: String,
+    pub header_version: String,
+    pub target_framework: Option<String>,
+    pub il_only: bool,
+    pub requires_32bit: bool,
+    pub prefers_32bit: bool,
+    pub strong_name_signed: bool,
+    pub assembly: Option<AssemblyName>,
+    pub references: Vec<AssemblyName>,
+}
+
+pub struct AssemblyName {
+    pub name: String,
+    pub version: String,
+    pub culture: Option<String>,
+    pub public_key_token: Option<String>,
+}
+
+impl ClrDetails {
+    pub fn parse(prg: &PE, buffer: &[u8]) -> Option<Self> {
+        let clr = prg.clr_data.as_ref()?;
+        let header = &clr.cor20_header;
+        let file_alignment = prg
+            .header
+            .optional_header
+            .map_or(0x200, |h| h.windows_fields.file_alignment);
+        let offset = find_offset(
+            header.metadata.virtual_address as usize,
+            &prg.sections,
+            file_alignment,
+            &ParseOptions::default(),
+        )?;
+        let metadata = buffer.get(offset..offset + header.metadata.size as usize)?;
+        let root = MetadataRoot::parse(metadata)?;
+        let tables = root
+            .stream("#~")
+            .or_else(|| root.stream("#-"))
+            .and_then(|stream| Tables::parse(stream, &root));
+
+        Some(ClrDetails {
+            runtime_version: root.version.clone(),
+            header_version: format!(
+                "{}.{}",
+                header.major_runtime_version, header.minor_runtime_version
+            ),
+            target_framework: root.stream("#Blob").and_then(target_framework),
+            il_only: header.is_il_only(),
+            requires_32bit: header.is_32bit_required() && !header.is_32bit_preferred(),
+            prefers_32bit: header.is_32bit_preferred(),
+            strong_name_signed: is_strong_name_signed(header),
+            assembly: tables.as_ref().and_then(|t| t.assembly()),
+            references: tables.map(|t| t.assembly_refs()).unwrap_or_default(),
+        })
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let mut value = record!(
+            "runtime_version" => Value::string(&self.runtime_version, span),
+            "header_version" => Value::string(&self.header_version, span),
+            "kind" => Value::string(if self.il_only { "il-only" } else { "mixed-mode" }, span),
+            "requires_32bit" => Value::bool(self.requires_32bit, span),
+            "prefers_32bit" => Value::bool(self.prefers_32bit, span),
+            "strong_name_signed" => Value::bool(self.strong_name_signed, span),
+        );
+        if let Some(target_framework) = &self.target_framework {
+            value.push("target_framework", Value::string(target_framework, span));
+        }
+        if let Some(assembly) = &self.assembly {
+            value.push("assembly", assembly.into_value(span));
+        }
+        value.push(
+            "references",
+            Value::list(
+                self.references.iter().map(|r| r.into_value(span)).collect(),
+                span,
+            ),
+        );
+        Value::record(value, span)
+    }
+}
+
+impl AssemblyName {
+    pub fn into_value(&self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "name" => Value::string(&self.name, span),
+                "version" => Value::string(&self.version, span),
+                "culture" => self
+                    .culture
+                    .as_ref()
+                    .map_or(Value::nothing(span), |c| Value::string(c, span)),
+                "public_key_token" => self
+                    .public_key_token
+                    .as_ref()
+                    .map_or(Value::nothing(span), |t| Value::string(t, span)),
+            ),
+            span,
+        )
+    }
+}
+
+fn is_strong_name_signed(header: &Cor20Header) -> bool {
+    header.is_strong_name_signed() && header.strong_name_signature.size > 0
+}
+
+/// `TargetFrameworkAttribute` values are stored as serialized strings in the blob heap.
+fn target_framework(blob: &[u8]) -> Option<String> {
+    [
+        &b".NETCoreApp,Version="[..],
+        b".NETFramework,Version=",
+        b".NETStandard,Version=",
+    ]
+    .iter()
+    .find_map(|prefix| {
+        let start = blob.windows(prefix.len()).position(|w| w == *prefix)?;
+        let len = *blob.get(start.checked_sub(1)?)? as usize;
+        let value = blob.get(start..start + len)?;
+        Some(String::from_utf8_lossy(value).into_owned())
+    })
+}
+
+/// The token is the last 8 bytes of the key's SHA-1, reversed.
+fn public_key_token(blob: &[u8], full_key: bool) -> String {
+    use sha1::{Digest, Sha1};
+
+    if !full_key {
+        return hex(blob);
+    }
+    let digest = Sha1::digest(blob);
+    hex(&digest.iter().rev().take(8).copied().collect::<Vec<_>>())
+}
+
+struct MetadataRoot<'a> {
+    version: String,
+    streams: Vec<(String, &'a [u8])>,
+}
+
+impl<'a> MetadataRoot<'a> {
+    fn parse(metadata: &'a [u8]) -> Option<Self> {
+        if u32_le(metadata, 0)? != METADATA_SIGNATURE {
+            return None;
+        }
+        let version_len = u32_le(metadata, 12)? as usize;
+        let version = cstr(metadata.get(16..16 + version_len)?, 0)?;
+        let mut offset = 16 + version_len + 2;
+        let count = u16_le(metadata, offset)?;
+        offset += 2;
+        let mut streams = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let start = u32_le(metadata, offset)? as usize;
+            let size = u32_le(metadata, offset + 4)? as usize;
+            let name = cstr(metadata, offset + 8)?;
+            // names are NUL terminated and padded to a 4 byte boundary
+            offset += 8 + (name.len() + 4) / 4 * 4;
+            streams.push((name, metadata.get(start..start + size)?));
+        }
+        Some(MetadataRoot { version, streams })
+    }
+
+    fn stream(&self, name: &str) -> Option<&'a [u8]> {
+        self.streams
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, data)| *data)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Column {
+    U16,
+    U32,
+    Str,
+    Guid,
+    Blob,
+    Table(usize),
+    Coded(&'static [usize], u32),
+}
+
+// `usize::MAX` marks the unused slots of a coded index.
+const NONE: usize = usize::MAX;
+const TYPE_DEF_OR_REF: Column = Column::Coded(&[0x02, 0x01, 0x1B], 2);
+const HAS_CONSTANT: Column = Column::Coded(&[0x04, 0x08, 0x17], 2);
+const HAS_CUSTOM_ATTRIBUTE: Column = Column::Coded(
+    &[
+        0x06, 0x04, 0x01, 0x02, 0x08, 0x09, 0x0A, 0x00, 0x0E, 0x17, 0x14, 0x11, 0x1A, 0x1B, 0x20,
+        0x23, 0x26, 0x27, 0x28, 0x2A, 0x2C, 0x2B,
+    ],
+    5,
+);
+const HAS_FIELD_MARSHAL: Column = Column::Coded(&[0x04, 0x08], 1);
+const HAS_DECL_SECURITY: Column = Column::Coded(&[0x02, 0x06, 0x20], 2);
+const MEMBER_REF_PARENT: Column = Column::Coded(&[0x02, 0x01, 0x1A, 0x06, 0x1B], 3);
+const HAS_SEMANTICS: Column = Column::Coded(&[0x14, 0x17], 1);
+const METHOD_DEF_OR_REF: Column = Column::Coded(&[0x06, 0x0A], 1);
+const MEMBER_FORWARDED: Column = Column::Coded(&[0x04, 0x06], 1);
+const CUSTOM_ATTRIBUTE_TYPE: Column = Column::Coded(&[NONE, NONE, 0x06, 0x0A, NONE], 3);
+const RESOLUTION_SCOPE: Column = Column::Coded(&[0x00, 0x1A, 0x23, 0x01], 2);
+
+/// Column layout of every table up to and including AssemblyRef, ECMA-335 II.22.
+const SCHEMA: [&[Column]; TABLE_ASSEMBLY_REF + 1] = {
+    use Column::*;
+    [
+        &[U16, Str, Guid, Guid, Guid],                               // Module
+        &[RESOLUTION_SCOPE, Str, Str],                               // TypeRef
+        &[U32, Str, Str, TYPE_DEF_OR_REF, Table(0x04), Table(0x06)], // TypeDef
+        &[Table(0x04)],                                              // FieldPtr
+        &[U16, Str, Blob],                                           // Field
+        &[Table(0x06)],                                              // MethodPtr
+        &[U32, U16, U16, Str, Blob, Table(0x08)],                    // MethodDef
+        &[Table(0x08)],                                              // ParamPtr
+        &[U16, U16, Str],                                            // Param
+        &[Table(0x02), TYPE_DEF_OR_REF],                             // InterfaceImpl
+        &[MEMBER_REF_PARENT, Str, Blob],                             // MemberRef
+        &[U16, HAS_CONSTANT, Blob],                                  // Constant
+        &[HAS_CUSTOM_ATTRIBUTE, CUSTOM_ATTRIBUTE_TYPE, Blob],        // CustomAttribute
+        &[HAS_FIELD_MARSHAL, Blob],                                  // FieldMarshal
+        &[U16, HAS_DECL_SECURITY, Blob],                             // DeclSecurity
+        &[U16, U32, Table(0x02)],                                    // ClassLayout
+        &[U32, Table(0x04)],                                         // FieldLayout
+        &[Blob],                                                     // StandAloneSig
+        &[Table(0x02), Table(0x14)],                                 // EventMap
+        &[Table(0x14)],                                              // EventPtr
+        &[U16, Str, TYPE_DEF_OR_REF],                                // Event
+        &[Table(0x02), Table(0x17)],                                 // PropertyMap
+        &[Table(0x17)],                                              // PropertyPtr
+        &[U16, Str, Blob],                                           // Property
+        &[U16, Table(0x06), HAS_SEMANTICS],                          // MethodSemantics
+        &[Table(0x02), METHOD_DEF_OR_REF, METHOD_DEF_OR_REF],        // MethodImpl
+        &[Str],                                                      // ModuleRef
+        &[Blob],                                                     // TypeSpec
+        &[U16, MEMBER_FORWARDED, Str, Table(TABLE_MODULE_REF)],      // ImplMap
+        &[U32, Table(0x04)],                                         // FieldRVA
+        &[U32, U32],                                                 // EncLog
+        &[U32],                                                      // EncMap
+        &[U32, U16, U16, U16, U16, U32, Blob, Str, Str],             // Assembly
+        &[U32],                                                      // AssemblyProcessor
+        &[U32, U32, U32],                                            // AssemblyOS
+        &[U16, U16, U16, U16, U32, Blob, Str, Str, Blob],            // AssemblyRef
+    ]
+};
+
+struct Tables<'a> {
+    data: &'a [u8],
+    strings: &'a [u8],
+    blobs: &'a [u8],
+    heap_sizes: u8,
+    rows: [u32; 64],
+    /// Offset of the first row of each table within `data`.
+    offsets: [usize; TABLE_ASSEMBLY_REF + 1],
+}
+
+impl<'a> Tables<'a> {
+    fn parse(stream: &'a [u8], root: &MetadataRoot<'a>) -> Option<Self> {
+        let heap_sizes = *stream.get(6)?;
+        let valid = u64_le(stream, 8)?;
+        let mut rows = [0u32; 64];
+        let mut offset = 24;
+        for (table, count) in rows.iter_mut().enumerate() {
+            if valid & (1 << table) != 0 {
+                *count = u32_le(stream, offset)?;
+                offset += 4;
+            }
+        }
+        let mut tables = Tables {
+            data: stream,
+            strings: root.stream("#Strings").unwrap_or_default(),
+            blobs: root.stream("#Blob").unwrap_or_default(),
+            heap_sizes,
+            rows,
+            offsets: [0; TABLE_ASSEMBLY_REF + 1],
+        };
+        for table in 0..=TABLE_ASSEMBLY_REF {
+            tables.offsets[table] = offset;
+            offset += tables.row_size(table) * tables.rows[table] as usize;
+        }
+        Some(tables)
+    }
+
+    fn column_size(&self, column: Column) -> usize {
+        let index = |wide: bool| if wide { 4 } else { 2 };
+        match column {
+            Column::U16 => 2,
+            Column::U32 => 4,
+            Column::Str => index(self.heap_sizes & 0x01 != 0),
+            Column::Guid => index(self.heap_sizes & 0x02 != 0),
+            Column::Blob => index(self.heap_sizes & 0x04 != 0),
+            Column::Table(table) => index(self.rows[table] > 0xFFFF),
+            Column::Coded(tables, tag_bits) => {
+                let max = tables
+                    .iter()
+                    .filter(|t| **t != NONE)
+                    .map(|t| self.rows[*t])
+                    .max()
+                    .unwrap_or(0);
+                index(max >= 1 << (16 - tag_bits))
+            }
+        }
+    }
+
+    fn row_size(&self, table: usize) -> usize {
+        SCHEMA[table].iter().map(|c| self.column_size(*c)).sum()
+    }
+
+    fn row(&self, table: usize, index: usize) -> Option<Vec<u32>> {
+        let mut offset = self.offsets[table] + index * self.row_size(table);
+        SCHEMA[table]
+            .iter()
+            .map(|column| {
+                let value = match self.column_size(*column) {
+                    2 => u16_le(self.data, offset)? as u32,
+                    _ => u32_le(self.data, offset)?,
+                };
+                offset += self.column_size(*column);
+                Some(value)
+            })
+            .collect()
+    }
+
+    fn string(&self, index: u32) -> Option<String> {
+        cstr(self.strings, index as usize).filter(|s| !s.is_empty())
+    }
+
+    fn blob(&self, index: u32) -> Option<&'a [u8]> {
+        let index = index as usize;
+        let first = *self.blobs.get(index)?;
+        // compressed length prefix, ECMA-335 II.23.2
+        let (len, start) = match first {
+            b if b & 0x80 == 0 => (b as usize, index + 1),
+            b if b & 0xC0 == 0x80 => (
+                ((b as usize & 0x3F) << 8) | *self.blobs.get(index + 1)? as usize,
+                index + 2,
+            ),
+            _ => (
+                u32::from_be_bytes(crate::bytes::array(self.blobs, index)?) as usize & 0x1FFF_FFFF,
+                index + 4,
+            ),
+        };
+        self.blobs.get(start..start + len)
+    }
+
+    fn assembly(&self) -> Option<AssemblyName> {
+        if self.rows[TABLE_ASSEMBLY] == 0 {
+            return None;
+        }
+        let row = self.row(TABLE_ASSEMBLY, 0)?;
+        Some(AssemblyName {
+            name: self.string(row[7])?,
+            version: format!("{}.{}.{}.{}", row[1], row[2], row[3], row[4]),
+            culture: self.string(row[8]),
+            public_key_token: None,
+        })
+    }
+
+    fn assembly_refs(&self) -> Vec<AssemblyName> {
+        // the row count comes from the file, so stop at the first row past the stream
+        (0..self.rows[TABLE_ASSEMBLY_REF] as usize)
+            .map_while(|index| self.row(TABLE_ASSEMBLY_REF, index))
+            .filter_map(|row| {
+                Some(AssemblyName {
+                    name: self.string(row[6])?,
+                    version: format!("{}.{}.{}.{}", row[0], row[1], row[2], row[3]),
+                    culture: self.string(row[7]),
+                    public_key_token: self
+                        .blob(row[5])
+                        .filter(|b| !b.is_empty())
+                        .map(|b| public_key_token(b, row[4] & AFL_PUBLIC_KEY != 0)),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::public_key_token;
+
+    #[test]
+    fn public_key_tokens() {
+        // the ECMA standard key that mscorlib references are signed with
+        let ecma = [0, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(public_key_token(&ecma, true), "b77a5c561934e089");
+        let token = [0xB0, 0x3F, 0x5F, 0x7F, 0x11, 0xD5, 0x0A, 0x3A];
+        assert_eq!(public_key_token(&token, false), "b03f5f7f11d50a3a");
+    }
+}
@@ -0,0 +1,344 @@
+//! Java class files, JARs and Android DEX bytecode.
+use crate::{
+    bytes::{u16_be, u32_le},
+    zip::ZipArchive,
+};
+use nu_protocol::{Record, Span, Value, record};
+
+pub const CLASS_MAGIC: &[u8] = &[0xCA, 0xFE, 0xBA, 0xBE];
+pub const DEX_MAGIC: &[u8] = b"dex\n";
+
+const ACC_INTERFACE: u16 = 0x0200;
+const ACC_ANNOTATION: u16 = 0x2000;
+const ACC_ENUM: u16 = 0x4000;
+const ACC_MODULE: u16 = 0x8000;
+
+/// Versioned entries of multi-release JARs are only loaded by newer JDKs.
+const VERSIONED_PREFIX: &str = "META-INF/versions/";
+
+#[derive(Default)]
+pub struct ClassFile {
+    pub minor_version: u16,
+    pub major_version: u16,
+    pub kind: &'static str,
+    pub name: Option<String>,
+    pub super_class: Option<String>,
+    pub interfaces: Vec<String>,
+}
+
+/// Java release that introduced a class file major version.
+pub fn java_release(major: u16) -> String {
+    match major {
+        45 => "1.1".to_string(),
+        46..=48 => format!("1.{}", major - 44),
+        49.. => (major - 44).to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// `(major, minor)` version out of the first 8 bytes of a class file.
+fn class_version(buffer: &[u8]) -> Option<(u16, u16)> {
+    if !buffer.starts_with(CLASS_MAGIC) {
+        return None;
+    }
+    Some((u16_be(buffer, 6)?, u16_be(buffer, 4)?))
+}
+
+impl ClassFile {
+    pub fn parse(buffer: &[u8]) -> Result<Self, String> {
+        let (major_version, minor_version) =
+            class_version(buffer).ok_or("Not a Java class file")?;
+        let mut class = ClassFile {
+            minor_version,
+            major_version,
+            kind: "class",
+            ..Default::default()
+        };
+        // a truncated constant pool still leaves us with the version
+        class.parse_names(buffer);
+        Ok(class)
+    }
+
+    fn parse_names(&mut self, buffer: &[u8]) -> Option<()> {
+        let count = u16_be(buffer, 8)? as usize;
+        // entries are 1-based; long and double take two slots
+        let mut utf8 = vec![None; count];
+        let mut classes = vec![None; count];
+        let mut pos = 10;
+        let mut index = 1;
+        while index < count {
+            let tag = *buffer.get(pos)?;
+            pos += 1;
+            let mut slots = 1;
+            pos += match tag {
+                1 => {
+                    let len = u16_be(buffer, pos)? as usize;
+                    let bytes = buffer.get(pos + 2..pos + 2 + len)?;
+                    utf8[index] = Some(String::from_utf8_lossy(bytes).into_owned());
+                    2 + len
+                }
+                7 => {
+                    classes[index] = Some(u16_be(buffer, pos)? as usize);
+                    2
+                }
+                8 | 16 | 19 | 20 => 2,
+                15 => 3,
+                3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => 4,
+                5 | 6 => {
+                    slots = 2;
+                    8
+                }
+                _ => return None,
+            };
+            index += slots;
+        }
+        let class_name = |index: usize| {
+            let name = utf8.get(*classes.get(index)?.as_ref()?)?.as_ref()?;
+            Some(name.replace('/', "."))
+        };
+
+        let access = u16_be(buffer, pos)?;
+        self.kind = if access & ACC_MODULE != 0 {
+            "module"
+        } else if access & ACC_ANNOTATION != 0 {
+            "annotation"
+        } else if access & ACC_INTERFACE != 0 {
+            "interface"
+        } else if access & ACC_ENUM != 0 {
+            "enum"
+        } else {
+            "class"
+        };
+        self.name = class_name(u16_be(buffer, pos + 2)? as usize);
+        self.super_class = class_name(u16_be(buffer, pos + 4)? as usize);
+        let interfaces = u16_be(buffer, pos + 6)? as usize;
+        self.interfaces = (0..interfaces)
+            .filter_map(|i| class_name(u16_be(buffer, pos + 8 + i * 2)? as usize))
+            .collect();
+        Some(())
+    }
+
+    pub fn description(&self) -> String {
+        format!(
+            "java {}, java {}",
+            self.kind,
+            java_release(self.major_version)
+        )
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let optional = |s: &Option<String>| {
+            s.as_ref()
+                .map_or(Value::nothing(span), |s| Value::string(s, span))
+        };
+        Value::record(
+            record!(
+                "format" => Value::string("class", span),
+                "version" => Value::string(format!("{}.{}", self.major_version, self.minor_version), span),
+                "java_release" => Value::string(java_release(self.major_version), span),
+                "kind" => Value::string(self.kind, span),
+                "name" => optional(&self.name),
+                "super_class" => optional(&self.super_class),
+                "interfaces" => Value::list(
+                    self.interfaces.iter().map(|i| Value::string(i, span)).collect(),
+                    span,
+                ),
+            ),
+            span,
+        )
+    }
+}
+
+pub struct Dex {
+    pub version: String,
+    pub string_count: u32,
+    pub type_count: u32,
+    pub field_count: u32,
+    /// Method references, the count the 64K limit applies to.
+    pub method_count: u32,
+    pub class_count: u32,
+}
+
+impl Dex {
+    pub fn parse(buffer: &[u8]) -> Result<Self, String> {
+        if !buffer.starts_with(DEX_MAGIC) {
+            return Err("Not a DEX file".to_string());
+        }
+        let version = buffer.get(4..7).ok_or("Truncated DEX header")?;
+        let count = |offset| u32_le(buffer, offset).ok_or("Truncated DEX header");
+        Ok(Dex {
+            version: String::from_utf8_lossy(version).into_owned(),
+            string_count: count(56)?,
+            type_count: count(64)?,
+            field_count: count(80)?,
+            method_count: count(88)?,
+            class_count: count(96)?,
+        })
+    }
+
+    pub fn description(&self) -> String {
+        format!("dalvik dex, version {}", self.version)
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let mut value = record!("format" => Value::string("dex", span));
+        self.push_values(&mut value, span);
+        Value::record(value, span)
+    }
+
+    fn push_values(&self, value: &mut Record, span: Span) {
+        value.push("version", Value::string(&self.version, span));
+        for (name, count) in [
+            ("class_count", self.class_count),
+            ("method_count", self.method_count),
+            ("field_count", self.field_count),
+            ("string_count", self.string_count),
+            ("type_count", self.type_count),
+        ] {
+            value.push(name, Value::int(count as _, span));
+        }
+    }
+}
+
+/// A JAR, or an APK when it carries DEX files instead of classes.
+#[derive(Default)]
+pub struct Package {
+    pub android: bool,
+    pub main_class: Option<String>,
+    pub created_by: Option<String>,
+    pub multi_release: bool,
+    pub class_count: usize,
+    /// Highest `(major, minor)` class file version outside `META-INF/versions`.
+    pub max_class_version: Option<(u16, u16)>,
+    pub dex: Vec<(String, Dex)>,
+}
+
+impl Package {
+    pub fn parse(buffer: &[u8], android: bool) -> Result<Self, String> {
+        let zip = ZipArchive::parse(buffer)?;
+        let mut package = Package {
+            android,
+            ..Default::default()
+        };
+        if let Some(manifest) = zip
+            .find("META-INF/MANIFEST.MF")
+            .and_then(|entry| zip.read(entry))
+        {
+            let attributes = manifest_attributes(&String::from_utf8_lossy(&manifest));
+            let attribute = |key: &str| {
+                attributes
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v.clone())
+            };
+            package.main_class = attribute("Main-Class");
+            package.created_by = attribute("Created-By");
+            package.multi_release =
+                attribute("Multi-Release").is_some_and(|v| v.eq_ignore_ascii_case("true"));
+        }
+        for entry in &zip.entries {
+            if entry.name.ends_with(".class") {
+                package.class_count += 1;
+                if entry.name.starts_with(VERSIONED_PREFIX) {
+                    continue;
+                }
+                let version = zip.read_prefix(entry, 8).as_deref().and_then(class_version);
+                package.max_class_version = package.max_class_version.max(version);
+            } else if entry.name.ends_with(".dex")
+                && !entry.name.contains('/')
+                && let Some(dex) = zip.read_prefix(entry, 112)
+                && let Ok(dex) = Dex::parse(&dex)
+            {
+                package.dex.push((entry.name.clone(), dex));
+            }
+        }
+        Ok(package)
+    }
+
+    pub fn description(&self) -> String {
+        match (self.android, self.max_class_version) {
+            (true, _) => format!("android package, with {} dex files", self.dex.len()),
+            (false, Some((major, _))) => {
+                format!("java archive, requires java {}", java_release(major))
+            }
+            (false, None) => "java archive".to_string(),
+        }
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let optional = |s: &Option<String>| {
+            s.as_ref()
+                .map_or(Value::nothing(span), |s| Value::string(s, span))
+        };
+        let mut value = record!(
+            "format" => Value::string(if self.android { "apk" } else { "jar" }, span),
+            "main_class" => optional(&self.main_class),
+            "created_by" => optional(&self.created_by),
+            "multi_release" => Value::bool(self.multi_release, span),
+            "class_count" => Value::int(self.class_count as _, span),
+            "max_class_version" => self.max_class_version.map_or(Value::nothing(span), |(major, minor)| {
+                Value::string(format!("{major}.{minor}"), span)
+            }),
+            "java_release" => self.max_class_version.map_or(Value::nothing(span), |(major, _)| {
+                Value::string(java_release(major), span)
+            }),
+        );
+        if self.android || !self.dex.is_empty() {
+            value.push(
+                "dex",
+                Value::list(
+                    self.dex
+                        .iter()
+                        .map(|(name, dex)| {
+                            let mut value = record!("name" => Value::string(name, span));
+                            dex.push_values(&mut value, span);
+                            Value::record(value, span)
+                        })
+                        .collect(),
+                    span,
+                ),
+            );
+        }
+        Value::record(value, span)
+    }
+}
+
+/// Main section attributes of a JAR manifest. Long values are wrapped onto
+/// continuation lines that start with a single space.
+fn manifest_attributes(manifest: &str) -> Vec<(String, String)> {
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    for line in manifest.lines() {
+        if line.is_empty() {
+            break;
+        }
+        if let Some(continuation) = line.strip_prefix(' ') {
+            if let Some((_, value)) = attributes.last_mut() {
+                value.push_str(continuation);
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            attributes.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod test {
+    use super::{java_release, manifest_attributes};
+
+    #[test]
+    fn releases() {
+        assert_eq!(java_release(45), "1.1");
+        assert_eq!(java_release(48), "1.4");
+        assert_eq!(java_release(52), "8");
+        assert_eq!(java_release(65), "21");
+    }
+
+    #[test]
+    fn manifest_continuation_lines() {
+        let manifest = "Manifest-Version: 1.0\r\nMain-Class: com.example.a.very.long.pack\r\n age.Main\r\n\r\nName: x\r\n";
+        let attributes = manifest_attributes(manifest);
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(attributes[1].1, "com.example.a.very.long.package.Main");
+    }
+}
@@ -0,0 +1,336 @@
+//! Resolve ELF `DT_NEEDED` entries the way `ld.so` would, without running
+//! anything. Search order follows glibc: `DT_RPATH` (when there is no
+//! `DT_RUNPATH`), `LD_LIBRARY_PATH`, `DT_RUNPATH`, `/etc/ld.so.cache` and
+//! finally the default library directories.
+use crate::bytes::{array, cstr, u32_le};
+use goblin::elf::Elf;
+use nu_protocol::{Span, Value, record};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+const LD_SO_CACHE: &str = "/etc/ld.so.cache";
+const CACHE_MAGIC_OLD: &[u8] = b"ld.so-1.7.0";
+const CACHE_MAGIC_NEW: &[u8] = b"glibc-ld.so.cache1.1";
+
+pub struct Dependency {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    /// Where the library was found: rpath, ld_library_path, runpath, cache or default.
+    pub source: Option<&'static str>,
+    pub dependencies: Vec<Dependency>,
+}
+
+impl Dependency {
+    pub fn into_value(&self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "name" => Value::string(&self.name, span),
+                "path" => self
+                    .path
+                    .as_ref()
+                    .map_or(Value::nothing(span), |p| Value::string(p.to_string_lossy(), span)),
+                "found" => Value::bool(self.path.is_some(), span),
+                "source" => self
+                    .source
+                    .map_or(Value::nothing(span), |s| Value::string(s, span)),
+                "dependencies" => Value::list(
+                    self.dependencies.iter().map(|d| d.into_value(span)).collect(),
+                    span,
+                ),
+            ),
+            span,
+        )
+    }
+}
+
+/// ELF identity the loader checks before accepting a candidate library.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct ElfIdent {
+    class: u8,
+    data: u8,
+    machine: u16,
+}
+
+impl ElfIdent {
+    fn read(path: &Path) -> Option<Self> {
+        use std::io::Read;
+        let mut header = [0u8; 20];
+        std::fs::File::open(path)
+            .ok()?
+            .read_exact(&mut header)
+            .ok()?;
+        if header[0..4] != *goblin::elf::header::ELFMAG {
+            return None;
+        }
+        let machine = array::<2>(&header, 18)?;
+        Some(ElfIdent {
+            class: header[4],
+            data: header[5],
+            machine: if header[5] == goblin::elf::header::ELFDATA2MSB {
+                u16::from_be_bytes(machine)
+            } else {
+                u16::from_le_bytes(machine)
+            },
+        })
+    }
+}
+
+pub struct Resolver {
+    ld_library_path: Vec<String>,
+    cache: Vec<(String, String)>,
+    visited: HashSet<PathBuf>,
+}
+
+impl Resolver {
+    pub fn new(ld_library_path: Vec<String>) -> Self {
+        Resolver {
+            ld_library_path,
+            cache: read_ld_so_cache(Path::new(LD_SO_CACHE)).unwrap_or_default(),
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Resolve the dependencies of the ELF at `path`, recursively.
+    pub fn resolve(&mut self, path: &Path) -> Result<Vec<Dependency>, String> {
+        let ident = ElfIdent::read(path).ok_or_else(|| "Not an ELF file".to_string())?;
+        self.visited
+            .insert(path.canonicalize().unwrap_or(path.to_path_buf()));
+        self.resolve_object(path, ident, &[])
+    }
+
+    /// `inherited_rpaths` are the DT_RPATH entries of the objects that loaded
+    /// this one, which glibc searches after the object's own (DT_RUNPATH is
+    /// not inherited).
+    fn resolve_object(
+        &mut self,
+        path: &Path,
+        ident: ElfIdent,
+        inherited_rpaths: &[String],
+    ) -> Result<Vec<Dependency>, String> {
+        let buffer = std::fs::read(path).map_err(|e| e.to_string())?;
+        let elf = Elf::parse(&buffer).map_err(|e| e.to_string())?;
+        let origin = path.parent().unwrap_or(Path::new("/"));
+        let expand = |entries: &[&str]| {
+            entries
+                .iter()
+                .flat_map(|e| e.split(':'))
+                .filter(|e| !e.is_empty())
+                .map(|e| expand_origin(e, origin, elf.is_64))
+                .collect::<Vec<_>>()
+        };
+        let runpaths = expand(&elf.runpaths);
+        let (rpaths, chain) = rpath_search(expand(&elf.rpaths), &runpaths, inherited_rpaths);
+        let needed = elf
+            .libraries
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        let mut dependencies = Vec::with_capacity(needed.len());
+        for name in needed {
+            let found = self.find(&name, ident, &rpaths, &runpaths, elf.is_64);
+            let mut dependency = Dependency {
+                name,
+                path: found.as_ref().map(|(path, _)| path.clone()),
+                source: found.as_ref().map(|(_, source)| *source),
+                dependencies: Vec::new(),
+            };
+            // each library is only expanded the first time it is seen
+            if let Some(path) = dependency.path.clone()
+                && self
+                    .visited
+                    .insert(path.canonicalize().unwrap_or(path.clone()))
+            {
+                dependency.dependencies = self
+                    .resolve_object(&path, ident, &chain)
+                    .unwrap_or_default();
+            }
+            dependencies.push(dependency);
+        }
+        Ok(dependencies)
+    }
+
+    fn find(
+        &self,
+        name: &str,
+        ident: ElfIdent,
+        rpaths: &[String],
+        runpaths: &[String],
+        is_64: bool,
+    ) -> Option<(PathBuf, &'static str)> {
+        let accept = |path: PathBuf| (ElfIdent::read(&path) == Some(ident)).then_some(path);
+        if name.contains('/') {
+            return accept(PathBuf::from(name)).map(|p| (p, "direct"));
+        }
+        let search = |dirs: &[String], source: &'static str| {
+            dirs.iter()
+                .find_map(|dir| accept(Path::new(dir).join(name)))
+                .map(|p| (p, source))
+        };
+        let defaults: &[&str] = if is_64 {
+            &["/lib64", "/usr/lib64", "/lib", "/usr/lib"]
+        } else {
+            &["/lib32", "/usr/lib32", "/lib", "/usr/lib"]
+        };
+        search(rpaths, "rpath")
+            .or_else(|| search(&self.ld_library_path, "ld_library_path"))
+            .or_else(|| search(runpaths, "runpath"))
+            .or_else(|| {
+                self.cache
+                    .iter()
+                    .filter(|(key, _)| key == name)
+                    .find_map(|(_, value)| accept(PathBuf::from(value)))
+                    .map(|p| (p, "cache"))
+            })
+            .or_else(|| {
+                defaults
+                    .iter()
+                    .find_map(|dir| accept(Path::new(dir).join(name)))
+                    .map(|p| (p, "default"))
+            })
+    }
+}
+
+/// The DT_RPATH directories searched for an object's own dependencies, and
+/// those its dependencies inherit. glibc drops an object's DT_RPATH when it
+/// has a DT_RUNPATH, and then searches no DT_RPATH at all, inherited or not.
+fn rpath_search(
+    rpaths: Vec<String>,
+    runpaths: &[String],
+    inherited: &[String],
+) -> (Vec<String>, Vec<String>) {
+    let own = if runpaths.is_empty() {
+        rpaths
+    } else {
+        Vec::new()
+    };
+    let chain: Vec<String> = own.into_iter().chain(inherited.iter().cloned()).collect();
+    let search = if runpaths.is_empty() {
+        chain.clone()
+    } else {
+        Vec::new()
+    };
+    (search, chain)
+}
+
+fn expand_origin(entry: &str, origin: &Path, is_64: bool) -> String {
+    let origin = origin.to_string_lossy();
+    let lib = if is_64 { "lib64" } else { "lib" };
+    entry
+        .replace("${ORIGIN}", &origin)
+        .replace("$ORIGIN", &origin)
+        .replace("${LIB}", lib)
+        .replace("$LIB", lib)
+}
+
+/// Read the `(soname, path)` pairs out of `ld.so.cache`, which comes in the
+/// old libc5 format, the new glibc format, or the old format with the new
+/// one appended.
+fn read_ld_so_cache(path: &Path) -> Option<Vec<(String, String)>> {
+    let data = std::fs::read(path).ok()?;
+    let mut start = 0;
+    if data.starts_with(CACHE_MAGIC_OLD) {
+        let count = u32_le(&data, 12)? as usize;
+        let old_end = 16 + count * 12;
+        // the new format follows, aligned to 8 bytes
+        start = (old_end + 7) & !7;
+        if !data.get(start..)?.starts_with(CACHE_MAGIC_NEW) {
+            // old format only, string offsets are relative to the string table
+            return (0..count)
+                .map(|i| {
+                    let entry = 16 + i * 12;
+                    Some((
+                        cstr(&data, old_end + u32_le(&data, entry + 4)? as usize)?,
+                        cstr(&data, old_end + u32_le(&data, entry + 8)? as usize)?,
+                    ))
+                })
+                .collect();
+        }
+    }
+    let new = data.get(start..)?;
+    if !new.starts_with(CACHE_MAGIC_NEW) {
+        return None;
+    }
+    let count = u32_le(new, 20)? as usize;
+    // header is 48 bytes, entries are 24, string offsets are relative to the header
+    (0..count)
+        .map(|i| {
+            let entry = 48 + i * 24;
+            Some((
+                cstr(new, u32_le(new, entry + 4)? as usize)?,
+                cstr(new, u32_le(new, entry + 8)? as usize)?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const IDENT: ElfIdent = ElfIdent {
+        class: 2,
+        data: 1,
+        machine: 62,
+    };
+
+    fn strings(dirs: &[&str]) -> Vec<String> {
+        dirs.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn rpath_inheritance() {
+        let inherited = strings(&["/app/lib"]);
+        let (search, chain) = rpath_search(strings(&["/opt/lib"]), &[], &inherited);
+        assert_eq!(search, ["/opt/lib", "/app/lib"]);
+        assert_eq!(chain, ["/opt/lib", "/app/lib"]);
+
+        // a DT_RUNPATH hides every DT_RPATH, but the loaders' still reach
+        // the object's own dependencies
+        let runpaths = strings(&["/usr/local/lib"]);
+        let (search, chain) = rpath_search(strings(&["/opt/lib"]), &runpaths, &inherited);
+        assert!(search.is_empty());
+        assert_eq!(chain, ["/app/lib"]);
+    }
+
+    #[test]
+    fn search_order() {
+        let root = std::env::temp_dir().join(format!("ldso-search-{}", std::process::id()));
+        let dir = |name: &str| {
+            let dir = root.join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            let mut header = b"\x7fELF\x02\x01\x01".to_vec();
+            header.resize(18, 0);
+            header.extend_from_slice(&62u16.to_le_bytes());
+            std::fs::write(dir.join("libfoo.so"), header).unwrap();
+            dir.to_string_lossy().into_owned()
+        };
+        let (rpath, ld_library_path, runpath) = (dir("rpath"), dir("env"), dir("runpath"));
+        let mut resolver = Resolver {
+            ld_library_path: vec![ld_library_path],
+            cache: Vec::new(),
+            visited: HashSet::new(),
+        };
+        let source = |resolver: &Resolver, rpaths: &[String]| {
+            resolver
+                .find(
+                    "libfoo.so",
+                    IDENT,
+                    rpaths,
+                    std::slice::from_ref(&runpath),
+                    true,
+                )
+                .map(|(_, source)| source)
+        };
+        assert_eq!(
+            source(&resolver, std::slice::from_ref(&rpath)),
+            Some("rpath")
+        );
+        assert_eq!(source(&resolver, &[]), Some("ld_library_path"));
+        resolver.ld_library_path.clear();
+        assert_eq!(source(&resolver, &[]), Some("runpath"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
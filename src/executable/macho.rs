@@ -0,0 +1,415 @@
+//! Load command and code signature details for Mach-O images.
+use crate::bytes::{cstr, hex, u32_be};
+use goblin::mach::{MachO, load_command::CommandVariant};
+use nu_protocol::{Record, Span, Value, record};
+
+const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xFADE_0CC0;
+const CSMAGIC_CODEDIRECTORY: u32 = 0xFADE_0C02;
+const CSMAGIC_EMBEDDED_ENTITLEMENTS: u32 = 0xFADE_7171;
+const CSMAGIC_BLOBWRAPPER: u32 = 0xFADE_0B01;
+
+const CSSLOT_CODEDIRECTORY: u32 = 0;
+const CSSLOT_ENTITLEMENTS: u32 = 5;
+const CSSLOT_SIGNATURESLOT: u32 = 0x10000;
+
+const CS_ADHOC: u32 = 0x0000_0002;
+const CS_RUNTIME: u32 = 0x0001_0000;
+
+#[derive(Default)]
+pub struct MachDetails {
+    pub uuid: Option<String>,
+    pub platform: Option<String>,
+    pub min_os: Option<String>,
+    pub sdk: Option<String>,
+    pub entry_offset: Option<u64>,
+    pub encrypted: Option<bool>,
+    pub code_signature: Option<CodeSignature>,
+}
+
+#[derive(Default)]
+pub struct CodeSignature {
+    pub identifier: Option<String>,
+    pub team_id: Option<String>,
+    pub adhoc: bool,
+    pub hardened_runtime: bool,
+    pub has_cms_signature: bool,
+    pub entitlements: Option<String>,
+}
+
+fn platform_name(platform: u32) -> String {
+    match platform {
+        1 => "macos",
+        2 => "ios",
+        3 => "tvos",
+        4 => "watchos",
+        5 => "bridgeos",
+        6 => "maccatalyst",
+        7 => "ios-simulator",
+        8 => "tvos-simulator",
+        9 => "watchos-simulator",
+        10 => "driverkit",
+        11 => "visionos",
+        12 => "visionos-simulator",
+        other => return format!("unknown ({other})"),
+    }
+    .to_string()
+}
+
+/// Versions are packed as `xxxx.yy.zz`: a 16 bit major, then a byte each
+/// for the minor and patch numbers.
+fn version_string(version: u32) -> String {
+    let patch = version & 0xFF;
+    let minor = (version >> 8) & 0xFF;
+    let major = version >> 16;
+    if patch == 0 {
+        format!("{major}.{minor}")
+    } else {
+        format!("{major}.{minor}.{patch}")
+    }
+}
+
+impl MachDetails {
+    /// `data` is the slice the Mach-O image was parsed from, so that it
+    /// also works for a single arch out of a fat binary.
+    pub fn parse(prg: &MachO, data: &[u8]) -> Self {
+        let mut details = MachDetails::default();
+        for command in &prg.load_commands {
+            match &command.command {
+                CommandVariant::Uuid(uuid) => details.uuid = Some(format_uuid(&uuid.uuid)),
+                CommandVariant::BuildVersion(build) => {
+                    details.platform = Some(platform_name(build.platform));
+                    details.min_os = Some(version_string(build.minos));
+                    details.sdk = Some(version_string(build.sdk));
+                }
+                // LC_BUILD_VERSION wins when both are present
+                CommandVariant::VersionMinMacosx(min)
+                | CommandVariant::VersionMinIphoneos(min)
+                | CommandVariant::VersionMinTvos(min)
+                | CommandVariant::VersionMinWatchos(min)
+                    if details.platform.is_none() =>
+                {
+                    details.platform = Some(
+                        match command.command {
+                            CommandVariant::VersionMinMacosx(_) => "macos",
+                            CommandVariant::VersionMinIphoneos(_) => "ios",
+                            CommandVariant::VersionMinTvos(_) => "tvos",
+                            _ => "watchos",
+                        }
+                        .to_string(),
+                    );
+                    details.min_os = Some(version_string(min.version));
+                    details.sdk = Some(version_string(min.sdk));
+                }
+                CommandVariant::Main(main) => details.entry_offset = Some(main.entryoff),
+                CommandVariant::EncryptionInfo32(info) => {
+                    details.encrypted = Some(info.cryptid != 0)
+                }
+                CommandVariant::EncryptionInfo64(info) => {
+                    details.encrypted = Some(info.cryptid != 0)
+                }
+                CommandVariant::CodeSignature(sig) => {
+                    let start = sig.dataoff as usize;
+                    details.code_signature = Some(
+                        data.get(start..start + sig.datasize as usize)
+                            .and_then(CodeSignature::parse)
+                            .unwrap_or_default(),
+                    );
+                }
+                _ => {}
+            }
+        }
+        details
+    }
+
+    pub fn push_values(&self, value: &mut Record, span: Span) {
+        for (name, field) in [
+            ("uuid", &self.uuid),
+            ("platform", &self.platform),
+            ("min_os", &self.min_os),
+            ("sdk", &self.sdk),
+        ] {
+            if let Some(field) = field {
+                value.push(name, Value::string(field, span));
+            }
+        }
+        if let Some(entry_offset) = self.entry_offset {
+            value.push("entry_offset", Value::int(entry_offset as _, span));
+        }
+        if let Some(encrypted) = self.encrypted {
+            value.push("encrypted", Value::bool(encrypted, span));
+        }
+        value.push(
+            "code_signature",
+            self.code_signature
+                .as_ref()
+                .map_or(Value::nothing(span), |c| c.into_value(span)),
+        );
+    }
+}
+
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex = hex(uuid).to_uppercase();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+impl CodeSignature {
+    /// Parse the embedded signature superblob, which is always big endian.
+    fn parse(blob: &[u8]) -> Option<Self> {
+        if u32_be(blob, 0)? != CSMAGIC_EMBEDDED_SIGNATURE {
+            return None;
+        }
+        let mut signature = CodeSignature::default();
+        let count = u32_be(blob, 8)? as usize;
+        for index in 0..count {
+            let slot = u32_be(blob, 12 + index * 8)?;
+            let offset = u32_be(blob, 16 + index * 8)? as usize;
+            let magic = u32_be(blob, offset)?;
+            let length = u32_be(blob, offset + 4)? as usize;
+            let Some(data) = blob.get(offset..offset + length) else {
+                continue;
+            };
+            match (slot, magic) {
+                (CSSLOT_CODEDIRECTORY, CSMAGIC_CODEDIRECTORY) => {
+                    signature.parse_code_directory(data);
+                }
+                (CSSLOT_ENTITLEMENTS, CSMAGIC_EMBEDDED_ENTITLEMENTS) => {
+                    signature.entitlements = data
+                        .get(8..)
+                        .map(|x| String::from_utf8_lossy(x).into_owned());
+                }
+                (CSSLOT_SIGNATURESLOT, CSMAGIC_BLOBWRAPPER) => {
+                    // ad-hoc signatures carry an empty CMS wrapper
+                    signature.has_cms_signature = length > 8;
+                }
+                _ => {}
+            }
+        }
+        Some(signature)
+    }
+
+    fn parse_code_directory(&mut self, cd: &[u8]) -> Option<()> {
+        let version = u32_be(cd, 8)?;
+        let flags = u32_be(cd, 12)?;
+        self.adhoc = flags & CS_ADHOC != 0;
+        self.hardened_runtime = flags & CS_RUNTIME != 0;
+        self.identifier = cstr(cd, u32_be(cd, 20)? as usize);
+        if version >= 0x20200 {
+            let team_offset = u32_be(cd, 48)? as usize;
+            if team_offset != 0 {
+                self.team_id = cstr(cd, team_offset);
+            }
+        }
+        Some(())
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let optional = |s: &Option<String>| {
+            s.as_ref()
+                .map_or(Value::nothing(span), |s| Value::string(s, span))
+        };
+        Value::record(
+            record!(
+                "identifier" => optional(&self.identifier),
+                "team_id" => optional(&self.team_id),
+                "adhoc" => Value::bool(self.adhoc, span),
+                "hardened_runtime" => Value::bool(self.hardened_runtime, span),
+                "cms_signature" => Value::bool(self.has_cms_signature, span),
+                "entitlements" => self
+                    .entitlements
+                    .as_deref()
+                    .map_or(Value::nothing(span), |e| plist_to_value(e, span)),
+            ),
+            span,
+        )
+    }
+}
+
+/// How deep arrays and dicts may nest in an entitlements plist.
+const MAX_PLIST_DEPTH: usize = 32;
+
+/// Convert an XML property list into a nushell value. Entitlements only use
+/// dicts, arrays, strings, integers and booleans, so that's all we handle;
+/// anything else, or nesting deeper than `MAX_PLIST_DEPTH`, falls back to
+/// the raw XML.
+fn plist_to_value(xml: &str, span: Span) -> Value {
+    let mut tokens = PlistTokens { rest: xml };
+    // skip the prolog and <plist> wrapper
+    while let Some(token) = tokens.next() {
+        if token == "dict" {
+            if let Some(value) = parse_plist_dict(&mut tokens, 0, span) {
+                return value;
+            }
+            break;
+        }
+    }
+    Value::string(xml, span)
+}
+
+fn parse_plist_value(
+    tag: &str,
+    tokens: &mut PlistTokens,
+    depth: usize,
+    span: Span,
+) -> Option<Value> {
+    Some(match tag {
+        "true/" => Value::bool(true, span),
+        "false/" => Value::bool(false, span),
+        "string" | "integer" | "real" | "date" => {
+            let text = tokens.text()?;
+            match tag {
+                "integer" => Value::int(text.trim().parse().ok()?, span),
+                _ => Value::string(text, span),
+            }
+        }
+        "string/" => Value::string("", span),
+        "array" if depth < MAX_PLIST_DEPTH => {
+            let mut items = Vec::new();
+            loop {
+                match tokens.next()?.as_str() {
+                    "/array" => break,
+                    tag => items.push(parse_plist_value(tag, tokens, depth + 1, span)?),
+                }
+            }
+            Value::list(items, span)
+        }
+        "array/" => Value::list(vec![], span),
+        "dict" if depth < MAX_PLIST_DEPTH => parse_plist_dict(tokens, depth + 1, span)?,
+        "dict/" => Value::record(Record::new(), span),
+        _ => return None,
+    })
+}
+
+fn parse_plist_dict(tokens: &mut PlistTokens, depth: usize, span: Span) -> Option<Value> {
+    let mut record = Record::new();
+    loop {
+        match tokens.next()?.as_str() {
+            "/dict" => break,
+            "key" => {
+                let key = tokens.text()?;
+                let tag = tokens.next()?;
+                record.push(key, parse_plist_value(&tag, tokens, depth, span)?);
+            }
+            _ => return None,
+        }
+    }
+    Some(Value::record(record, span))
+}
+
+struct PlistTokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> PlistTokens<'a> {
+    /// The next tag name without angle brackets or attributes; self closing
+    /// tags keep their trailing `/`.
+    fn next(&mut self) -> Option<String> {
+        loop {
+            let start = self.rest.find('<')?;
+            let end = start + self.rest[start..].find('>')?;
+            let tag = &self.rest[start + 1..end];
+            self.rest = &self.rest[end + 1..];
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+            let name = tag.trim_end_matches('/').split_whitespace().next()?;
+            return Some(if tag.ends_with('/') {
+                format!("{name}/")
+            } else {
+                name.to_string()
+            });
+        }
+    }
+
+    /// Text content up to the closing tag, which is consumed.
+    fn text(&mut self) -> Option<String> {
+        let end = self.rest.find('<')?;
+        let text = &self.rest[..end];
+        self.next()?;
+        Some(
+            text.replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!(version_string(0x000E_0200), "14.2");
+        assert_eq!(version_string(0x000D_0501), "13.5.1");
+        assert_eq!(version_string(0x0100_0000), "256.0");
+    }
+
+    #[test]
+    fn code_signature() {
+        let be = |blob: &mut Vec<u8>, n: u32| blob.extend_from_slice(&n.to_be_bytes());
+        let mut directory = Vec::new();
+        be(&mut directory, CSMAGIC_CODEDIRECTORY);
+        be(&mut directory, 0);
+        be(&mut directory, 0x20200);
+        be(&mut directory, CS_ADHOC | CS_RUNTIME);
+        be(&mut directory, 0);
+        be(&mut directory, 52);
+        directory.resize(48, 0);
+        be(&mut directory, 52 + 17);
+        directory.extend_from_slice(b"com.example.tool\0TEAM123456\0");
+        let entitlements =
+            b"<plist><dict><key>com.apple.security.app-sandbox</key><true/></dict></plist>";
+
+        let mut blob = Vec::new();
+        be(&mut blob, CSMAGIC_EMBEDDED_SIGNATURE);
+        be(&mut blob, 0);
+        be(&mut blob, 2);
+        be(&mut blob, CSSLOT_CODEDIRECTORY);
+        be(&mut blob, 28);
+        be(&mut blob, CSSLOT_ENTITLEMENTS);
+        be(&mut blob, 28 + directory.len() as u32);
+        let length = directory.len() as u32;
+        directory[4..8].copy_from_slice(&length.to_be_bytes());
+        blob.extend_from_slice(&directory);
+        be(&mut blob, CSMAGIC_EMBEDDED_ENTITLEMENTS);
+        be(&mut blob, 8 + entitlements.len() as u32);
+        blob.extend_from_slice(entitlements);
+
+        let signature = CodeSignature::parse(&blob).unwrap();
+        assert_eq!(signature.identifier.as_deref(), Some("com.example.tool"));
+        assert_eq!(signature.team_id.as_deref(), Some("TEAM123456"));
+        assert!(signature.adhoc && signature.hardened_runtime);
+        assert!(!signature.has_cms_signature);
+        let entitlements = plist_to_value(&signature.entitlements.unwrap(), Span::test_data());
+        assert_eq!(
+            entitlements.get_data_by_key("com.apple.security.app-sandbox"),
+            Some(Value::test_bool(true))
+        );
+    }
+
+    #[test]
+    fn nested_plists() {
+        let span = Span::test_data();
+        let nested = |depth: usize| {
+            format!(
+                "<plist><dict><key>a</key>{}<true/>{}</dict></plist>",
+                "<array>".repeat(depth),
+                "</array>".repeat(depth)
+            )
+        };
+        let shallow = plist_to_value(&nested(2), span);
+        assert!(shallow.as_record().is_ok());
+        // too deep to recurse into, so the XML is kept as is
+        let deep = nested(100_000);
+        assert_eq!(plist_to_value(&deep, span), Value::string(deep, span));
+    }
+}
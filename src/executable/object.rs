@@ -0,0 +1,251 @@
+//! Relocatable objects and the `ar` archives static libraries are made of.
+use crate::bytes::u16_le;
+use goblin::{
+    Object,
+    archive::Archive,
+    elf::{Elf, header::ET_REL, section_header::SHN_UNDEF, sym},
+    mach::{Mach, MachO, header::MH_OBJECT},
+    pe::{
+        Coff,
+        symbol::{IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_CLASS_WEAK_EXTERNAL, IMAGE_SYM_UNDEFINED},
+    },
+};
+use nu_protocol::{Record, Span, Value, record};
+
+pub const AR_MAGIC: &[u8] = b"!<arch>\n";
+const LLVM_BITCODE_MAGIC: &[u8] = b"BC\xC0\xDE";
+
+/// Global symbols of a relocatable object, which is what the linker cares about.
+#[derive(Default)]
+pub struct Symbols {
+    pub defined: Vec<String>,
+    pub undefined: Vec<String>,
+}
+
+impl Symbols {
+    /// Only relocatable objects get their symbols listed, linked images are
+    /// better described by their imports.
+    pub fn from_elf(elf: &Elf) -> Option<Self> {
+        if elf.header.e_type != ET_REL {
+            return None;
+        }
+        let mut symbols = Symbols::default();
+        for symbol in elf.syms.iter() {
+            if symbol.st_bind() == sym::STB_LOCAL
+                || matches!(symbol.st_type(), sym::STT_SECTION | sym::STT_FILE)
+            {
+                continue;
+            }
+            let Some(name) = elf.strtab.get_at(symbol.st_name).filter(|n| !n.is_empty()) else {
+                continue;
+            };
+            symbols.push(name, symbol.st_shndx == SHN_UNDEF as usize);
+        }
+        Some(symbols)
+    }
+
+    pub fn from_macho(macho: &MachO) -> Option<Self> {
+        if macho.header.filetype != MH_OBJECT {
+            return None;
+        }
+        let mut symbols = Symbols::default();
+        for (name, nlist) in macho.symbols().flatten() {
+            if nlist.is_stab() || !nlist.is_global() || name.is_empty() {
+                continue;
+            }
+            symbols.push(name, nlist.is_undefined());
+        }
+        Some(symbols)
+    }
+
+    pub fn from_coff(coff: &Coff) -> Self {
+        let mut symbols = Symbols::default();
+        let Some(table) = &coff.symbols else {
+            return symbols;
+        };
+        for (_, inline_name, symbol) in table.iter() {
+            if !matches!(
+                symbol.storage_class,
+                IMAGE_SYM_CLASS_EXTERNAL | IMAGE_SYM_CLASS_WEAK_EXTERNAL
+            ) {
+                continue;
+            }
+            let name = match (inline_name, &coff.strings) {
+                (Some(name), _) => name,
+                (None, Some(strings)) => match symbol.name(strings) {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                },
+                (None, None) => continue,
+            };
+            // undefined externals with a value are common symbols, which
+            // the linker allocates
+            let undefined = symbol.section_number == IMAGE_SYM_UNDEFINED
+                && symbol.value == 0
+                && symbol.storage_class == IMAGE_SYM_CLASS_EXTERNAL;
+            symbols.push(name, undefined);
+        }
+        symbols
+    }
+
+    fn push(&mut self, name: &str, undefined: bool) {
+        if undefined {
+            self.undefined.push(name.to_string());
+        } else {
+            self.defined.push(name.to_string());
+        }
+    }
+
+    pub fn push_values(&self, value: &mut Record, span: Span) {
+        let list = |names: &[String]| {
+            Value::list(names.iter().map(|n| Value::string(n, span)).collect(), span)
+        };
+        value.push("defined_symbols", list(&self.defined));
+        value.push("undefined_symbols", list(&self.undefined));
+    }
+}
+
+pub struct Member {
+    pub name: String,
+    pub offset: u64,
+    pub size: usize,
+    pub format: Option<&'static str>,
+    pub arch: Option<String>,
+}
+
+pub struct StaticLibrary {
+    pub members: Vec<Member>,
+    /// `(symbol, member)` pairs from the archive symbol index.
+    pub symbol_index: Vec<(String, String)>,
+}
+
+impl StaticLibrary {
+    pub fn parse(buffer: &[u8]) -> Result<Self, String> {
+        let archive = Archive::parse(buffer).map_err(|e| e.to_string())?;
+        let mut members = Vec::with_capacity(archive.len());
+        let mut symbol_index = Vec::new();
+        for (name, member, symbols) in archive.summarize() {
+            let start = member.offset as usize;
+            let (format, arch) = buffer
+                .get(start..start + member.size())
+                .map_or((None, None), member_format);
+            members.push(Member {
+                name: name.to_string(),
+                offset: member.offset,
+                size: member.size(),
+                format,
+                arch,
+            });
+            symbol_index.extend(
+                symbols
+                    .into_iter()
+                    .map(|symbol| (symbol.to_string(), name.to_string())),
+            );
+        }
+        symbol_index.sort();
+        Ok(StaticLibrary {
+            members,
+            symbol_index,
+        })
+    }
+
+    pub fn description(&self) -> String {
+        let mut formats = self
+            .members
+            .iter()
+            .filter_map(|m| m.format.map(|f| (f, m.arch.as_deref().unwrap_or_default())))
+            .collect::<Vec<_>>();
+        formats.sort();
+        formats.dedup();
+        let formats = formats
+            .iter()
+            .map(|(format, arch)| format!("{format} {arch}"))
+            .collect::<Vec<_>>();
+        if formats.is_empty() {
+            format!("ar archive, with {} members", self.members.len())
+        } else {
+            format!(
+                "static library, with {} members: [{}]",
+                self.members.len(),
+                formats.join(", ")
+            )
+        }
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let optional = |s: Option<&str>| s.map_or(Value::nothing(span), |s| Value::string(s, span));
+        Value::record(
+            record!(
+                "format" => Value::string("ar", span),
+                "members" => Value::list(
+                    self.members
+                        .iter()
+                        .map(|m| {
+                            Value::record(
+                                record!(
+                                    "name" => Value::string(&m.name, span),
+                                    "offset" => Value::int(m.offset as _, span),
+                                    "size" => Value::int(m.size as _, span),
+                                    "format" => optional(m.format),
+                                    "arch" => optional(m.arch.as_deref()),
+                                ),
+                                span,
+                            )
+                        })
+                        .collect(),
+                    span,
+                ),
+                "symbol_index" => Value::list(
+                    self.symbol_index
+                        .iter()
+                        .map(|(symbol, member)| {
+                            Value::record(
+                                record!(
+                                    "symbol" => Value::string(symbol, span),
+                                    "member" => Value::string(member, span),
+                                ),
+                                span,
+                            )
+                        })
+                        .collect(),
+                    span,
+                ),
+            ),
+            span,
+        )
+    }
+}
+
+/// Format and architecture of an archive member.
+fn member_format(data: &[u8]) -> (Option<&'static str>, Option<String>) {
+    // short import objects in Windows import libraries
+    if u16_le(data, 0) == Some(0) && u16_le(data, 2) == Some(0xFFFF) {
+        let machine = u16_le(data, 6).unwrap_or_default();
+        return (
+            Some("import"),
+            Some(goblin::pe::header::machine_to_str(machine).to_lowercase()),
+        );
+    }
+    if data.starts_with(LLVM_BITCODE_MAGIC) {
+        return (Some("llvm-bitcode"), None);
+    }
+    match Object::parse(data) {
+        Ok(Object::Elf(elf)) => (
+            Some(if elf.is_64 { "elf64" } else { "elf32" }),
+            Some(goblin::elf::header::machine_to_str(elf.header.e_machine).to_lowercase()),
+        ),
+        Ok(Object::Mach(Mach::Binary(macho))) => (
+            Some("mach-o"),
+            goblin::mach::cputype::get_arch_name_from_types(
+                macho.header.cputype,
+                macho.header.cpusubtype,
+            )
+            .map(|x| x.to_lowercase()),
+        ),
+        Ok(Object::COFF(coff)) => (
+            Some("coff"),
+            Some(goblin::pe::header::machine_to_str(coff.header.machine).to_lowercase()),
+        ),
+        _ => (None, None),
+    }
+}
@@ -0,0 +1,161 @@
+//! Entropy based heuristics for packed or obfuscated images. None of these
+//! prove anything on their own; they are meant for triage.
+use super::section::Section;
+use nu_protocol::{Span, Value, record};
+
+/// Compressed or encrypted data sits close to 8 bits per byte, code is
+/// usually between 5 and 6.5.
+const HIGH_ENTROPY: f64 = 7.2;
+/// Entropy of very small sections says little.
+const MIN_ENTROPY_SIZE: usize = 512;
+/// Packers only import what they need to unpack and resolve the rest.
+const TINY_IMPORT_TABLE: usize = 5;
+
+/// Section names left behind by well known packers and protectors.
+const PACKER_SECTIONS: &[(&str, &str)] = &[
+    ("upx0", "UPX"),
+    ("upx1", "UPX"),
+    ("upx2", "UPX"),
+    (".aspack", "ASPack"),
+    (".adata", "ASPack"),
+    (".themida", "Themida"),
+    (".winlice", "Themida"),
+    (".mpress1", "MPRESS"),
+    (".mpress2", "MPRESS"),
+    (".vmp0", "VMProtect"),
+    (".vmp1", "VMProtect"),
+    (".vmp2", "VMProtect"),
+    (".petite", "Petite"),
+    (".nsp0", "NsPack"),
+    (".nsp1", "NsPack"),
+    (".enigma1", "Enigma"),
+    (".enigma2", "Enigma"),
+];
+
+/// UPX writes this into the headers of everything it packs, sections or not.
+const UPX_MAGIC: &[u8] = b"UPX!";
+const UPX_HEADER_SEARCH: usize = 4096;
+
+/// Shannon entropy in bits per byte, 0.0 for empty input.
+pub fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+pub struct SectionEntropy {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+    pub entropy: f64,
+    pub executable: bool,
+    pub writable: bool,
+}
+
+#[derive(Default)]
+pub struct Packing {
+    pub sections: Vec<SectionEntropy>,
+    pub packer: Option<&'static str>,
+    /// Why the image looks packed, empty when it doesn't.
+    pub indicators: Vec<String>,
+}
+
+impl Packing {
+    /// `data` is the image `sections` were read from. `import_count` is the
+    /// number of imported functions for PE images, where a tiny import
+    /// table means something.
+    pub fn analyze(data: &[u8], sections: &[Section], import_count: Option<usize>) -> Self {
+        let mut packing = Packing::default();
+        for section in sections.iter().filter(|s| s.size > 0) {
+            let Some(contents) = section.data(data) else {
+                continue;
+            };
+            let entropy = entropy(contents);
+            let name = section.name.trim_end_matches('\0');
+            if section.executable && section.size >= MIN_ENTROPY_SIZE && entropy >= HIGH_ENTROPY {
+                packing.indicators.push(format!(
+                    "high entropy executable section {name} ({entropy:.2})"
+                ));
+            }
+            packing.sections.push(SectionEntropy {
+                name: name.to_string(),
+                offset: section.offset,
+                size: section.size,
+                entropy,
+                executable: section.executable,
+                writable: section.writable,
+            });
+        }
+        for section in sections {
+            let name = section.name.to_lowercase();
+            if let Some((_, packer)) = PACKER_SECTIONS.iter().find(|(n, _)| *n == name) {
+                packing.packer.get_or_insert(packer);
+                packing
+                    .indicators
+                    .push(format!("{packer} section {}", section.name));
+            }
+            if section.executable && section.writable {
+                packing
+                    .indicators
+                    .push(format!("writable and executable section {}", section.name));
+            }
+        }
+        let header = &data[..data.len().min(UPX_HEADER_SEARCH)];
+        if header.windows(UPX_MAGIC.len()).any(|w| w == UPX_MAGIC) {
+            packing.packer.get_or_insert("UPX");
+            packing.indicators.push("UPX header".to_string());
+        }
+        if let Some(count) = import_count
+            && count <= TINY_IMPORT_TABLE
+        {
+            packing
+                .indicators
+                .push(format!("tiny import table ({count} imports)"));
+        }
+        packing
+    }
+
+    pub fn is_packed(&self) -> bool {
+        !self.indicators.is_empty()
+    }
+
+    pub fn section_value(section: &SectionEntropy, span: Span) -> Value {
+        Value::record(
+            record!(
+                "name" => Value::string(&section.name, span),
+                "offset" => Value::int(section.offset as _, span),
+                "size" => Value::int(section.size as _, span),
+                "entropy" => Value::float(section.entropy, span),
+                "executable" => Value::bool(section.executable, span),
+                "writable" => Value::bool(section.writable, span),
+            ),
+            span,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::entropy;
+
+    #[test]
+    fn entropy_bounds() {
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(&[0x41; 1000]), 0.0);
+        let all = (0..=255u8).collect::<Vec<_>>();
+        assert!((entropy(&all) - 8.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,415 @@
+use super::dotnet::ClrDetails;
+use crate::der::{self, Certificate, Tlv};
+use goblin::pe::{
+    PE,
+    certificate_table::{AttributeCertificate, AttributeCertificateType},
+    resource::{VersionField, VersionInfo},
+};
+use nu_protocol::{Record, Span, Value, record};
+
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+const OID_TST_INFO: &str = "1.2.840.113549.1.9.16.1.4";
+
+pub struct PeDetails {
+    pub version_info: Option<PeVersionInfo>,
+    pub authenticode: Authenticode,
+    pub clr: Option<ClrDetails>,
+}
+
+pub struct PeVersionInfo {
+    pub file_version: Option<String>,
+    pub product_version: Option<String>,
+    pub product_name: Option<String>,
+    pub company_name: Option<String>,
+    pub file_description: Option<String>,
+    pub original_filename: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Authenticode {
+    pub signed: bool,
+    pub certificate_type: Option<&'static str>,
+    pub subject: Option<String>,
+    pub issuer: Option<String>,
+    pub signing_time: Option<String>,
+}
+
+impl PeDetails {
+    pub fn parse(prg: &PE, buffer: &[u8]) -> Self {
+        PeDetails {
+            version_info: prg
+                .resource_data
+                .as_ref()
+                .and_then(|r| r.version_info.as_ref())
+                .map(PeVersionInfo::from),
+            authenticode: prg
+                .certificates
+                .first()
+                .map(Authenticode::parse)
+                .unwrap_or_default(),
+            clr: ClrDetails::parse(prg, buffer),
+        }
+    }
+    pub fn push_values(&self, value: &mut Record, span: Span) {
+        if let Some(version_info) = &self.version_info {
+            value.push("version_info", version_info.into_value(span));
+        }
+        value.push("authenticode", self.authenticode.into_value(span));
+        if let Some(clr) = &self.clr {
+            value.push("clr", clr.into_value(span));
+        }
+    }
+}
+
+impl From<&VersionInfo<'_>> for PeVersionInfo {
+    fn from(info: &VersionInfo) -> Self {
+        let fixed = info.fixed_info.filter(|f| f.is_valid());
+        let strings = &info.string_info;
+        PeVersionInfo {
+            // goblin's `file_version` reads the file date fields instead
+            file_version: strings.file_version().or_else(|| {
+                fixed.map(|f| {
+                    VersionField::from_ms_ls(f.file_version_ms, f.file_version_ls).to_string()
+                })
+            }),
+            product_version: strings
+                .product_version()
+                .or_else(|| fixed.map(|f| f.product_version().to_string())),
+            product_name: strings.product_name(),
+            company_name: strings.company_name(),
+            file_description: strings.file_description(),
+            original_filename: strings.original_filename(),
+        }
+    }
+}
+
+impl PeVersionInfo {
+    pub fn into_value(&self, span: Span) -> Value {
+        let mut value = Record::new();
+        for (name, field) in [
+            ("file_version", &self.file_version),
+            ("product_version", &self.product_version),
+            ("product_name", &self.product_name),
+            ("company_name", &self.company_name),
+            ("file_description", &self.file_description),
+            ("original_filename", &self.original_filename),
+        ] {
+            if let Some(field) = field {
+                value.push(name, Value::string(field.trim_end_matches('\0'), span));
+            }
+        }
+        Value::record(value, span)
+    }
+}
+
+impl Authenticode {
+    fn parse(cert: &AttributeCertificate) -> Self {
+        let mut authenticode = Authenticode {
+            signed: true,
+            ..Default::default()
+        };
+        match cert.certificate_type {
+            AttributeCertificateType::X509 => {
+                authenticode.certificate_type = Some("x509");
+                if let Some(cert) = Tlv::parse(cert.certificate)
+                    .as_ref()
+                    .and_then(Certificate::parse)
+                {
+                    authenticode.subject = Some(cert.subject);
+                    authenticode.issuer = Some(cert.issuer);
+                }
+            }
+            AttributeCertificateType::PkcsSignedData => {
+                authenticode.certificate_type = Some("pkcs7");
+                authenticode.parse_signed_data(cert.certificate);
+            }
+            _ => authenticode.certificate_type = Some("unknown"),
+        }
+        authenticode
+    }
+
+    /// Pull the first signer out of a PKCS#7 `ContentInfo`.
+    fn parse_signed_data(&mut self, bytes: &[u8]) -> Option<()> {
+        let content_info = Tlv::parse(bytes)?;
+        if content_info.child(0)?.oid()? != OID_SIGNED_DATA {
+            return None;
+        }
+        let signed_data = content_info.child(1)?.child(0)?;
+        let certificates = signed_data
+            .children()
+            .find(|c| c.context() == Some(0))
+            .map(|c| c.children().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let signer = signed_data.children().last()?.child(0)?;
+        let mut fields = signer.children().skip(1);
+
+        // sid is IssuerAndSerialNumber for Authenticode
+        let sid = fields.next()?;
+        let issuer = sid.child(0)?;
+        let serial = sid.child(1)?.content;
+        self.issuer = Some(der::name_to_string(&issuer));
+        self.subject = certificates
+            .iter()
+            .filter_map(Certificate::parse)
+            .find(|c| c.serial == serial)
+            .map(|c| c.subject);
+
+        let attributes = fields
+            .filter(|f| matches!(f.context(), Some(0) | Some(1)))
+            .collect::<Vec<_>>();
+        self.signing_time = attributes
+            .iter()
+            .find_map(|attrs| signing_time(*attrs))
+            .or_else(|| attributes.iter().find_map(|attrs| timestamp_time(*attrs)));
+        Some(())
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let mut value = record!(
+            "signed" => Value::bool(self.signed, span),
+        );
+        for (name, field) in [
+            (
+                "certificate_type",
+                &self.certificate_type.map(str::to_string),
+            ),
+            ("subject", &self.subject),
+            ("issuer", &self.issuer),
+            ("signing_time", &self.signing_time),
+        ] {
+            if let Some(field) = field {
+                value.push(name, Value::string(field, span));
+            }
+        }
+        Value::record(value, span)
+    }
+}
+
+/// `signingTime` from the signer or a nested countersignature.
+fn signing_time(attrs: Tlv) -> Option<String> {
+    let attr = der::find(attrs, &|t| {
+        t.tag == der::SEQUENCE
+            && t.child(0).and_then(|o| o.oid()).as_deref() == Some(OID_SIGNING_TIME)
+    })?;
+    attr.child(1)?.child(0)?.time()
+}
+
+/// `genTime` of an RFC 3161 timestamp token.
+fn timestamp_time(attrs: Tlv) -> Option<String> {
+    let encap = der::find(attrs, &|t| {
+        t.tag == der::SEQUENCE && t.child(0).and_then(|o| o.oid()).as_deref() == Some(OID_TST_INFO)
+    })?;
+    let tst_info = Tlv::parse(encap.child(1)?.child(0)?.content)?;
+    tst_info
+        .children()
+        .find(|t| t.tag == der::GENERALIZED_TIME)?
+        .time()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tlv(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+        let content = parts.concat();
+        let mut out = vec![tag];
+        match content.len() {
+            len @ 0..0x80 => out.push(len as u8),
+            len @ 0x80..0x100 => out.extend([0x81, len as u8]),
+            len => out.extend([0x82, (len >> 8) as u8, len as u8]),
+        }
+        out.extend(content);
+        out
+    }
+
+    fn name(common_name: &str) -> Vec<u8> {
+        let attribute = tlv(
+            der::SEQUENCE,
+            &[
+                &tlv(der::OID, &[&[0x55, 0x04, 0x03]]),
+                &tlv(der::UTF8_STRING, &[common_name.as_bytes()]),
+            ],
+        );
+        tlv(der::SEQUENCE, &[&tlv(der::SET, &[&attribute])])
+    }
+
+    #[test]
+    fn signed_data() {
+        // OIDs under 1.2.840.113549.1
+        let pkcs = |tail: &[u8]| {
+            tlv(
+                der::OID,
+                &[&[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01], tail],
+            )
+        };
+        let algorithm = tlv(der::SEQUENCE, &[&pkcs(&[0x01, 0x01, 0x0B])]);
+        let serial = tlv(der::INTEGER, &[&[0x2A]]);
+        let time = tlv(der::UTC_TIME, &[b"240102030405Z"]);
+        let tbs = tlv(
+            der::SEQUENCE,
+            &[
+                &tlv(0xA0, &[&tlv(der::INTEGER, &[&[2]])]),
+                &serial,
+                &algorithm,
+                &name("Test CA"),
+                &tlv(der::SEQUENCE, &[&time, &time]),
+                &name("nu"),
+                &tlv(der::SEQUENCE, &[&algorithm, &tlv(der::BIT_STRING, &[&[0]])]),
+            ],
+        );
+        let certificate = tlv(
+            der::SEQUENCE,
+            &[&tbs, &algorithm, &tlv(der::BIT_STRING, &[&[0]])],
+        );
+        let signing_time = tlv(
+            der::SEQUENCE,
+            &[&pkcs(&[0x09, 0x05]), &tlv(der::SET, &[&time])],
+        );
+        let signer = tlv(
+            der::SEQUENCE,
+            &[
+                &tlv(der::INTEGER, &[&[1]]),
+                &tlv(der::SEQUENCE, &[&name("Test CA"), &serial]),
+                &algorithm,
+                &tlv(0xA0, &[&signing_time]),
+                &algorithm,
+                &tlv(der::OCTET_STRING, &[&[0]]),
+            ],
+        );
+        let signed_data = tlv(
+            der::SEQUENCE,
+            &[
+                &tlv(der::INTEGER, &[&[1]]),
+                &tlv(der::SET, &[&algorithm]),
+                &tlv(der::SEQUENCE, &[&pkcs(&[0x07, 0x01])]),
+                &tlv(0xA0, &[&certificate]),
+                &tlv(der::SET, &[&signer]),
+            ],
+        );
+        let content_info = tlv(
+            der::SEQUENCE,
+            &[&pkcs(&[0x07, 0x02]), &tlv(0xA0, &[&signed_data])],
+        );
+
+        let mut authenticode = Authenticode::default();
+        assert_eq!(authenticode.parse_signed_data(&content_info), Some(()));
+        assert_eq!(authenticode.subject.as_deref(), Some("CN=nu"));
+        assert_eq!(authenticode.issuer.as_deref(), Some("CN=Test CA"));
+        assert_eq!(
+            authenticode.signing_time.as_deref(),
+            Some("2024-01-02T03:04:05Z")
+        );
+    }
+
+    /// A `VS_VERSIONINFO` style node: header, key, value, then children.
+    fn version_node(key: &str, text: bool, value: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut node = vec![0; 6];
+        node.extend(key.encode_utf16().chain([0]).flat_map(u16::to_le_bytes));
+        node.resize(node.len().next_multiple_of(4), 0);
+        node.extend(value);
+        node.resize(node.len().next_multiple_of(4), 0);
+        node.extend(children);
+        let value_len = if text { value.len() / 2 } else { value.len() };
+        let len = node.len() as u16;
+        node[0..2].copy_from_slice(&len.to_le_bytes());
+        node[2..4].copy_from_slice(&(value_len as u16).to_le_bytes());
+        node[4..6].copy_from_slice(&(text as u16).to_le_bytes());
+        node
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    /// A PE32+ image whose only section holds an `RT_VERSION` resource.
+    fn pe_with_version(version: &[u8]) -> Vec<u8> {
+        const RVA: u32 = 0x1000;
+        let mut rsrc = Vec::new();
+        // type, name and language directories, one id entry each
+        for (id, next) in [(16u32, 0x8000_0018u32), (1, 0x8000_0030), (0x409, 0x48)] {
+            rsrc.extend([0; 12]);
+            rsrc.extend(1u32.to_le_bytes());
+            rsrc.extend(id.to_le_bytes());
+            rsrc.extend(next.to_le_bytes());
+        }
+        for field in [RVA + 0x58, version.len() as u32, 0, 0] {
+            rsrc.extend(field.to_le_bytes());
+        }
+        rsrc.extend(version);
+        let size = rsrc.len() as u32;
+        rsrc.resize(rsrc.len().next_multiple_of(0x200), 0);
+
+        let mut image = vec![0; 0x200];
+        image[0..2].copy_from_slice(b"MZ");
+        image[0x3C..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        let mut put = |offset: usize, bytes: &[u8]| {
+            image[offset..offset + bytes.len()].copy_from_slice(bytes)
+        };
+        put(0x40, b"PE\0\0");
+        // COFF header: x86-64, one section, a PE32+ optional header
+        put(0x44, &0x8664u16.to_le_bytes());
+        put(0x46, &1u16.to_le_bytes());
+        put(0x54, &0xF0u16.to_le_bytes());
+        put(0x56, &0x22u16.to_le_bytes());
+        let optional = 0x58;
+        put(optional, &0x20Bu16.to_le_bytes());
+        put(optional + 24, &0x1_4000_0000u64.to_le_bytes());
+        put(optional + 32, &0x1000u32.to_le_bytes());
+        put(optional + 36, &0x200u32.to_le_bytes());
+        put(optional + 56, &(RVA + 0x1000).to_le_bytes());
+        put(optional + 60, &0x200u32.to_le_bytes());
+        put(optional + 108, &16u32.to_le_bytes());
+        // the resource data directory
+        put(optional + 128, &RVA.to_le_bytes());
+        put(optional + 132, &size.to_le_bytes());
+        let section = optional + 0xF0;
+        put(section, b".rsrc");
+        put(section + 8, &size.to_le_bytes());
+        put(section + 12, &RVA.to_le_bytes());
+        put(section + 16, &(rsrc.len() as u32).to_le_bytes());
+        put(section + 20, &0x200u32.to_le_bytes());
+        put(section + 36, &0x4000_0040u32.to_le_bytes());
+        image.extend(rsrc);
+        image
+    }
+
+    #[test]
+    fn version_info() {
+        let mut fixed = Vec::new();
+        // signature, struct version, then file version 1.2.3.4 and product version 5.6.7.8
+        for field in [
+            0xFEEF_04BD,
+            0x1_0000,
+            0x1_0002,
+            0x3_0004,
+            0x5_0006,
+            0x7_0008,
+        ] {
+            fixed.extend(u32::to_le_bytes(field));
+        }
+        fixed.resize(52, 0);
+        let strings = [
+            version_node("CompanyName", true, &utf16("nushell"), &[]),
+            version_node("ProductName", true, &utf16("nu"), &[]),
+        ]
+        .concat();
+        let table = version_node("040904B0", true, &[], &strings);
+        let info = version_node("StringFileInfo", true, &[], &table);
+        let version = version_node("VS_VERSION_INFO", false, &fixed, &info);
+
+        let image = pe_with_version(&version);
+        let pe = PE::parse(&image).unwrap();
+        let details = PeDetails::parse(&pe, &image);
+        let version_info = details.version_info.unwrap();
+        assert_eq!(version_info.company_name.as_deref(), Some("nushell"));
+        assert_eq!(version_info.product_name.as_deref(), Some("nu"));
+        // no FileVersion string, so the fixed info fills it in
+        assert_eq!(version_info.file_version.as_deref(), Some("1.2.3.4"));
+        assert_eq!(version_info.product_version.as_deref(), Some("5.6.7.8"));
+        assert_eq!(version_info.file_description, None);
+    }
+}
@@ -0,0 +1,395 @@
+//! Build provenance embedded by the Go and Rust toolchains: Go's build info
+//! blob, the rustc version and `cargo auditable` dependency lists.
+use super::section::{self, Section};
+use crate::bytes::{u32_be, u32_le, u64_be, u64_le};
+use flate2::read::ZlibDecoder;
+use nu_protocol::{Record, Span, Value, record};
+use std::io::Read;
+
+const GO_BUILDINFO_MAGIC: &[u8] = b"\xff Go buildinf:";
+const GO_FLAG_BIG_ENDIAN: u8 = 0x1;
+const GO_FLAG_INLINE: u8 = 0x2;
+/// Sentinels around `runtime.modinfo`.
+const GO_MODINFO_PADDING: usize = 16;
+
+const RUSTC_COMMENT_PREFIX: &str = "rustc version ";
+const RUSTC_PATH_PREFIX: &[u8] = b"/rustc/";
+
+/// Audit data is small; anything bigger than this is not what we're after.
+const MAX_AUDIT_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Default)]
+pub struct Provenance {
+    pub go: Option<GoBuildInfo>,
+    pub rust: Option<RustInfo>,
+}
+
+#[derive(Default)]
+pub struct GoBuildInfo {
+    pub version: String,
+    pub path: Option<String>,
+    pub main: Option<GoModule>,
+    pub deps: Vec<GoModule>,
+    pub settings: Vec<(String, String)>,
+}
+
+pub struct GoModule {
+    pub path: String,
+    pub version: String,
+    pub sum: Option<String>,
+    pub replacement: Option<Box<GoModule>>,
+}
+
+#[derive(Default)]
+pub struct RustInfo {
+    pub rustc_version: Option<String>,
+    /// Commit hash out of `/rustc/<hash>/library/...` paths in panic messages.
+    pub rustc_commit: Option<String>,
+    pub dependencies: Option<Vec<RustPackage>>,
+}
+
+pub struct RustPackage {
+    pub name: String,
+    pub version: String,
+    pub source: String,
+    pub kind: String,
+    pub root: bool,
+}
+
+impl Provenance {
+    /// `data` is the image `sections` were read from.
+    pub fn parse(data: &[u8], sections: &[Section]) -> Option<Self> {
+        let provenance = Provenance {
+            go: GoBuildInfo::parse(data, sections),
+            rust: RustInfo::parse(data, sections),
+        };
+        (provenance.go.is_some() || provenance.rust.is_some()).then_some(provenance)
+    }
+
+    pub fn push_values(&self, value: &mut Record, span: Span) {
+        if let Some(go) = &self.go {
+            value.push("go", go.into_value(span));
+        }
+        if let Some(rust) = &self.rust {
+            value.push("rust", rust.into_value(span));
+        }
+    }
+}
+
+impl GoBuildInfo {
+    fn parse(data: &[u8], sections: &[Section]) -> Option<Self> {
+        // the blob has its own section in ELF and Mach-O, and is 16 byte
+        // aligned somewhere in the data of PE files
+        let start = [".go.buildinfo", "__go_buildinfo"]
+            .iter()
+            .filter_map(|name| section::find(sections, name))
+            .map(|s| s.offset)
+            .find(|&offset| {
+                data.get(offset..)
+                    .is_some_and(|d| d.starts_with(GO_BUILDINFO_MAGIC))
+            })
+            .or_else(|| {
+                (0..data.len())
+                    .step_by(16)
+                    .find(|&offset| data[offset..].starts_with(GO_BUILDINFO_MAGIC))
+            })?;
+        let header = data.get(start..start + 32)?;
+        let ptr_size = header[14] as usize;
+        let flags = header[15];
+
+        let (version, modinfo) = if flags & GO_FLAG_INLINE != 0 {
+            let mut pos = start + 32;
+            let mut read = || {
+                let (len, used) = uvarint(data.get(pos..)?)?;
+                let end = pos
+                    .checked_add(used)?
+                    .checked_add(usize::try_from(len).ok()?)?;
+                let value = data.get(pos + used..end)?;
+                pos = end;
+                Some(value)
+            };
+            (read()?.to_vec(), read()?.to_vec())
+        } else {
+            // before Go 1.18 the header points at two Go string headers
+            let big_endian = flags & GO_FLAG_BIG_ENDIAN != 0;
+            let read_ptr = |offset: usize| match (ptr_size, big_endian) {
+                (4, false) => u32_le(data, offset).map(u64::from),
+                (4, true) => u32_be(data, offset).map(u64::from),
+                (8, false) => u64_le(data, offset),
+                (8, true) => u64_be(data, offset),
+                _ => None,
+            };
+            let read_string = |address: u64| {
+                let header = section::translate(sections, address)?;
+                let string = section::translate(sections, read_ptr(header)?)?;
+                let len = read_ptr(header + ptr_size)? as usize;
+                data.get(string..string.checked_add(len)?)
+                    .map(<[u8]>::to_vec)
+            };
+            (
+                read_string(read_ptr(start + 16)?)?,
+                read_string(read_ptr(start + 16 + ptr_size)?).unwrap_or_default(),
+            )
+        };
+
+        let mut info = GoBuildInfo {
+            version: String::from_utf8_lossy(&version).into_owned(),
+            ..Default::default()
+        };
+        if modinfo.len() > 2 * GO_MODINFO_PADDING {
+            let modinfo = &modinfo[GO_MODINFO_PADDING..modinfo.len() - GO_MODINFO_PADDING];
+            info.parse_modinfo(&String::from_utf8_lossy(modinfo));
+        }
+        Some(info)
+    }
+
+    /// Tab separated lines, as printed by `go version -m`.
+    fn parse_modinfo(&mut self, modinfo: &str) {
+        for line in modinfo.lines() {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let module = || {
+                Some(GoModule {
+                    path: fields.get(1)?.to_string(),
+                    version: fields.get(2).unwrap_or(&"").to_string(),
+                    sum: fields
+                        .get(3)
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string()),
+                    replacement: None,
+                })
+            };
+            match fields[0] {
+                "path" => self.path = fields.get(1).map(|s| s.to_string()),
+                "mod" => self.main = module(),
+                "dep" => self.deps.extend(module()),
+                // replaces the module on the line before
+                "=>" => {
+                    let replaced = match self.deps.last_mut() {
+                        Some(dep) => Some(dep),
+                        None => self.main.as_mut(),
+                    };
+                    if let Some(replaced) = replaced {
+                        replaced.replacement = module().map(Box::new);
+                    }
+                }
+                "build" => {
+                    if let Some((key, value)) = fields.get(1).and_then(|s| s.split_once('=')) {
+                        self.settings.push((key.to_string(), value.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "version" => Value::string(&self.version, span),
+                "path" => self.path.as_ref().map_or(Value::nothing(span), |p| Value::string(p, span)),
+                "main" => self.main.as_ref().map_or(Value::nothing(span), |m| m.into_value(span)),
+                "deps" => Value::list(self.deps.iter().map(|d| d.into_value(span)).collect(), span),
+                "settings" => Value::record(
+                    self.settings
+                        .iter()
+                        .map(|(k, v)| (k.clone(), Value::string(v, span)))
+                        .collect(),
+                    span,
+                ),
+            ),
+            span,
+        )
+    }
+}
+
+impl GoModule {
+    pub fn into_value(&self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "path" => Value::string(&self.path, span),
+                "version" => Value::string(&self.version, span),
+                "sum" => self.sum.as_ref().map_or(Value::nothing(span), |s| Value::string(s, span)),
+                "replacement" => self.replacement.as_ref().map_or(Value::nothing(span), |r| r.into_value(span)),
+            ),
+            span,
+        )
+    }
+}
+
+impl RustInfo {
+    fn parse(data: &[u8], sections: &[Section]) -> Option<Self> {
+        let rustc_version = section::find(sections, ".comment")
+            .and_then(|s| s.data(data))
+            .and_then(|comment| {
+                comment
+                    .split(|b| *b == 0)
+                    .filter_map(|entry| std::str::from_utf8(entry).ok())
+                    .find_map(|entry| entry.strip_prefix(RUSTC_COMMENT_PREFIX))
+                    .map(String::from)
+            });
+        let rustc_commit = data
+            .windows(RUSTC_PATH_PREFIX.len())
+            .enumerate()
+            .filter(|(_, window)| *window == RUSTC_PATH_PREFIX)
+            .find_map(|(pos, _)| {
+                let hash = data.get(pos + RUSTC_PATH_PREFIX.len()..)?.get(..41)?;
+                (hash[40] == b'/' && hash[..40].iter().all(u8::is_ascii_hexdigit))
+                    .then(|| String::from_utf8_lossy(&hash[..40]).into_owned())
+            });
+        // `.dep-v0` in ELF and PE, `__DATA,.dep-v0` in Mach-O
+        let dependencies = section::find(sections, ".dep-v0")
+            .and_then(|s| s.data(data))
+            .and_then(audit_packages);
+        if rustc_version.is_none() && rustc_commit.is_none() && dependencies.is_none() {
+            return None;
+        }
+        Some(RustInfo {
+            rustc_version,
+            rustc_commit,
+            dependencies,
+        })
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let optional = |s: &Option<String>| {
+            s.as_ref()
+                .map_or(Value::nothing(span), |s| Value::string(s, span))
+        };
+        Value::record(
+            record!(
+                "rustc_version" => optional(&self.rustc_version),
+                "rustc_commit" => optional(&self.rustc_commit),
+                "dependencies" => self.dependencies.as_ref().map_or(Value::nothing(span), |packages| {
+                    Value::list(
+                        packages
+                            .iter()
+                            .map(|p| {
+                                Value::record(
+                                    record!(
+                                        "name" => Value::string(&p.name, span),
+                                        "version" => Value::string(&p.version, span),
+                                        "source" => Value::string(&p.source, span),
+                                        "kind" => Value::string(&p.kind, span),
+                                        "root" => Value::bool(p.root, span),
+                                    ),
+                                    span,
+                                )
+                            })
+                            .collect(),
+                        span,
+                    )
+                }),
+            ),
+            span,
+        )
+    }
+}
+
+/// Inflate and decode the `cargo auditable` JSON.
+fn audit_packages(compressed: &[u8]) -> Option<Vec<RustPackage>> {
+    let mut json = Vec::new();
+    ZlibDecoder::new(compressed)
+        .take(MAX_AUDIT_SIZE)
+        .read_to_end(&mut json)
+        .ok()?;
+    let json: serde_json::Value = serde_json::from_slice(&json).ok()?;
+    let field = |package: &serde_json::Value, name: &str, default: &str| {
+        package
+            .get(name)
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or(default)
+            .to_string()
+    };
+    Some(
+        json.get("packages")?
+            .as_array()?
+            .iter()
+            .map(|package| RustPackage {
+                name: field(package, "name", ""),
+                version: field(package, "version", ""),
+                source: field(package, "source", ""),
+                kind: field(package, "kind", "runtime"),
+                root: package
+                    .get("root")
+                    .and_then(serde_json::Value::as_bool)
+                    .unwrap_or(false),
+            })
+            .collect(),
+    )
+}
+
+/// Unsigned LEB128 as used by Go's `encoding/binary`, with the number of
+/// bytes it took.
+fn uvarint(buf: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, byte) in buf.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The start of `.go.buildinfo` from a Go 1.22 binary.
+    fn buildinfo(version: &[u8], modinfo: &str) -> Vec<u8> {
+        let mut blob = GO_BUILDINFO_MAGIC.to_vec();
+        blob.extend_from_slice(&[8, GO_FLAG_INLINE]);
+        blob.resize(32, 0);
+        blob.push(version.len() as u8);
+        blob.extend_from_slice(version);
+        let mut modinfo = modinfo.as_bytes().to_vec();
+        modinfo.splice(
+            0..0,
+            *b"\x30\x77\xaf\x0c\x92\x74\x08\x02\x41\xe1\xc1\x07\xe6\xd6\x18\xe6",
+        );
+        modinfo
+            .extend_from_slice(b"\xf9\x32\x43\x31\x86\x18\x20\x72\x00\x82\x42\x10\x41\x16\xd8\xf2");
+        let mut length = modinfo.len();
+        while length >= 0x80 {
+            blob.push(length as u8 | 0x80);
+            length >>= 7;
+        }
+        blob.push(length as u8);
+        blob.extend_from_slice(&modinfo);
+        blob
+    }
+
+    #[test]
+    fn go_buildinfo() {
+        let blob = buildinfo(
+            b"go1.22-20231128-RC02 cl/586117301 +6111acc8e6 X:fieldtrack,boringcrypto",
+            "path\tgo-app-stager\n\
+             mod\tgo-app-stager\t(devel)\t\n\
+             dep\tgopkg.in/yaml.v2\tv2.2.2\n\
+             =>\t./gopkg.in/yaml\t(devel)\t\n\
+             build\t-buildmode=exe\n\
+             build\tCGO_ENABLED=0\n\
+             build\tGOARCH=amd64\n",
+        );
+        let info = GoBuildInfo::parse(&blob, &[]).unwrap();
+        assert_eq!(
+            info.version,
+            "go1.22-20231128-RC02 cl/586117301 +6111acc8e6 X:fieldtrack,boringcrypto"
+        );
+        assert_eq!(info.path.as_deref(), Some("go-app-stager"));
+        assert_eq!(info.main.unwrap().version, "(devel)");
+        let replacement = info.deps[0].replacement.as_ref().unwrap();
+        assert_eq!(
+            (info.deps[0].path.as_str(), replacement.path.as_str()),
+            ("gopkg.in/yaml.v2", "./gopkg.in/yaml")
+        );
+        assert_eq!(info.settings.len(), 3);
+
+        // a length that wraps around the address space
+        let mut blob = buildinfo(b"", "");
+        blob.truncate(32);
+        blob.extend_from_slice(&[0xFF; 9]);
+        blob.push(0x01);
+        assert!(GoBuildInfo::parse(&blob, &[]).is_none());
+    }
+}
@@ -0,0 +1,159 @@
+//! A format independent view of the sections of an image, for the parsers
+//! that look inside section contents.
+use goblin::{
+    Object,
+    elf::{
+        Elf,
+        section_header::{SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS},
+    },
+    mach::{Mach, MachO, SingleArch, constants},
+    pe::{
+        PE,
+        section_table::{IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_WRITE},
+    },
+};
+
+const VM_PROT_WRITE: u32 = 0x2;
+const VM_PROT_EXECUTE: u32 = 0x4;
+
+pub struct Section {
+    pub name: String,
+    /// File offset and size of the contents; zero sized for sections that
+    /// only exist in memory.
+    pub offset: usize,
+    pub size: usize,
+    /// Virtual address the section is loaded at.
+    pub address: u64,
+    pub executable: bool,
+    pub writable: bool,
+}
+
+impl Section {
+    pub fn from_elf(elf: &Elf) -> Vec<Self> {
+        elf.section_headers
+            .iter()
+            .map(|sh| Section {
+                name: elf
+                    .shdr_strtab
+                    .get_at(sh.sh_name)
+                    .unwrap_or_default()
+                    .to_string(),
+                offset: sh.sh_offset as usize,
+                size: if sh.sh_type == SHT_NOBITS {
+                    0
+                } else {
+                    sh.sh_size as usize
+                },
+                address: sh.sh_addr,
+                executable: sh.sh_flags & SHF_EXECINSTR as u64 != 0,
+                writable: sh.sh_flags & SHF_WRITE as u64 != 0,
+            })
+            .collect()
+    }
+
+    pub fn from_pe(pe: &PE) -> Vec<Self> {
+        pe.sections
+            .iter()
+            .map(|sh| Section {
+                name: sh.name().unwrap_or_default().to_string(),
+                offset: sh.pointer_to_raw_data as usize,
+                size: sh.size_of_raw_data as usize,
+                // both come from the file, so a hostile image base must not panic
+                address: pe.image_base.wrapping_add(sh.virtual_address as u64),
+                executable: sh.characteristics & IMAGE_SCN_MEM_EXECUTE != 0,
+                writable: sh.characteristics & IMAGE_SCN_MEM_WRITE != 0,
+            })
+            .collect()
+    }
+
+    pub fn from_macho(macho: &MachO) -> Vec<Self> {
+        macho
+            .segments
+            .iter()
+            .flat_map(|segment| {
+                // protection is per segment, sections inherit it
+                let prot = segment.initprot;
+                segment
+                    .sections()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |(sh, _)| (sh, prot))
+            })
+            .map(|(sh, prot)| Section {
+                name: sh.name().unwrap_or_default().to_string(),
+                offset: sh.offset as usize,
+                size: match sh.flags & constants::SECTION_TYPE {
+                    constants::S_ZEROFILL
+                    | constants::S_GB_ZEROFILL
+                    | constants::S_THREAD_LOCAL_ZEROFILL => 0,
+                    _ => sh.size as usize,
+                },
+                address: sh.addr,
+                executable: prot & VM_PROT_EXECUTE != 0,
+                writable: prot & VM_PROT_WRITE != 0,
+            })
+            .collect()
+    }
+
+    /// Sections of whatever image `data` holds, with offsets into `data`,
+    /// empty for anything that isn't one.
+    pub fn from_image(data: &[u8]) -> Vec<Self> {
+        match Object::parse(data) {
+            Ok(Object::Elf(elf)) => Self::from_elf(&elf),
+            Ok(Object::PE(pe)) => Self::from_pe(&pe),
+            Ok(Object::Mach(Mach::Binary(macho))) => Self::from_macho(&macho),
+            Ok(Object::Mach(Mach::Fat(fat))) => fat
+                .iter_arches()
+                .enumerate()
+                .filter_map(|(index, arch)| match (arch, fat.get(index)) {
+                    (Ok(arch), Ok(SingleArch::MachO(macho))) => Some((arch.offset, macho)),
+                    _ => None,
+                })
+                .flat_map(|(base, macho)| {
+                    // slices keep offsets relative to their own start, and a
+                    // section pushed past the end of the address space is dropped
+                    Self::from_macho(&macho)
+                        .into_iter()
+                        .filter_map(move |mut s| {
+                            s.offset = s.offset.checked_add(base as usize)?;
+                            Some(s)
+                        })
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Contents of the section, `data` being the image the section table came from.
+    pub fn data<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        data.get(self.offset..self.offset.checked_add(self.size)?)
+    }
+}
+
+/// The section whose contents hold file offset `offset`.
+pub fn containing(sections: &[Section], offset: usize) -> Option<&Section> {
+    sections.iter().find(|s| {
+        s.size > 0
+            && s.offset
+                .checked_add(s.size)
+                .is_some_and(|end| (s.offset..end).contains(&offset))
+    })
+}
+
+pub fn find<'a>(sections: &'a [Section], name: &str) -> Option<&'a Section> {
+    sections.iter().find(|s| s.name == name)
+}
+
+/// File offset of a virtual address.
+pub fn translate(sections: &[Section], address: u64) -> Option<usize> {
+    sections.iter().find_map(|s| {
+        if s.address == 0 {
+            return None;
+        }
+        let delta = address.checked_sub(s.address)?;
+        if delta >= s.size as u64 {
+            return None;
+        }
+        s.offset.checked_add(delta as usize)
+    })
+}
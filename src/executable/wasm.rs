@@ -0,0 +1,508 @@
+//! WebAssembly modules and components.
+use crate::bytes::u16_le;
+use nu_protocol::{Record, Span, Value, record};
+
+pub const WASM_MAGIC: &[u8] = b"\0asm";
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_TABLE: u8 = 4;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_EXPORT: u8 = 7;
+
+const COMPONENT_SECTION_IMPORT: u8 = 10;
+const COMPONENT_SECTION_EXPORT: u8 = 11;
+
+#[derive(Default)]
+pub struct Module {
+    pub version: u16,
+    /// Component model binaries share the preamble but use layer 1.
+    pub component: bool,
+    pub imports: Vec<Import>,
+    pub exports: Vec<Export>,
+    pub memories: Vec<Limits>,
+    pub tables: Vec<Table>,
+    pub custom_sections: Vec<String>,
+    /// `producers` section: field name to `name version` entries.
+    pub producers: Vec<(String, Vec<String>)>,
+    /// `target_features` section, as `+feature`, `-feature` or `=feature`.
+    pub target_features: Vec<String>,
+}
+
+pub struct Import {
+    /// Components import by name only.
+    pub module: Option<String>,
+    pub name: String,
+    pub kind: &'static str,
+}
+
+pub struct Export {
+    pub name: String,
+    pub kind: &'static str,
+}
+
+pub struct Limits {
+    pub min: u64,
+    pub max: Option<u64>,
+    pub shared: bool,
+    pub memory64: bool,
+}
+
+pub struct Table {
+    pub element: String,
+    pub limits: Limits,
+}
+
+/// Cursor over LEB128 encoded section contents. Everything returns `None`
+/// once the data runs out, leaving whatever was read so far.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn leb(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.leb()?.try_into().ok()
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        Some(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn limits(&mut self) -> Option<Limits> {
+        let flags = self.byte()?;
+        let min = self.leb()?;
+        let max = if flags & 0x01 != 0 {
+            Some(self.leb()?)
+        } else {
+            None
+        };
+        // custom page sizes
+        if flags & 0x08 != 0 {
+            self.u32()?;
+        }
+        Some(Limits {
+            min,
+            max,
+            shared: flags & 0x02 != 0,
+            memory64: flags & 0x04 != 0,
+        })
+    }
+
+    fn table(&mut self) -> Option<Table> {
+        // tables with an initializer expression are prefixed with 0x40 0x00;
+        // the expression itself would need a full decoder, so callers stop
+        // after one of those
+        if self.buf.get(self.pos) == Some(&0x40) {
+            self.bytes(2)?;
+        }
+        let element = match self.byte()? {
+            0x70 => "funcref".to_string(),
+            0x6F => "externref".to_string(),
+            0x69 => "exnref".to_string(),
+            // typed references, (ref null? $t)
+            prefix @ (0x63 | 0x64) => {
+                let heap = match self.leb()? {
+                    0x70 => "func".to_string(),
+                    0x6F => "extern".to_string(),
+                    index => index.to_string(),
+                };
+                let null = if prefix == 0x63 { "null " } else { "" };
+                format!("(ref {null}{heap})")
+            }
+            other => format!("unknown ({other:#04x})"),
+        };
+        Some(Table {
+            element,
+            limits: self.limits()?,
+        })
+    }
+}
+
+fn external_kind(kind: u8) -> &'static str {
+    match kind {
+        0x00 => "func",
+        0x01 => "table",
+        0x02 => "memory",
+        0x03 => "global",
+        0x04 => "tag",
+        _ => "unknown",
+    }
+}
+
+fn component_sort(sort: u8) -> &'static str {
+    match sort {
+        0x00 => "core",
+        0x01 => "func",
+        0x02 => "value",
+        0x03 => "type",
+        0x04 => "component",
+        0x05 => "instance",
+        _ => "unknown",
+    }
+}
+
+impl Module {
+    pub fn parse(buffer: &[u8]) -> Result<Self, String> {
+        if !buffer.starts_with(WASM_MAGIC) {
+            return Err("Not a WebAssembly module".to_string());
+        }
+        let version = u16_le(buffer, 4).ok_or("Truncated WebAssembly header")?;
+        let layer = u16_le(buffer, 6).ok_or("Truncated WebAssembly header")?;
+        let mut module = Module {
+            version,
+            component: layer == 1,
+            ..Default::default()
+        };
+        let mut sections = Reader::new(&buffer[8..]);
+        while !sections.is_empty() {
+            let Some((id, contents)) = sections
+                .byte()
+                .zip(sections.u32())
+                .and_then(|(id, len)| Some((id, sections.bytes(len as usize)?)))
+            else {
+                return Err("Truncated WebAssembly section".to_string());
+            };
+            module.parse_section(id, &mut Reader::new(contents));
+        }
+        Ok(module)
+    }
+
+    /// Malformed sections are read up to the point where they go wrong.
+    fn parse_section(&mut self, id: u8, section: &mut Reader) -> Option<()> {
+        if id == SECTION_CUSTOM {
+            let name = section.name()?;
+            match name.as_str() {
+                "producers" => self.parse_producers(section),
+                "target_features" => self.parse_target_features(section),
+                _ => None,
+            };
+            self.custom_sections.push(name);
+            return Some(());
+        }
+        match (self.component, id) {
+            (false, SECTION_IMPORT) => {
+                for _ in 0..section.u32()? {
+                    let module = section.name()?;
+                    let name = section.name()?;
+                    let kind = section.byte()?;
+                    match kind {
+                        0x00 => {
+                            section.u32()?;
+                        }
+                        // imported tables and memories come first in their index spaces
+                        0x01 => self.tables.push(section.table()?),
+                        0x02 => self.memories.push(section.limits()?),
+                        // value type and mutability
+                        0x03 => {
+                            if matches!(section.byte()?, 0x63 | 0x64) {
+                                section.leb()?;
+                            }
+                            section.byte()?;
+                        }
+                        // attribute and type index
+                        0x04 => {
+                            section.byte()?;
+                            section.u32()?;
+                        }
+                        _ => return None,
+                    }
+                    self.imports.push(Import {
+                        module: Some(module),
+                        name,
+                        kind: external_kind(kind),
+                    });
+                }
+            }
+            (false, SECTION_TABLE) => {
+                for _ in 0..section.u32()? {
+                    let initialized = section.buf.get(section.pos) == Some(&0x40);
+                    self.tables.push(section.table()?);
+                    if initialized {
+                        break;
+                    }
+                }
+            }
+            (false, SECTION_MEMORY) => {
+                for _ in 0..section.u32()? {
+                    self.memories.push(section.limits()?);
+                }
+            }
+            (false, SECTION_EXPORT) => {
+                for _ in 0..section.u32()? {
+                    let name = section.name()?;
+                    let kind = external_kind(section.byte()?);
+                    section.u32()?;
+                    self.exports.push(Export { name, kind });
+                }
+            }
+            (true, COMPONENT_SECTION_IMPORT) => {
+                for _ in 0..section.u32()? {
+                    let name = component_name(section)?;
+                    let kind = component_extern_desc(section)?;
+                    self.imports.push(Import {
+                        module: None,
+                        name,
+                        kind,
+                    });
+                }
+            }
+            (true, COMPONENT_SECTION_EXPORT) => {
+                for _ in 0..section.u32()? {
+                    let name = component_name(section)?;
+                    let sort = section.byte()?;
+                    let kind = if sort == 0x00 {
+                        // core sorts carry the core kind as well
+                        section.byte()?;
+                        "core"
+                    } else {
+                        component_sort(sort)
+                    };
+                    section.u32()?;
+                    // optional type ascription
+                    if section.byte()? == 0x01 {
+                        component_extern_desc(section)?;
+                    }
+                    self.exports.push(Export { name, kind });
+                }
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    fn parse_producers(&mut self, section: &mut Reader) -> Option<()> {
+        for _ in 0..section.u32()? {
+            let field = section.name()?;
+            let values = (0..section.u32()?)
+                .map(|_| {
+                    let name = section.name()?;
+                    let version = section.name()?;
+                    Some(format!("{name} {version}").trim_end().to_string())
+                })
+                .collect::<Option<Vec<_>>>()?;
+            self.producers.push((field, values));
+        }
+        Some(())
+    }
+
+    fn parse_target_features(&mut self, section: &mut Reader) -> Option<()> {
+        for _ in 0..section.u32()? {
+            let prefix = section.byte()? as char;
+            self.target_features
+                .push(format!("{prefix}{}", section.name()?));
+        }
+        Some(())
+    }
+
+    pub fn description(&self) -> String {
+        format!(
+            "wasm {}, version {}",
+            if self.component {
+                "component"
+            } else {
+                "module"
+            },
+            self.version
+        )
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        let limits = |limits: &Limits| {
+            record!(
+                "min" => Value::int(limits.min as _, span),
+                "max" => limits.max.map_or(Value::nothing(span), |m| Value::int(m as _, span)),
+                "shared" => Value::bool(limits.shared, span),
+                "memory64" => Value::bool(limits.memory64, span),
+            )
+        };
+        Value::record(
+            record!(
+                "format" => Value::string("wasm", span),
+                "version" => Value::int(self.version as _, span),
+                "component" => Value::bool(self.component, span),
+                "imports" => Value::list(
+                    self.imports
+                        .iter()
+                        .map(|i| {
+                            Value::record(
+                                record!(
+                                    "module" => i.module.as_ref().map_or(Value::nothing(span), |m| Value::string(m, span)),
+                                    "name" => Value::string(&i.name, span),
+                                    "kind" => Value::string(i.kind, span),
+                                ),
+                                span,
+                            )
+                        })
+                        .collect(),
+                    span,
+                ),
+                "exports" => Value::list(
+                    self.exports
+                        .iter()
+                        .map(|e| {
+                            Value::record(
+                                record!(
+                                    "name" => Value::string(&e.name, span),
+                                    "kind" => Value::string(e.kind, span),
+                                ),
+                                span,
+                            )
+                        })
+                        .collect(),
+                    span,
+                ),
+                "memories" => Value::list(
+                    self.memories
+                        .iter()
+                        .map(|m| Value::record(limits(m), span))
+                        .collect(),
+                    span,
+                ),
+                "tables" => Value::list(
+                    self.tables
+                        .iter()
+                        .map(|t| {
+                            let mut table = record!("element" => Value::string(&t.element, span));
+                            for (name, value) in limits(&t.limits) {
+                                if name != "shared" {
+                                    table.push(name, value);
+                                }
+                            }
+                            Value::record(table, span)
+                        })
+                        .collect(),
+                    span,
+                ),
+                "custom_sections" => Value::list(
+                    self.custom_sections
+                        .iter()
+                        .map(|s| Value::string(s, span))
+                        .collect(),
+                    span,
+                ),
+                "producers" => Value::record(
+                    self.producers
+                        .iter()
+                        .map(|(field, values)| {
+                            (
+                                field.clone(),
+                                Value::list(values.iter().map(|v| Value::string(v, span)).collect(), span),
+                            )
+                        })
+                        .collect::<Record>(),
+                    span,
+                ),
+                "target_features" => Value::list(
+                    self.target_features
+                        .iter()
+                        .map(|f| Value::string(f, span))
+                        .collect(),
+                    span,
+                ),
+            ),
+            span,
+        )
+    }
+}
+
+/// Component import and export names, optionally followed by a version
+/// suffix in newer encodings.
+fn component_name(section: &mut Reader) -> Option<String> {
+    match section.byte()? {
+        0x00 => section.name(),
+        0x01 => {
+            let name = section.name()?;
+            if section.byte()? == 0x01 {
+                section.name()?;
+            }
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// Skip an `externdesc`, returning the kind it describes.
+fn component_extern_desc(section: &mut Reader) -> Option<&'static str> {
+    let kind = section.byte()?;
+    match kind {
+        // core module type
+        0x00 => {
+            section.byte()?;
+            section.u32()?;
+        }
+        // value bound: equal to a value, or of a value type
+        0x02 => {
+            section.byte()?;
+            section.leb()?;
+        }
+        // type bound: equal to a type, or a fresh resource
+        0x03 => {
+            if section.byte()? == 0x00 {
+                section.u32()?;
+            }
+        }
+        0x01 | 0x04 | 0x05 => {
+            section.u32()?;
+        }
+        _ => return None,
+    }
+    Some(match kind {
+        0x00 => "module",
+        other => component_sort(other),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::Module;
+
+    #[test]
+    fn imports_and_memories() {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // (import "env" "mem" (memory 16 256 shared)) (memory 17)
+        wasm.extend(b"\x02\x0e\x01\x03env\x03mem\x02\x03\x10\x80\x02");
+        wasm.extend(b"\x05\x03\x01\x00\x11");
+        let module = Module::parse(&wasm).unwrap();
+        assert_eq!(module.imports.len(), 1);
+        assert_eq!(module.imports[0].module.as_deref(), Some("env"));
+        assert_eq!(module.imports[0].kind, "memory");
+        assert_eq!(module.memories.len(), 2);
+        assert_eq!(module.memories[0].max, Some(256));
+        assert!(module.memories[0].shared);
+        assert_eq!(module.memories[1].min, 17);
+    }
+}
@@ -24,6 +24,9 @@ extension_enum! {
         Code(CodeExtension),
         Database(DatabaseExtension),
         Book(BookExtension),
+        Disk(DiskExtension),
+        VirtualDisk(VirtualDiskExtension),
+        Data(DataExtension),
     }
 }
 
@@ -149,6 +152,12 @@ extension_category_enum! {
         Msi = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1],
         Jar = [0x50, 0x4B, 0x03, 0x04],
         Bat = [],
+        Wasm = [0x00, 0x61, 0x73, 0x6D],
+        A = [0x21, 0x3C, 0x61, 0x72, 0x63, 0x68, 0x3E, 0x0A],
+        Lib = [0x21, 0x3C, 0x61, 0x72, 0x63, 0x68, 0x3E, 0x0A],
+        O = [],
+        Class = [0xCA, 0xFE, 0xBA, 0xBE],
+        Dex = [0x64, 0x65, 0x78, 0x0A],
     }
 }
 
@@ -216,6 +225,14 @@ extension_category_enum! {
         P12,
         P8,
         Keychain,
+        Key,
+        Crt,
+        Cer,
+        Der,
+        Csr,
+        Pfx,
+        Asc,
+        Gpg,
     }
 }
 
@@ -290,6 +307,56 @@ extension_category_enum! {
     }
 }
 
+// disk and filesystem image extensions
+extension_category_enum! {
+    DiskExtension _ALL_DISK_EXTENSIONS {
+        Img = [0x55, 0xAA] + 510,
+        Iso = [0x43, 0x44, 0x30, 0x30, 0x31] + 0x8001,
+        Udf = [0x42, 0x45, 0x41, 0x30, 0x31] + 0x8001,
+        Squashfs = [0x68, 0x73, 0x71, 0x73] | [0x73, 0x71, 0x73, 0x68],
+        Sqsh = [0x68, 0x73, 0x71, 0x73] | [0x73, 0x71, 0x73, 0x68],
+        Ext2 = [0x53, 0xEF] + 0x438,
+        Ext3 = [0x53, 0xEF] + 0x438,
+        Ext4 = [0x53, 0xEF] + 0x438,
+        Vfat = [0xEB, _, 0x90],
+        Ntfs = [0x4E, 0x54, 0x46, 0x53, 0x20, 0x20, 0x20, 0x20] + 3,
+        Exfat = [0x45, 0x58, 0x46, 0x41, 0x54, 0x20, 0x20, 0x20] + 3,
+        Cpio = [0x30, 0x37, 0x30, 0x37, 0x30, 0x31] | [0x30, 0x37, 0x30, 0x37, 0x30, 0x32] | [0x30, 0x37, 0x30, 0x37, 0x30, 0x37] | [0xC7, 0x71],
+    }
+}
+
+// virtual machine disk extensions
+extension_category_enum! {
+    VirtualDiskExtension _ALL_VIRTUAL_DISK_EXTENSIONS {
+        Qcow = [0x51, 0x46, 0x49, 0xFB],
+        Qcow2 = [0x51, 0x46, 0x49, 0xFB],
+        Vmdk = [0x4B, 0x44, 0x4D, 0x56] | [0x23, 0x20, 0x44, 0x69, 0x73, 0x6B, 0x20, 0x44, 0x65, 0x73, 0x63, 0x72, 0x69, 0x70, 0x74, 0x6F, 0x72],
+        Vhd = [0x63, 0x6F, 0x6E, 0x65, 0x63, 0x74, 0x69, 0x78],
+        Vhdx = [0x76, 0x68, 0x64, 0x78, 0x66, 0x69, 0x6C, 0x65],
+        Vdi = [0x7F, 0x10, 0xDA, 0xBE] + 0x40,
+        Raw = [0x55, 0xAA] + 510,
+    }
+}
+
+// columnar, array and scientific data extensions
+extension_category_enum! {
+    DataExtension _ALL_DATA_EXTENSIONS {
+        Parquet = [0x50, 0x41, 0x52, 0x31],
+        Arrow = [0x41, 0x52, 0x52, 0x4F, 0x57, 0x31],
+        Feather = [0x41, 0x52, 0x52, 0x4F, 0x57, 0x31] | [0x46, 0x45, 0x41, 0x31],
+        Avro = [0x4F, 0x62, 0x6A, 0x01],
+        Orc = [0x4F, 0x52, 0x43],
+        H5 = [0x89, 0x48, 0x44, 0x46, 0x0D, 0x0A, 0x1A, 0x0A],
+        Hdf5 = [0x89, 0x48, 0x44, 0x46, 0x0D, 0x0A, 0x1A, 0x0A],
+        Hdf = [0x89, 0x48, 0x44, 0x46, 0x0D, 0x0A, 0x1A, 0x0A] | [0x0E, 0x03, 0x13, 0x01],
+        Nc = [0x43, 0x44, 0x46, 0x01] | [0x43, 0x44, 0x46, 0x02] | [0x43, 0x44, 0x46, 0x05] | [0x89, 0x48, 0x44, 0x46, 0x0D, 0x0A, 0x1A, 0x0A],
+        Npy = [0x93, 0x4E, 0x55, 0x4D, 0x50, 0x59],
+        Npz = [0x50, 0x4B, 0x03, 0x04],
+        Pkl = [0x80, 0x02] | [0x80, 0x03] | [0x80, 0x04] | [0x80, 0x05],
+        Pickle = [0x80, 0x02] | [0x80, 0x03] | [0x80, 0x04] | [0x80, 0x05],
+    }
+}
+
 #[cfg(test)]
 mod test {
 
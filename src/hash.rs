@@ -0,0 +1,209 @@
+//! Content digests computed while the file is read for type detection,
+//! exact ones and the ssdeep and TLSH similarity digests.
+pub mod ssdeep;
+pub mod tlsh;
+
+use crate::bytes::hex;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{fmt, io::Read};
+use xxhash_rust::xxh3::Xxh3;
+
+const READ_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Sha1,
+    Md5,
+    Blake3,
+    Xxh3,
+    Ssdeep,
+    Tlsh,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 7] = [
+        Algorithm::Sha256,
+        Algorithm::Sha1,
+        Algorithm::Md5,
+        Algorithm::Blake3,
+        Algorithm::Xxh3,
+        Algorithm::Ssdeep,
+        Algorithm::Tlsh,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace('-', "").as_str() {
+            "sha256" => Some(Algorithm::Sha256),
+            "sha1" => Some(Algorithm::Sha1),
+            "md5" => Some(Algorithm::Md5),
+            "blake3" => Some(Algorithm::Blake3),
+            "xxh3" | "xxhash3" | "xxh364" => Some(Algorithm::Xxh3),
+            "ssdeep" => Some(Algorithm::Ssdeep),
+            "tlsh" => Some(Algorithm::Tlsh),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Md5 => "md5",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Xxh3 => "xxh3",
+            Algorithm::Ssdeep => "ssdeep",
+            Algorithm::Tlsh => "tlsh",
+        })
+    }
+}
+
+enum State {
+    Sha256(Sha256),
+    Sha1(Sha1),
+    Md5(Md5),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+    Ssdeep(Box<ssdeep::Hasher>),
+    Tlsh(Box<tlsh::Hasher>),
+}
+
+/// Several digests fed from one read of the file.
+pub struct Hasher {
+    states: Vec<(Algorithm, State)>,
+    /// Set once the input has been read to the end.
+    complete: bool,
+}
+
+impl Hasher {
+    pub fn new(algorithms: &[Algorithm]) -> Self {
+        let mut states: Vec<(Algorithm, State)> = Vec::new();
+        for algorithm in algorithms {
+            if states.iter().any(|(a, _)| a == algorithm) {
+                continue;
+            }
+            let state = match algorithm {
+                Algorithm::Sha256 => State::Sha256(Sha256::new()),
+                Algorithm::Sha1 => State::Sha1(Sha1::new()),
+                Algorithm::Md5 => State::Md5(Md5::new()),
+                Algorithm::Blake3 => State::Blake3(Box::default()),
+                Algorithm::Xxh3 => State::Xxh3(Box::default()),
+                Algorithm::Ssdeep => State::Ssdeep(Box::default()),
+                Algorithm::Tlsh => State::Tlsh(Box::default()),
+            };
+            states.push((*algorithm, state));
+        }
+        Hasher {
+            states,
+            complete: false,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, state) in &mut self.states {
+            match state {
+                State::Sha256(h) => h.update(data),
+                State::Sha1(h) => h.update(data),
+                State::Md5(h) => h.update(data),
+                State::Blake3(h) => {
+                    h.update(data);
+                }
+                State::Xxh3(h) => h.update(data),
+                State::Ssdeep(h) => h.update(data),
+                State::Tlsh(h) => h.update(data),
+            }
+        }
+    }
+
+    /// Feed the rest of `reader`, marking the digests complete when it ends.
+    pub fn update_from(&mut self, mut reader: impl Read) -> std::io::Result<()> {
+        let mut buffer = vec![0; READ_SIZE];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => self.update(&buffer[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.complete = true;
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Lower case hex digests, in the order they were asked for.
+    pub fn finish(self) -> Vec<(Algorithm, String)> {
+        self.states
+            .into_iter()
+            .map(|(algorithm, state)| {
+                let digest = match state {
+                    State::Sha256(h) => hex(&h.finalize()),
+                    State::Sha1(h) => hex(&h.finalize()),
+                    State::Md5(h) => hex(&h.finalize()),
+                    State::Blake3(h) => h.finalize().to_hex().to_string(),
+                    State::Xxh3(h) => format!("{:016x}", h.digest()),
+                    State::Ssdeep(h) => h.finalize(),
+                    State::Tlsh(h) => h.finalize(),
+                };
+                (algorithm, digest)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Algorithm, Hasher};
+
+    #[test]
+    fn digests_of_abc() {
+        let mut hasher = Hasher::new(&Algorithm::ALL);
+        hasher.update_from(&b"abc"[..]).unwrap();
+        assert!(hasher.is_complete());
+        let digests = hasher.finish();
+        let digest = |algorithm| {
+            digests
+                .iter()
+                .find(|(a, _)| *a == algorithm)
+                .map(|(_, d)| d.as_str())
+        };
+        assert_eq!(
+            digest(Algorithm::Sha256),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            digest(Algorithm::Sha1),
+            Some("a9993e364706816aba3e25717850c26c9cd0d89d")
+        );
+        assert_eq!(
+            digest(Algorithm::Md5),
+            Some("900150983cd24fb0d6963f7d28e17f72")
+        );
+        assert_eq!(
+            digest(Algorithm::Blake3),
+            Some("6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85")
+        );
+        assert_eq!(digest(Algorithm::Xxh3), Some("78af5f94892f3950"));
+    }
+
+    #[test]
+    fn blake3_across_chunks() {
+        // from the official test_vectors.json, input bytes cycle through 0..251
+        let input = (0..2048).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut hasher = Hasher::new(&[Algorithm::Blake3]);
+        // split unevenly to exercise the streaming path
+        hasher.update(&input[..682]);
+        hasher.update(&input[682..]);
+        assert_eq!(
+            hasher.finish()[0].1,
+            "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Context triggered piecewise hashing, compatible with ssdeep. Digests for
+//! every candidate block size are kept while streaming, the way ssdeep 2.10
+//! and later do it, so the input is only read once.
+
+const ROLLING_WINDOW: usize = 7;
+const MIN_BLOCKSIZE: u64 = 3;
+const HASH_PRIME: u32 = 0x01000193;
+const HASH_INIT: u32 = 0x28021967;
+const SPAMSUM_LENGTH: usize = 64;
+const NUM_BLOCKHASHES: usize = 31;
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn block_size(index: usize) -> u64 {
+    MIN_BLOCKSIZE << index
+}
+
+fn sum_hash(c: u8, h: u32) -> u32 {
+    h.wrapping_mul(HASH_PRIME) ^ c as u32
+}
+
+#[derive(Default)]
+struct Roll {
+    window: [u8; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl Roll {
+    fn hash(&mut self, c: u8) {
+        self.h2 = self.h2.wrapping_sub(self.h1);
+        self.h2 = self
+            .h2
+            .wrapping_add((ROLLING_WINDOW as u32).wrapping_mul(c as u32));
+        self.h1 = self.h1.wrapping_add(c as u32);
+        self.h1 = self.h1.wrapping_sub(self.window[self.n] as u32);
+        self.window[self.n] = c;
+        self.n = (self.n + 1) % ROLLING_WINDOW;
+        self.h3 = (self.h3 << 5) ^ c as u32;
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+#[derive(Clone)]
+struct BlockHash {
+    h: u32,
+    half_h: u32,
+    /// One character past `len` holds the pending last character once the
+    /// digest is full.
+    digest: [u8; SPAMSUM_LENGTH],
+    half_digest: Option<u8>,
+    len: usize,
+}
+
+impl BlockHash {
+    fn new() -> Self {
+        BlockHash {
+            h: HASH_INIT,
+            half_h: HASH_INIT,
+            digest: [0; SPAMSUM_LENGTH],
+            half_digest: None,
+            len: 0,
+        }
+    }
+}
+
+pub struct Hasher {
+    start: usize,
+    blocks: Vec<BlockHash>,
+    total_size: u64,
+    roll: Roll,
+    /// Whether a character is waiting past the end of a full digest.
+    full: Vec<bool>,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher {
+            start: 0,
+            blocks: vec![BlockHash::new()],
+            total_size: 0,
+            roll: Roll::default(),
+            full: vec![false],
+        }
+    }
+}
+
+impl Hasher {
+    fn try_fork(&mut self) {
+        if self.blocks.len() < NUM_BLOCKHASHES {
+            let last = self.blocks.last().expect("at least one block hash");
+            let mut forked = BlockHash::new();
+            forked.h = last.h;
+            forked.half_h = last.half_h;
+            self.blocks.push(forked);
+            self.full.push(false);
+        }
+    }
+
+    /// Drop the smallest block size once it can no longer be chosen.
+    fn try_reduce(&mut self) {
+        if self.blocks.len() - self.start < 2
+            || block_size(self.start) * SPAMSUM_LENGTH as u64 >= self.total_size
+            || self.blocks[self.start + 1].len < SPAMSUM_LENGTH / 2
+        {
+            return;
+        }
+        self.start += 1;
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.total_size += data.len() as u64;
+        for &c in data {
+            self.roll.hash(c);
+            let h = self.roll.sum() as u64;
+            for block in &mut self.blocks[self.start..] {
+                block.h = sum_hash(c, block.h);
+                block.half_h = sum_hash(c, block.half_h);
+            }
+            let mut i = self.start;
+            // block sizes double, a miss at one size is a miss at all larger
+            while i < self.blocks.len() && h % block_size(i) == block_size(i) - 1 {
+                if self.blocks[i].len == 0 {
+                    self.try_fork();
+                }
+                let block = &mut self.blocks[i];
+                block.digest[block.len] = B64[block.h as usize % 64];
+                block.half_digest = Some(B64[block.half_h as usize % 64]);
+                if block.len < SPAMSUM_LENGTH - 1 {
+                    block.len += 1;
+                    block.h = HASH_INIT;
+                    if block.len < SPAMSUM_LENGTH / 2 {
+                        block.half_h = HASH_INIT;
+                        block.half_digest = None;
+                    }
+                    self.full[i] = false;
+                } else {
+                    self.full[i] = true;
+                    self.try_reduce();
+                }
+                i += 1;
+            }
+        }
+    }
+
+    /// `blocksize:digest:digest`, as printed by ssdeep.
+    pub fn finalize(&self) -> String {
+        let h = self.roll.sum();
+        let mut index = self.start;
+        while block_size(index) * (SPAMSUM_LENGTH as u64) < self.total_size
+            && index < NUM_BLOCKHASHES - 1
+        {
+            index += 1;
+        }
+        index = index.min(self.blocks.len() - 1);
+        while index > self.start && self.blocks[index].len < SPAMSUM_LENGTH / 2 {
+            index -= 1;
+        }
+
+        let block = &self.blocks[index];
+        let mut first = block.digest[..block.len].to_vec();
+        if h != 0 {
+            first.push(B64[block.h as usize % 64]);
+        } else if self.full[index] {
+            first.push(block.digest[block.len]);
+        }
+
+        let mut second = Vec::new();
+        if let Some(next) = self.blocks.get(index + 1) {
+            let len = next.len.min(SPAMSUM_LENGTH / 2 - 1);
+            second.extend_from_slice(&next.digest[..len]);
+            if h != 0 {
+                second.push(B64[next.half_h as usize % 64]);
+            } else if let Some(c) = next.half_digest {
+                second.push(c);
+            }
+        } else if h != 0 {
+            second.push(B64[block.h as usize % 64]);
+        }
+
+        format!(
+            "{}:{}:{}",
+            block_size(index),
+            String::from_utf8_lossy(&first),
+            String::from_utf8_lossy(&second)
+        )
+    }
+}
+
+/// Runs of more than three identical characters carry no information.
+fn eliminate_sequences(digest: &str) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(digest.len());
+    for &c in digest.as_bytes() {
+        if out.len() >= 3 && out[out.len() - 3..].iter().all(|&p| p == c) {
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn has_common_substring(a: &[u8], b: &[u8]) -> bool {
+    a.windows(ROLLING_WINDOW)
+        .any(|window| b.windows(ROLLING_WINDOW).any(|other| other == window))
+}
+
+/// Levenshtein distance where a substitution costs as much as a removal
+/// and an insertion.
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replace = previous[j] + if ca == cb { 0 } else { 2 };
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+fn score_strings(a: &[u8], b: &[u8], block_size: u64) -> u32 {
+    if a.len() > SPAMSUM_LENGTH || b.len() > SPAMSUM_LENGTH || !has_common_substring(a, b) {
+        return 0;
+    }
+    let distance = edit_distance(a, b) as u64;
+    let scaled = distance * SPAMSUM_LENGTH as u64 / (a.len() + b.len()) as u64;
+    let scaled = 100 * scaled / SPAMSUM_LENGTH as u64;
+    if scaled >= 100 {
+        return 0;
+    }
+    let score = 100 - scaled;
+    // small block sizes can't claim more than their digests support
+    if block_size >= (99 + ROLLING_WINDOW as u64) / ROLLING_WINDOW as u64 * MIN_BLOCKSIZE {
+        return score as u32;
+    }
+    let cap = block_size / MIN_BLOCKSIZE * a.len().min(b.len()) as u64;
+    score.min(cap) as u32
+}
+
+fn parse(digest: &str) -> Option<(u64, Vec<u8>, Vec<u8>)> {
+    let mut parts = digest.splitn(3, ':');
+    let block_size = parts.next()?.parse().ok()?;
+    let first = eliminate_sequences(parts.next()?);
+    let second = eliminate_sequences(parts.next()?.split(',').next()?);
+    Some((block_size, first, second))
+}
+
+/// Match score between two digests, 0 for no match up to 100.
+pub fn compare(a: &str, b: &str) -> Option<u32> {
+    let (size_a, a1, a2) = parse(a)?;
+    let (size_b, b1, b2) = parse(b)?;
+    Some(if size_a == size_b {
+        if a1 == b1 && a2 == b2 {
+            return Some(100);
+        }
+        score_strings(&a1, &b1, size_a).max(score_strings(&a2, &b2, size_a * 2))
+    } else if size_a == size_b * 2 {
+        score_strings(&a1, &b2, size_a)
+    } else if size_b == size_a * 2 {
+        score_strings(&a2, &b1, size_b)
+    } else {
+        0
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Hasher, compare};
+
+    #[test]
+    fn digests_and_scores() {
+        assert_eq!(Hasher::default().finalize(), "3::");
+        let text = (0..20_000u32)
+            .map(|i| format!("line {} of some text {}\n", i, i * 7 % 13))
+            .collect::<String>();
+        let mut edited = text.clone();
+        edited.replace_range(9_000..9_100, &"x".repeat(100));
+
+        let digest = |data: &[u8]| {
+            let mut hasher = Hasher::default();
+            // chunking must not change the digest
+            for chunk in data.chunks(4096) {
+                hasher.update(chunk);
+            }
+            hasher.finalize()
+        };
+        let original = digest(text.as_bytes());
+        let mut whole = Hasher::default();
+        whole.update(text.as_bytes());
+        assert_eq!(whole.finalize(), original);
+
+        assert_eq!(compare(&original, &original), Some(100));
+        let score = compare(&original, &digest(edited.as_bytes())).unwrap();
+        assert!(score > 50 && score < 100, "{score}");
+        assert_eq!(compare(&original, &digest(b"unrelated")), Some(0));
+        assert_eq!(compare("garbage", &original), None);
+    }
+}
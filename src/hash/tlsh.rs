@@ -0,0 +1,232 @@
+//! TLSH locality sensitive hashing, the standard 128 bucket, 1 byte
+//! checksum variant printed with the `T1` version prefix.
+
+const BUCKETS: usize = 128;
+const CODE_SIZE: usize = BUCKETS / 4;
+const WINDOW: usize = 5;
+/// Shorter inputs don't fill enough buckets to mean anything.
+const MIN_DATA_LENGTH: u64 = 50;
+
+/// Pearson's permutation table, as used by TLSH.
+const V_TABLE: [u8; 256] = [
+    1, 87, 49, 12, 176, 178, 102, 166, 121, 193, 6, 84, 249, 230, 44, 163, 14, 197, 213, 181, 161,
+    85, 218, 80, 64, 239, 24, 226, 236, 142, 38, 200, 110, 177, 104, 103, 141, 253, 255, 50, 77,
+    101, 81, 18, 45, 96, 31, 222, 25, 107, 190, 70, 86, 237, 240, 34, 72, 242, 20, 214, 244, 227,
+    149, 235, 97, 234, 57, 22, 60, 250, 82, 175, 208, 5, 127, 199, 111, 62, 135, 248, 174, 169,
+    211, 58, 66, 154, 106, 195, 245, 171, 17, 187, 182, 179, 0, 243, 132, 56, 148, 75, 128, 133,
+    158, 100, 130, 126, 91, 13, 153, 246, 216, 219, 119, 68, 223, 78, 83, 88, 201, 99, 122, 11, 92,
+    32, 136, 114, 52, 10, 138, 30, 48, 183, 156, 35, 61, 26, 143, 74, 251, 94, 129, 162, 63, 152,
+    170, 7, 115, 167, 241, 206, 3, 150, 55, 59, 151, 220, 90, 53, 23, 131, 125, 173, 15, 238, 79,
+    95, 89, 16, 105, 137, 225, 224, 217, 160, 37, 123, 118, 73, 2, 157, 46, 116, 9, 145, 134, 228,
+    207, 212, 202, 215, 69, 229, 27, 188, 67, 124, 168, 252, 42, 4, 29, 108, 21, 247, 19, 205, 39,
+    203, 233, 40, 186, 147, 198, 192, 155, 33, 164, 191, 98, 204, 165, 180, 117, 76, 140, 36, 210,
+    172, 41, 54, 159, 8, 185, 232, 113, 196, 231, 47, 146, 120, 51, 65, 28, 144, 254, 221, 93, 189,
+    194, 139, 112, 43, 71, 109, 184, 209,
+];
+
+fn b_mapping(salt: u8, i: u8, j: u8, k: u8) -> u8 {
+    let h = V_TABLE[salt as usize];
+    let h = V_TABLE[(h ^ i) as usize];
+    let h = V_TABLE[(h ^ j) as usize];
+    V_TABLE[(h ^ k) as usize]
+}
+
+pub struct Hasher {
+    buckets: [u32; 256],
+    checksum: u8,
+    window: [u8; WINDOW],
+    length: u64,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher {
+            buckets: [0; 256],
+            checksum: 0,
+            window: [0; WINDOW],
+            length: 0,
+        }
+    }
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        for &c in data {
+            self.window.rotate_right(1);
+            self.window[0] = c;
+            self.length += 1;
+            if self.length < WINDOW as u64 {
+                continue;
+            }
+            // w[0] is the newest byte, w[4] the oldest
+            let w = self.window;
+            self.checksum = b_mapping(0, w[0], w[1], self.checksum);
+            for (salt, a, b) in [
+                (2, 1, 2),
+                (3, 1, 3),
+                (5, 2, 3),
+                (7, 2, 4),
+                (11, 1, 4),
+                (13, 3, 4),
+            ] {
+                self.buckets[b_mapping(salt, w[0], w[a], w[b]) as usize] += 1;
+            }
+        }
+    }
+
+    /// `T1` and 70 hex digits, or `TNULL` when the input is too short or
+    /// too uniform.
+    pub fn finalize(&self) -> String {
+        let digest = self.digest();
+        match digest {
+            Some(bytes) => format!("T1{}", crate::bytes::hex(&bytes).to_uppercase()),
+            None => "TNULL".to_string(),
+        }
+    }
+
+    fn digest(&self) -> Option<[u8; 3 + CODE_SIZE]> {
+        if self.length < MIN_DATA_LENGTH {
+            return None;
+        }
+        let buckets = &self.buckets[..BUCKETS];
+        if buckets.iter().filter(|&&b| b > 0).count() <= BUCKETS / 2 {
+            return None;
+        }
+        let mut sorted = buckets.to_vec();
+        sorted.sort_unstable();
+        let (q1, q2, q3) = (
+            sorted[BUCKETS / 4 - 1],
+            sorted[BUCKETS / 2 - 1],
+            sorted[BUCKETS * 3 / 4 - 1],
+        );
+        if q3 == 0 {
+            return None;
+        }
+
+        let mut code = [0u8; CODE_SIZE];
+        for (i, byte) in code.iter_mut().enumerate() {
+            for j in 0..4 {
+                let k = buckets[4 * i + j];
+                let quartile = if q3 < k {
+                    3
+                } else if q2 < k {
+                    2
+                } else if q1 < k {
+                    1
+                } else {
+                    0
+                };
+                *byte |= quartile << (j * 2);
+            }
+        }
+        let q1_ratio = ((q1 as f32 * 100.0 / q3 as f32) as u32 % 16) as u8;
+        let q2_ratio = ((q2 as f32 * 100.0 / q3 as f32) as u32 % 16) as u8;
+
+        // header bytes have their nibbles swapped and the body is reversed
+        let swap = |b: u8| b.rotate_left(4);
+        let mut out = [0u8; 3 + CODE_SIZE];
+        out[0] = swap(self.checksum);
+        out[1] = swap(length_code(self.length));
+        out[2] = (q1_ratio << 4) | q2_ratio;
+        for (i, byte) in code.iter().rev().enumerate() {
+            out[3 + i] = *byte;
+        }
+        Some(out)
+    }
+}
+
+/// The log-scale length byte.
+fn length_code(length: u64) -> u8 {
+    let length = length as f64;
+    let code = if length <= 656.0 {
+        (length.ln() / 1.5f64.ln()).floor()
+    } else if length <= 3199.0 {
+        (length.ln() / 1.3f64.ln() - 8.72777).floor()
+    } else {
+        (length.ln() / 1.1f64.ln() - 62.5472).floor()
+    };
+    (code as u64 & 0xFF) as u8
+}
+
+fn parse(digest: &str) -> Option<[u8; 3 + CODE_SIZE]> {
+    let hex = digest.strip_prefix("T1").unwrap_or(digest);
+    if hex.len() != 2 * (3 + CODE_SIZE) {
+        return None;
+    }
+    let mut out = [0u8; 3 + CODE_SIZE];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+fn mod_diff(x: u32, y: u32, range: u32) -> u32 {
+    let (low, high) = if x < y { (x, y) } else { (y, x) };
+    (high - low).min(low + range - high)
+}
+
+/// TLSH distance including the length byte, 0 for identical digests and
+/// growing without a fixed upper bound. Below about 100 is usually related.
+pub fn distance(a: &str, b: &str) -> Option<u32> {
+    let (a, b) = (parse(a)?, parse(b)?);
+    let mut diff = 0;
+    if a[0] != b[0] {
+        diff += 1;
+    }
+    // the length byte is nibble swapped in the digest
+    let length = mod_diff(a[1].rotate_left(4) as u32, b[1].rotate_left(4) as u32, 256);
+    diff += if length <= 1 { length } else { length * 12 };
+    for shift in [4, 0] {
+        let q = mod_diff(
+            (a[2] >> shift & 0x0F) as u32,
+            (b[2] >> shift & 0x0F) as u32,
+            16,
+        );
+        diff += if q <= 1 { q } else { (q - 1) * 12 };
+    }
+    for (x, y) in a[3..].iter().zip(&b[3..]) {
+        for shift in [0, 2, 4, 6] {
+            let d = (x >> shift & 3).abs_diff(y >> shift & 3) as u32;
+            diff += if d == 3 { 6 } else { d };
+        }
+    }
+    Some(diff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Hasher, V_TABLE, distance};
+
+    #[test]
+    fn digests_and_distances() {
+        let mut seen = [false; 256];
+        V_TABLE.iter().for_each(|v| seen[*v as usize] = true);
+        assert!(seen.iter().all(|s| *s), "not a permutation");
+
+        let digest = |data: &[u8]| {
+            let mut hasher = Hasher::default();
+            hasher.update(data);
+            hasher.finalize()
+        };
+        assert_eq!(digest(b"too short"), "TNULL");
+        let text = (0..2_000u32)
+            .map(|i| format!("{} bottles of beer on the wall, {}\n", i, i * 31 % 17))
+            .collect::<String>();
+        let mut edited = text.clone().into_bytes();
+        edited[5_000..5_200].fill(b'#');
+        let original = digest(text.as_bytes());
+        assert_eq!(original.len(), 72);
+        assert_eq!(distance(&original, &original), Some(0));
+        let near = distance(&original, &digest(&edited)).unwrap();
+        let far = distance(
+            &original,
+            &digest(
+                &(0..60_000u32)
+                    .map(|i| (i * i % 251) as u8)
+                    .collect::<Vec<_>>(),
+            ),
+        )
+        .unwrap();
+        assert!(near > 0 && near < far, "{near} {far}");
+    }
+}
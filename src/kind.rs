@@ -49,4 +49,10 @@ pub enum ObjectKind {
     Database = 21,
     /// E-book file
     Book = 22,
+    /// Disk or filesystem image
+    Disk = 23,
+    /// Virtual machine disk image
+    VirtualDisk = 24,
+    /// Columnar, array or scientific dataset
+    Data = 25,
 }
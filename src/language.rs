@@ -0,0 +1,510 @@
+//! Source language of scripts and code from their contents: the `#!` line,
+//! editor modelines, and failing those, which keywords show up how often.
+use crate::{
+    extensions::{CodeExtension, Extension, TextExtension},
+    shebang::Shebang,
+};
+use nu_protocol::{Span, Value, record};
+use std::{fmt, fs::File, io::Read, path::Path};
+
+/// Plenty to tell languages apart.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+/// Vim looks for modelines in the first and last five lines.
+const MODELINE_LINES: usize = 5;
+/// One pattern repeated all over the file shouldn't decide on its own.
+const MAX_PATTERN_COUNT: usize = 5;
+/// Below this the keywords could have come from prose.
+const MIN_SCORE: usize = 6;
+/// Markers a more specific dialect needs before it wins over its base.
+const MIN_DIALECT_MARKERS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Shebang,
+    Modeline,
+    Keywords,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Shebang => "shebang",
+            Source::Modeline => "modeline",
+            Source::Keywords => "keywords",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Language {
+    /// The extension files in this language usually have.
+    pub extension: Extension,
+    pub source: Source,
+}
+
+const fn code(extension: CodeExtension) -> Extension {
+    Extension::Code(extension)
+}
+
+const fn text(extension: TextExtension) -> Extension {
+    Extension::Text(extension)
+}
+
+/// Substrings typical of each language, with their weights.
+const KEYWORDS: &[(Extension, &[(&str, usize)])] = &[
+    (
+        code(CodeExtension::Py),
+        &[
+            ("def ", 1),
+            ("import ", 1),
+            ("self.", 2),
+            ("elif ", 3),
+            ("__init__", 3),
+            ("__name__", 3),
+            ("print(", 1),
+            (" None", 1),
+            ("):\n", 2),
+        ],
+    ),
+    (
+        code(CodeExtension::Rs),
+        &[
+            ("fn ", 2),
+            ("let mut ", 3),
+            ("impl ", 2),
+            ("pub fn ", 3),
+            ("use std::", 3),
+            ("#[derive(", 3),
+            ("&self", 2),
+            ("Some(", 1),
+            ("Ok(", 1),
+            ("-> ", 1),
+        ],
+    ),
+    (
+        code(CodeExtension::Go),
+        &[
+            ("package ", 2),
+            ("func ", 2),
+            (" := ", 2),
+            ("import (", 3),
+            ("fmt.", 2),
+            ("err != nil", 3),
+        ],
+    ),
+    (
+        code(CodeExtension::C),
+        &[
+            ("#include <", 2),
+            ("#include \"", 2),
+            ("#define ", 2),
+            ("#ifdef ", 1),
+            ("#endif", 1),
+            ("typedef ", 2),
+            ("struct ", 1),
+            ("printf(", 1),
+            ("malloc(", 2),
+            ("sizeof(", 1),
+            ("NULL", 1),
+            ("void ", 1),
+        ],
+    ),
+    (
+        code(CodeExtension::Java),
+        &[
+            ("public class ", 3),
+            ("import java.", 3),
+            ("System.out.", 3),
+            ("public static void main", 3),
+            ("@Override", 3),
+            ("private final ", 2),
+            ("package ", 1),
+        ],
+    ),
+    (
+        code(CodeExtension::Js),
+        &[
+            ("function ", 1),
+            ("const ", 1),
+            ("let ", 1),
+            ("=> ", 1),
+            ("require(", 2),
+            ("module.exports", 3),
+            ("console.log", 2),
+            ("document.", 2),
+            ("export ", 1),
+            (" === ", 2),
+        ],
+    ),
+    (
+        code(CodeExtension::Sh),
+        &[
+            ("fi\n", 2),
+            ("then\n", 2),
+            ("esac", 3),
+            ("echo ", 1),
+            ("$(", 1),
+            ("${", 1),
+            ("; do", 2),
+            ("done\n", 2),
+            ("export ", 1),
+        ],
+    ),
+    (
+        code(CodeExtension::Rb),
+        &[
+            ("def ", 1),
+            ("end\n", 1),
+            ("require '", 2),
+            ("require \"", 2),
+            ("puts ", 2),
+            ("attr_accessor", 3),
+            ("elsif ", 3),
+            (" do |", 3),
+        ],
+    ),
+    (code(CodeExtension::Php), &[("<?php", 10), ("$this->", 3)]),
+    (
+        code(CodeExtension::Swift),
+        &[
+            ("import Foundation", 3),
+            ("import UIKit", 3),
+            ("guard let ", 3),
+            ("func ", 1),
+            ("var ", 1),
+            ("@IBOutlet", 3),
+        ],
+    ),
+    (
+        code(CodeExtension::Scala),
+        &[
+            ("object ", 1),
+            ("val ", 1),
+            ("case class ", 3),
+            ("import scala.", 3),
+            ("extends App", 3),
+        ],
+    ),
+    (
+        code(CodeExtension::Dart),
+        &[("import 'package:", 3), ("void main()", 2), ("Widget ", 2)],
+    ),
+    (
+        code(CodeExtension::Html),
+        &[
+            ("<!DOCTYPE html", 10),
+            ("<html", 3),
+            ("<head>", 2),
+            ("<body", 2),
+            ("<div", 1),
+        ],
+    ),
+    (
+        code(CodeExtension::Css),
+        &[
+            ("color:", 1),
+            ("margin:", 1),
+            ("padding:", 1),
+            ("px;", 1),
+            ("@media ", 2),
+            ("font-", 1),
+        ],
+    ),
+    (
+        text(TextExtension::Nu),
+        &[
+            ("| each ", 3),
+            ("| where ", 3),
+            ("| get ", 2),
+            ("| select ", 2),
+            ("$in", 2),
+            ("$env.", 2),
+            ("export def ", 3),
+            ("def --env ", 3),
+        ],
+    ),
+    (
+        text(TextExtension::Ps1),
+        &[
+            ("param(", 2),
+            ("Write-Host", 3),
+            ("Write-Output", 3),
+            ("Get-", 1),
+            (" -eq ", 2),
+            ("[CmdletBinding", 3),
+        ],
+    ),
+    (
+        text(TextExtension::Bat),
+        &[("@echo off", 10), ("%~dp0", 3), ("goto ", 2), ("REM ", 1)],
+    ),
+];
+
+/// Languages that extend another, and what gives them away.
+const DIALECTS: &[(Extension, Extension, &[&str])] = &[
+    (
+        code(CodeExtension::C),
+        code(CodeExtension::Cpp),
+        &[
+            "std::",
+            "namespace ",
+            "template<",
+            "template <",
+            "class ",
+            "public:",
+            "private:",
+            "nullptr",
+            "virtual ",
+            "constexpr ",
+            "#include <iostream>",
+        ],
+    ),
+    (
+        code(CodeExtension::Js),
+        code(CodeExtension::Ts),
+        &[
+            ": string",
+            ": number",
+            ": boolean",
+            ": void",
+            "interface ",
+            "private readonly ",
+            " as const",
+            "implements ",
+            "enum ",
+        ],
+    ),
+    (
+        code(CodeExtension::Sh),
+        code(CodeExtension::Bash),
+        &[
+            "[[ ",
+            "declare ",
+            "function ",
+            "shopt ",
+            "${BASH",
+            "source ",
+        ],
+    ),
+];
+
+impl Language {
+    pub fn detect_file(path: &Path) -> Option<Self> {
+        let mut data = Vec::new();
+        File::open(path)
+            .ok()?
+            .take(SAMPLE_SIZE)
+            .read_to_end(&mut data)
+            .ok()?;
+        Self::detect(&String::from_utf8_lossy(&data))
+    }
+
+    pub fn detect(text: &str) -> Option<Self> {
+        let found = |extension, source| Language { extension, source };
+        if let Some(shebang) = Shebang::parse(text.as_bytes())
+            && let Some(extension) = from_interpreter(&shebang.interpreter)
+        {
+            return Some(found(extension, Source::Shebang));
+        }
+        if let Some(extension) = modeline(text) {
+            return Some(found(extension, Source::Modeline));
+        }
+        keywords(text).map(|extension| found(extension, Source::Keywords))
+    }
+
+    pub fn into_value(&self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "name" => Value::string(self.extension.to_string(), span),
+                "source" => Value::string(self.source.to_string(), span),
+            ),
+            span,
+        )
+    }
+}
+
+/// The language of scripts run by `interpreter`, as in `python3` or `nu`.
+pub fn from_interpreter(interpreter: &str) -> Option<Extension> {
+    // python3.12, ruby2.7, pypy3
+    let name = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match name {
+        "python" | "pypy" => code(CodeExtension::Py),
+        "ruby" | "jruby" => code(CodeExtension::Rb),
+        "node" | "nodejs" | "bun" => code(CodeExtension::Js),
+        "deno" | "ts-node" | "tsx" => code(CodeExtension::Ts),
+        "sh" | "dash" | "ash" | "ksh" | "mksh" => code(CodeExtension::Sh),
+        "bash" => code(CodeExtension::Bash),
+        "zsh" => code(CodeExtension::Zsh),
+        "php" => code(CodeExtension::Php),
+        "scala" => code(CodeExtension::Scala),
+        "swift" => code(CodeExtension::Swift),
+        "dart" => code(CodeExtension::Dart),
+        "java" => code(CodeExtension::Java),
+        "go" | "gorun" => code(CodeExtension::Go),
+        "cargo" | "rust-script" => code(CodeExtension::Rs),
+        "nu" => text(TextExtension::Nu),
+        "pwsh" | "powershell" => text(TextExtension::Ps1),
+        _ => return None,
+    })
+}
+
+/// Language names used by Vim's `filetype` and Emacs' `mode`.
+fn from_name(name: &str) -> Option<Extension> {
+    let name = name.to_lowercase();
+    Some(match name.as_str() {
+        "python" => code(CodeExtension::Py),
+        "rust" => code(CodeExtension::Rs),
+        "c++" => code(CodeExtension::Cpp),
+        "typescript" => code(CodeExtension::Ts),
+        "javascript" | "js2" => code(CodeExtension::Js),
+        "shell-script" => code(CodeExtension::Sh),
+        "ruby" => code(CodeExtension::Rb),
+        "golang" => code(CodeExtension::Go),
+        "markdown" => text(TextExtension::Md),
+        "powershell" => text(TextExtension::Ps1),
+        "dosbatch" => text(TextExtension::Bat),
+        "nushell" => text(TextExtension::Nu),
+        // most names are the extension, `sh` being both text and code
+        "sh" => code(CodeExtension::Sh),
+        name => match Extension::from_str(name)? {
+            crate::magic::ExtensionPossibility::Known(
+                extension @ (Extension::Code(_) | Extension::Text(_)),
+            ) => extension,
+            _ => return None,
+        },
+    })
+}
+
+/// `vim: set ft=python:`, `vi: filetype=c`, `-*- mode: c++ -*-` or `-*- C++ -*-`.
+fn modeline(text: &str) -> Option<Extension> {
+    let lines = text.lines().collect::<Vec<_>>();
+    let head = &lines[..lines.len().min(MODELINE_LINES)];
+    let tail = &lines[lines.len().saturating_sub(MODELINE_LINES)..];
+    head.iter().chain(tail).find_map(|line| {
+        vim_modeline(line)
+            .or_else(|| emacs_modeline(line))
+            .and_then(from_name)
+    })
+}
+
+fn vim_modeline(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(pos, _)| *pos == 0 || line[..*pos].ends_with(char::is_whitespace))
+            .map(|(pos, _)| pos + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            ["ft=", "filetype=", "syntax=", "syn="]
+                .iter()
+                .find_map(|key| option.strip_prefix(key))
+        })
+        .filter(|name| !name.is_empty())
+}
+
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+    let variables = variables.trim();
+    if !variables.contains(':') {
+        return Some(variables);
+    }
+    variables.split(';').find_map(|variable| {
+        let (key, value) = variable.split_once(':')?;
+        (key.trim().eq_ignore_ascii_case("mode")).then(|| value.trim())
+    })
+}
+
+fn keywords(text: &str) -> Option<Extension> {
+    let score = |patterns: &[(&str, usize)]| {
+        patterns
+            .iter()
+            .map(|(pattern, weight)| weight * text.matches(pattern).take(MAX_PATTERN_COUNT).count())
+            .sum::<usize>()
+    };
+    let markers = |extension: Extension| {
+        DIALECTS
+            .iter()
+            .filter(|(base, _, _)| *base == extension)
+            .flat_map(|(_, _, markers)| markers.iter())
+            .map(|marker| text.matches(marker).take(MAX_PATTERN_COUNT).count())
+            .sum::<usize>()
+    };
+    // dialects count towards their base language, then get picked from it
+    let mut scores = KEYWORDS
+        .iter()
+        .map(|(extension, patterns)| (*extension, score(patterns) + markers(*extension)))
+        .collect::<Vec<_>>();
+    scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    let (best, best_score) = *scores.first()?;
+    if best_score < MIN_SCORE || scores.get(1).is_some_and(|(_, s)| *s == best_score) {
+        return None;
+    }
+    let dialect = DIALECTS
+        .iter()
+        .find(|(base, _, markers)| {
+            *base == best
+                && markers
+                    .iter()
+                    .filter(|marker| text.contains(*marker))
+                    .count()
+                    >= MIN_DIALECT_MARKERS
+        })
+        .map(|(_, dialect, _)| *dialect);
+    Some(dialect.unwrap_or(best))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Language, Source};
+    use crate::extensions::{CodeExtension, Extension, TextExtension};
+
+    fn detect(text: &str) -> Option<(Extension, Source)> {
+        Language::detect(text).map(|l| (l.extension, l.source))
+    }
+
+    #[test]
+    fn shebangs_and_modelines() {
+        assert_eq!(
+            detect("#!/usr/bin/env python3\nprint('hi')\n"),
+            Some((Extension::Code(CodeExtension::Py), Source::Shebang))
+        );
+        assert_eq!(
+            detect("#!/usr/bin/env nu\n"),
+            Some((Extension::Text(TextExtension::Nu), Source::Shebang))
+        );
+        assert_eq!(
+            detect("#!/bin/sh\n# vim: set ft=zsh ts=4:\n"),
+            Some((Extension::Code(CodeExtension::Sh), Source::Shebang))
+        );
+        assert_eq!(
+            detect("// -*- mode: C++; indent-tabs-mode: nil -*-\nint x;\n"),
+            Some((Extension::Code(CodeExtension::Cpp), Source::Modeline))
+        );
+        assert_eq!(
+            detect("x = 1\n\n# vim:ft=ruby\n"),
+            Some((Extension::Code(CodeExtension::Rb), Source::Modeline))
+        );
+    }
+
+    #[test]
+    fn keyword_statistics() {
+        let c = "#include <stdio.h>\n#include \"x.h\"\ntypedef struct point { int x; } point;\nvoid f(void);\n";
+        assert_eq!(
+            detect(c),
+            Some((Extension::Code(CodeExtension::C), Source::Keywords))
+        );
+        let cpp = "#include <vector>\nnamespace geo {\nclass Point {\npublic:\n  std::vector<int> v;\n};\n}\n";
+        assert_eq!(
+            detect(cpp),
+            Some((Extension::Code(CodeExtension::Cpp), Source::Keywords))
+        );
+        let ts = "export interface User {\n  name: string;\n  age: number;\n}\nexport const greet = (u: User): void => console.log(u.name);\n";
+        assert_eq!(
+            detect(ts),
+            Some((Extension::Code(CodeExtension::Ts), Source::Keywords))
+        );
+        assert_eq!(detect("Just some prose, nothing to see here.\n"), None);
+    }
+}
@@ -1,6 +1,9 @@
 #![allow(dead_code)]
 
-use crate::extensions::{CodeExtension, Extension, VideoExtension};
+use crate::extensions::{
+    CodeExtension, DocumentExtension, Extension, ImageExtension, KeyExtension, VideoExtension,
+    VirtualDiskExtension,
+};
 use std::fs::File;
 use std::io::Read;
 use std::io::Seek;
@@ -26,6 +29,8 @@ pub struct MagicBytesMeta {
 pub trait MagicBytes: Sized + PartialEq {
     fn has_magic_bytes(&self, buf: &[u8]) -> bool;
     fn magic_bytes_meta(&self) -> Vec<MagicBytesMeta>;
+    /// One matcher per entry of `magic_bytes_meta`, honoring wildcards.
+    fn magic_bytes_matchers(&self) -> Vec<fn(&[u8]) -> bool>;
 }
 
 #[macro_export]
@@ -54,7 +59,7 @@ macro_rules! extension_enum {
 		}
 	) => {
 		// construct enum
-		#[derive(Debug, ::serde::Serialize, ::serde::Deserialize, PartialEq, Eq)]
+		#[derive(Debug, Clone, Copy, ::serde::Serialize, ::serde::Deserialize, PartialEq, Eq)]
 		pub enum Extension {
 			$( $variant($type), )*
 		}
@@ -152,6 +157,13 @@ macro_rules! extension_category_enum {
 					] ),*
 				}
 			}
+			fn magic_bytes_matchers(&self) -> Vec<fn(&[u8]) -> bool> {
+				match self {
+					$( $enum_name::$variant => vec![
+						$( (|buf: &[u8]| matches!(buf, &[$($magic_bytes,)* ..])) as fn(&[u8]) -> bool, )+
+					] ),*
+				}
+			}
 		}
 	};
 	(@magic_bytes; $enum_name:ident ($($(#[$variant_attr:meta])* $variant:ident),*)) => {};
@@ -173,6 +185,30 @@ pub fn verify_magic_bytes<T: MagicBytes>(ext: T, file: &mut File) -> Option<T> {
     None
 }
 
+/// MPEG transport stream packets are 188 bytes and start with a 0x47 sync
+/// byte, M2TS adds a 4 byte timestamp in front of each.
+const TS_PACKET_SIZES: [(usize, usize); 2] = [(188, 0), (192, 4)];
+/// Packets that need their sync byte in place, fewer for shorter files.
+const TS_SYNC_PACKETS: usize = 4;
+const TS_MIN_PACKETS: usize = 2;
+
+fn is_transport_stream(file: &mut File) -> bool {
+    let mut buf = Vec::new();
+    if file.seek(SeekFrom::Start(0)).is_err()
+        || file
+            .by_ref()
+            .take((192 * TS_SYNC_PACKETS) as u64)
+            .read_to_end(&mut buf)
+            .is_err()
+    {
+        return false;
+    }
+    TS_PACKET_SIZES.iter().any(|(size, sync)| {
+        let packets = (buf.len().saturating_sub(*sync) / size).min(TS_SYNC_PACKETS);
+        packets >= TS_MIN_PACKETS && (0..packets).all(|packet| buf[packet * size + sync] == 0x47)
+    })
+}
+
 impl Extension {
     pub fn resolve_conflicting(
         path: impl AsRef<Path>,
@@ -208,20 +244,208 @@ impl Extension {
                 }
             }
             ExtensionPossibility::Conflicts(ext) => match ext_str {
+                // a lone 0x47 is just as likely the `G` of TypeScript
                 "ts" if ext.iter().any(|e| matches!(e, Extension::Video(_))) => {
-                    verify_magic_bytes(VideoExtension::Ts, file)
-                        .map_or(Some(Extension::Code(CodeExtension::Ts)), |video_ext| {
-                            Some(Extension::Video(video_ext))
-                        })
+                    if is_transport_stream(file) {
+                        Some(Extension::Video(VideoExtension::Ts))
+                    } else {
+                        Some(Extension::Code(CodeExtension::Ts))
+                    }
                 }
                 "mts" if ext.iter().any(|e| matches!(e, Extension::Video(_))) => {
-                    verify_magic_bytes(VideoExtension::Mts, file)
-                        .map_or(Some(Extension::Code(CodeExtension::Mts)), |video_ext| {
-                            Some(Extension::Video(video_ext))
-                        })
+                    if is_transport_stream(file) {
+                        Some(Extension::Video(VideoExtension::Mts))
+                    } else {
+                        Some(Extension::Code(CodeExtension::Mts))
+                    }
                 }
+                // camera raw unless there is a partition table
+                "raw" => Some(
+                    match crate::disk::detect(file).and_then(|image| image.table) {
+                        Some(_) => Extension::VirtualDisk(VirtualDiskExtension::Raw),
+                        None => Extension::Image(ImageExtension::Raw),
+                    },
+                ),
+                // Keynote documents are zip files, anything else is key material
+                "key" => Some(
+                    verify_magic_bytes(DocumentExtension::Key, file)
+                        .map(Extension::Document)
+                        .unwrap_or(Extension::Key(KeyExtension::Key)),
+                ),
                 _ => None,
             },
         }
     }
 }
+
+type Matcher = fn(&[u8]) -> bool;
+
+/// Signature bytes that need to match before a hit means anything.
+const MIN_SIGNIFICANT_BYTES: usize = 2;
+/// The same for filesystems, raw disks and datasets, whose two byte
+/// signatures like `55 AA` or a pickle's `80 04` turn up everywhere inside
+/// other files. Their longer ones, ISO 9660's `CD001` or Parquet's `PAR1`,
+/// still count.
+const MIN_SIGNIFICANT_STORAGE_BYTES: usize = 3;
+
+/// One distinct signature and every format that declares it, for scans
+/// that don't start from a file extension.
+pub struct Signature {
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+    /// `false` where the signature has a `_` wildcard.
+    significant: Vec<bool>,
+    pub formats: Vec<Extension>,
+}
+
+impl Signature {
+    /// Whether the signature matches an object starting at `base`.
+    pub fn matches_at(&self, data: &[u8], base: usize) -> bool {
+        let Some(window) = base
+            .checked_add(self.offset)
+            .and_then(|start| data.get(start..start.checked_add(self.bytes.len())?))
+        else {
+            return false;
+        };
+        window
+            .iter()
+            .zip(&self.bytes)
+            .zip(&self.significant)
+            .all(|((a, b), significant)| !significant || a == b)
+    }
+
+    /// Bytes that aren't wildcards.
+    pub fn significant_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.bytes
+            .iter()
+            .zip(&self.significant)
+            .filter(|(_, significant)| **significant)
+            .map(|(b, _)| *b)
+    }
+
+    /// The longest run of literal bytes and where it starts in the
+    /// signature, to search for before checking the rest.
+    pub fn literal(&self) -> (usize, &[u8]) {
+        let mut best = 0..0;
+        let mut start = 0;
+        for i in 0..=self.bytes.len() {
+            if i == self.bytes.len() || !self.significant[i] {
+                if i - start > best.len() {
+                    best = start..i;
+                }
+                start = i + 1;
+            }
+        }
+        (best.start, &self.bytes[best])
+    }
+}
+
+/// Every signature in the extension tables, those sharing the same bytes at
+/// the same offset merged.
+pub static SIGNATURES: std::sync::LazyLock<Vec<Signature>> = std::sync::LazyLock::new(|| {
+    let mut signatures: Vec<Signature> = Vec::new();
+    for ext in Extension::with_magic_bytes() {
+        for (meta, matcher) in ext.signatures() {
+            // a byte is a wildcard when changing it keeps the match
+            let significant = (0..meta.length)
+                .map(|i| {
+                    let mut changed = meta.bytes.clone();
+                    changed[i] ^= 0xFF;
+                    !matcher(&changed)
+                })
+                .collect::<Vec<_>>();
+            let min = match ext {
+                Extension::Disk(_) | Extension::VirtualDisk(_) | Extension::Data(_) => {
+                    MIN_SIGNIFICANT_STORAGE_BYTES
+                }
+                _ => MIN_SIGNIFICANT_BYTES,
+            };
+            if significant.iter().filter(|s| **s).count() < min {
+                continue;
+            }
+            match signatures.iter_mut().find(|s| {
+                s.offset == meta.offset && s.bytes == meta.bytes && s.significant == significant
+            }) {
+                Some(existing) => existing.formats.push(ext),
+                None => signatures.push(Signature {
+                    offset: meta.offset,
+                    bytes: meta.bytes,
+                    significant,
+                    formats: vec![ext],
+                }),
+            }
+        }
+    }
+    signatures
+});
+
+impl Extension {
+    /// Every format in the categories that have magic bytes.
+    fn with_magic_bytes() -> impl Iterator<Item = Extension> {
+        use crate::extensions::*;
+        (ALL_VIDEO_EXTENSIONS.iter().map(|x| Extension::Video(*x)))
+            .chain(ALL_IMAGE_EXTENSIONS.iter().map(|x| Extension::Image(*x)))
+            .chain(_ALL_AUDIO_EXTENSIONS.iter().map(|x| Extension::Audio(*x)))
+            .chain(
+                _ALL_ARCHIVE_EXTENSIONS
+                    .iter()
+                    .map(|x| Extension::Archive(*x)),
+            )
+            .chain(
+                _ALL_EXECUTABLE_EXTENSIONS
+                    .iter()
+                    .map(|x| Extension::Executable(*x)),
+            )
+            .chain(
+                _ALL_DOCUMENT_EXTENSIONS
+                    .iter()
+                    .map(|x| Extension::Document(*x)),
+            )
+            .chain(
+                _ALL_ENCRYPTED_EXTENSIONS
+                    .iter()
+                    .map(|x| Extension::Encrypted(*x)),
+            )
+            .chain(_ALL_FONT_EXTENSIONS.iter().map(|x| Extension::Font(*x)))
+            .chain(_ALL_MESH_EXTENSIONS.iter().map(|x| Extension::Mesh(*x)))
+            .chain(
+                _ALL_DATABASE_EXTENSIONS
+                    .iter()
+                    .map(|x| Extension::Database(*x)),
+            )
+            .chain(_ALL_BOOK_EXTENSIONS.iter().map(|x| Extension::Book(*x)))
+            .chain(_ALL_DISK_EXTENSIONS.iter().map(|x| Extension::Disk(*x)))
+            .chain(
+                _ALL_VIRTUAL_DISK_EXTENSIONS
+                    .iter()
+                    .map(|x| Extension::VirtualDisk(*x)),
+            )
+            .chain(_ALL_DATA_EXTENSIONS.iter().map(|x| Extension::Data(*x)))
+    }
+
+    fn signatures(&self) -> Vec<(MagicBytesMeta, Matcher)> {
+        fn pair<T: MagicBytes>(x: &T) -> Vec<(MagicBytesMeta, Matcher)> {
+            x.magic_bytes_meta()
+                .into_iter()
+                .zip(x.magic_bytes_matchers())
+                .collect()
+        }
+        match self {
+            Self::Video(x) => pair(x),
+            Self::Image(x) => pair(x),
+            Self::Audio(x) => pair(x),
+            Self::Archive(x) => pair(x),
+            Self::Executable(x) => pair(x),
+            Self::Document(x) => pair(x),
+            Self::Encrypted(x) => pair(x),
+            Self::Font(x) => pair(x),
+            Self::Mesh(x) => pair(x),
+            Self::Database(x) => pair(x),
+            Self::Book(x) => pair(x),
+            Self::Disk(x) => pair(x),
+            Self::VirtualDisk(x) => pair(x),
+            Self::Data(x) => pair(x),
+            _ => Vec::new(),
+        }
+    }
+}
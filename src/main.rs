@@ -1,12 +1,27 @@
 // Attribution: spacedrive
 // https://github.com/spacedriveapp/spacedrive/tree/main/crates/file-ext
+pub mod bytes;
+pub mod carve;
+pub mod data;
+pub mod der;
+pub mod disk;
 #[cfg(feature = "executables")]
 pub mod executable;
 pub mod extensions;
+pub mod hash;
+pub mod key;
 pub mod kind;
+pub mod language;
 pub mod magic;
-#[cfg(feature = "executables")]
-use executable::BinaryArch;
+pub mod polyglot;
+pub mod secrets;
+pub mod shebang;
+pub mod strings;
+pub mod text;
+pub mod validate;
+pub mod vm;
+pub mod yara;
+pub mod zip;
 
 use crate::{extensions::Extension, magic::MagicBytes, magic::MagicBytesMeta};
 
@@ -18,7 +33,11 @@ use nu_plugin::{
 use nu_protocol::{
     Category, Example, LabeledError, Signature, Span, Spanned, SyntaxShape, Value, record,
 };
-use std::{fs::File, io::Read, path::Path};
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 struct FilePlugin;
 
@@ -28,7 +47,12 @@ impl Plugin for FilePlugin {
     }
 
     fn commands(&self) -> Vec<Box<dyn PluginCommand<Plugin = Self>>> {
-        vec![Box::new(Implementation)]
+        vec![
+            Box::new(Implementation),
+            Box::new(Strings),
+            Box::new(Similar),
+            Box::new(Carve),
+        ]
     }
 }
 
@@ -61,6 +85,38 @@ impl SimplePluginCommand for Implementation {
                 SyntaxShape::Filepath,
                 "full path to file name to inspect",
             )
+            .switch(
+                "resolve",
+                "resolve the shared library tree of ELF binaries the way the dynamic loader would, without running them",
+                Some('r'),
+            )
+            .switch(
+                "validate",
+                "parse JSON, YAML, TOML, XML, CSV and NUON files and report syntax errors",
+                None,
+            )
+            .switch(
+                "secrets",
+                "scan text files for private keys, access tokens and high entropy passwords",
+                None,
+            )
+            .named(
+                "hash",
+                SyntaxShape::List(Box::new(SyntaxShape::String)),
+                "digests to add as columns: sha256, sha1, md5, blake3, xxh3, ssdeep, tlsh or all",
+                None,
+            )
+            .switch(
+                "polyglot",
+                "report every format the file is valid as, and data appended past the end of its format",
+                None,
+            )
+            .named(
+                "yara",
+                SyntaxShape::Filepath,
+                "directory of YARA rules to match against the file",
+                None,
+            )
             .category(Category::Experimental)
     }
 
@@ -89,229 +145,716 @@ impl SimplePluginCommand for Implementation {
             return Ok(Value::nothing(call.head));
         };
         let span = filename.span;
+        let canon_path = resolve_path(engine, call, filename)?;
 
-        let filename = if filename.item.starts_with('~') {
-            let home_dir = match home_dir() {
-                Some(path) => path,
-                None => {
-                    return Err(LabeledError::new("Cannot find home directory")
-                        .with_label("Cannot find home directory", call.head));
-                }
-            };
-            let Some(home_dir) = home_dir.to_str() else {
-                return Err(
-                    LabeledError::new("Cannot convert home directory to valid UTF-8")
-                        .with_label("Cannot convert home directory to valid UTF-8", span),
-                );
-            };
-            filename.item.replace('~', home_dir)
-        } else if (cfg!(target_family = "unix") && filename.item.starts_with('/'))
-            || (cfg!(target_family = "windows") && is_windows_absolute_path(&filename.item))
-        {
-            filename.item
-        } else {
-            match engine.get_current_dir() {
-                Ok(dir) => dir.to_string() + std::path::MAIN_SEPARATOR_STR + &filename.item,
-                Err(e) => {
-                    return Err(LabeledError::new(e.to_string()).with_label(e.to_string(), span));
-                }
-            }
+        let mut hasher = match call.get_flag::<Vec<Spanned<String>>>("hash")? {
+            Some(names) => Some(hash::Hasher::new(&hash_algorithms(names)?)),
+            None => None,
         };
 
-        let canon_path = match Path::new(&filename).canonicalize() {
-            Ok(path) => path,
-            Err(e) => return Err(LabeledError::new(e.to_string()).with_label(e.to_string(), span)),
+        let rules = match call.get_flag::<Spanned<String>>("yara")? {
+            Some(dir) => {
+                let dir_span = dir.span;
+                let dir = resolve_path(engine, call, dir)?;
+                Some(yara::load(&dir).map_err(|e| {
+                    LabeledError::new("Cannot load YARA rules").with_label(e, dir_span)
+                })?)
+            }
+            None => None,
         };
 
         let file_format = extensions::Extension::resolve_conflicting(&canon_path, true);
-        let mime = infer_mime(&canon_path);
-
-        match file_format {
-            Some(file_format) => match file_format {
-                Extension::Document(document_format) => {
-                    let magic = document_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Document",
-                        document_format.to_string(),
-                        span,
-                        &mime,
-                    ))
-                }
-                Extension::Video(video_format) => {
-                    let magic = video_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Video",
-                        video_format.to_string(),
-                        span,
-                        &mime,
-                    ))
-                }
-                Extension::Image(image_format) => {
-                    let magic = image_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Image",
-                        image_format.to_string(),
-                        span,
-                        &mime,
-                    ))
-                }
-                Extension::Audio(audio_format) => {
-                    let magic = audio_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Audio",
-                        audio_format.to_string(),
-                        span,
-                        &mime,
-                    ))
+        let mime = infer_mime(&canon_path, hasher.as_mut());
+        let mut details = describe(&canon_path, file_format, &mime, engine, call, span)?;
+        if let Some(hasher) = hasher {
+            if !hasher.is_complete() {
+                return Err(LabeledError::new("Cannot hash file")
+                    .with_label("could not read the whole file", span));
+            }
+            if let Value::Record { val, .. } = &mut details {
+                for (algorithm, digest) in hasher.finish() {
+                    val.to_mut()
+                        .push(algorithm.to_string(), Value::string(digest, span));
                 }
-                Extension::Archive(archive_format) => {
-                    let magic = archive_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Archive",
-                        archive_format.to_string(),
-                        span,
-                        &mime,
-                    ))
+            }
+        }
+        let polyglot = call.has_flag("polyglot")?;
+        if rules.is_some() || polyglot {
+            let data = std::fs::read(&canon_path)
+                .map_err(|e| LabeledError::new(e.to_string()).with_label(e.to_string(), span))?;
+            if let Value::Record { val, .. } = &mut details {
+                if let Some(rules) = rules {
+                    let matches = yara::scan(&rules, &data)
+                        .into_iter()
+                        .map(|m| m.into_value(span))
+                        .collect();
+                    val.to_mut().push("yara", Value::list(matches, span));
                 }
-                #[cfg(feature = "executables")]
-                Extension::Executable(_) => {
-                    let bin = crate::executable::Binary::parse(&canon_path).map_err(|e| {
-                        LabeledError::new(e.to_string()).with_label(e.to_string(), span)
-                    })?;
-                    Ok(get_executable_format_details(bin, span, &mime))
+                if polyglot {
+                    let (hits, overlay) = polyglot::scan(&data);
+                    let hits = hits.into_iter().map(|hit| hit.into_value(span)).collect();
+                    val.to_mut().push("polyglot", Value::list(hits, span));
+                    val.to_mut().push(
+                        "overlay",
+                        overlay.map_or(Value::nothing(span), |o| o.into_value(span)),
+                    );
                 }
-                #[cfg(not(feature = "executables"))]
-                Extension::Executable(executable_format) => {
-                    let magic = executable_format.magic_bytes_meta();
-                    return Ok(get_magic_details(
-                        magic,
-                        "Encrypted",
+            }
+        }
+        Ok(details)
+    }
+}
+
+/// The details record for `path`, by category.
+#[cfg_attr(not(feature = "executables"), allow(unused_variables))]
+fn describe(
+    canon_path: &Path,
+    file_format: Option<Extension>,
+    mime: &str,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    span: Span,
+) -> Result<Value, LabeledError> {
+    match file_format {
+        Some(file_format) => match file_format {
+            Extension::Document(document_format) => {
+                let magic = document_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Document",
+                    document_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            Extension::Video(video_format) => {
+                let magic = video_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Video",
+                    video_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            Extension::Image(image_format) => {
+                let magic = image_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Image",
+                    image_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            Extension::Audio(audio_format) => {
+                let magic = audio_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Audio",
+                    audio_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            Extension::Archive(archive_format) => {
+                let magic = archive_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Archive",
+                    archive_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            #[cfg(feature = "executables")]
+            Extension::Executable(executable_format) => {
+                let details = match executable::Container::sniff(canon_path)
+                    .or_else(|| executable::Container::from_extension(executable_format))
+                {
+                    Some(container) => {
+                        get_executable_details(canon_path, container, engine, call, span, mime)?
+                    }
+                    None => None,
+                };
+                // an extension we know, but content we can't take apart
+                Ok(details.unwrap_or_else(|| {
+                    get_magic_details(
+                        executable_format.magic_bytes_meta(),
+                        "Executable",
                         executable_format.to_string(),
                         span,
-                        &mime,
-                    ));
-                }
-                Extension::Text(text_format) => Ok(get_text_format_details(
-                    "Text",
-                    text_format.to_string(),
+                        mime,
+                    )
+                }))
+            }
+            #[cfg(not(feature = "executables"))]
+            Extension::Executable(executable_format) => {
+                let magic = executable_format.magic_bytes_meta();
+                return Ok(get_magic_details(
+                    magic,
+                    "Encrypted",
+                    executable_format.to_string(),
                     span,
-                )),
-                Extension::Encrypted(encrypted_format) => {
-                    let magic = encrypted_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Encrypted",
-                        encrypted_format.to_string(),
-                        span,
-                        &mime,
-                    ))
-                }
-                Extension::Key(key_format) => {
-                    Ok(get_text_format_details("Key", key_format.to_string(), span))
-                }
-                Extension::Font(font_format) => {
-                    let magic = font_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Font",
-                        font_format.to_string(),
-                        span,
-                        &mime,
-                    ))
-                }
-                Extension::Mesh(mesh_format) => {
-                    let magic = mesh_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Mesh",
-                        mesh_format.to_string(),
-                        span,
-                        &mime,
-                    ))
+                    mime,
+                ));
+            }
+            Extension::Text(text_format) => {
+                let mut details =
+                    get_text_format_details(canon_path, "Text", text_format.to_string(), span);
+                if call.has_flag("validate")?
+                    && let Some(result) = validate::validate(canon_path, text_format)
+                    && let Value::Record { val, .. } = &mut details
+                {
+                    val.to_mut()
+                        .push("validation", validate::into_value(&result, span));
                 }
-                Extension::Code(code_format) => Ok(get_text_format_details(
-                    "Code",
-                    code_format.to_string(),
+                push_secrets(&mut details, canon_path, call, span)?;
+                Ok(details)
+            }
+            Extension::Encrypted(encrypted_format) => {
+                let magic = encrypted_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Encrypted",
+                    encrypted_format.to_string(),
                     span,
-                )),
-                Extension::Database(database_format) => {
-                    let magic = database_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Database",
-                        database_format.to_string(),
-                        span,
-                        &mime,
-                    ))
-                }
-                Extension::Book(book_format) => {
-                    let magic = book_format.magic_bytes_meta();
-                    Ok(get_magic_details(
-                        magic,
-                        "Book",
-                        book_format.to_string(),
-                        span,
-                        &mime,
-                    ))
-                }
-            },
-            None => {
-                #[cfg(feature = "executables")]
-                if executable::Binary::has_magic_bytes(&canon_path) {
-                    let bin = crate::executable::Binary::parse(&canon_path).map_err(|e| {
-                        LabeledError::new(e.to_string()).with_label(e.to_string(), span)
-                    })?;
-                    return Ok(get_executable_format_details(bin, span, &mime));
-                }
-                Ok(Value::record(
-                    record!("mime" => Value::string(&mime, call.head)),
-                    call.head,
+                    mime,
                 ))
             }
+            Extension::Key(key_format) => Ok(get_key_details(
+                key::detect_file(canon_path),
+                key_format.to_string(),
+                span,
+                mime,
+            )),
+            Extension::Font(font_format) => {
+                let magic = font_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Font",
+                    font_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            Extension::Mesh(mesh_format) => {
+                let magic = mesh_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Mesh",
+                    mesh_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            Extension::Code(code_format) => {
+                let mut details =
+                    get_text_format_details(canon_path, "Code", code_format.to_string(), span);
+                push_secrets(&mut details, canon_path, call, span)?;
+                Ok(details)
+            }
+            Extension::Database(database_format) => {
+                let magic = database_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Database",
+                    database_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            Extension::Book(book_format) => {
+                let magic = book_format.magic_bytes_meta();
+                Ok(get_magic_details(
+                    magic,
+                    "Book",
+                    book_format.to_string(),
+                    span,
+                    mime,
+                ))
+            }
+            Extension::Disk(disk_format) => {
+                let magic = disk_format.magic_bytes_meta();
+                // cloud images named .img are usually qcow2
+                Ok(if let Some(image) = vm::detect_file(canon_path) {
+                    get_virtual_disk_details(image, magic, span)
+                } else if let Some(image) = disk::detect_file(canon_path) {
+                    get_disk_details(image, magic, span)
+                } else {
+                    get_magic_details(magic, "Disk image", disk_format.to_string(), span, mime)
+                })
+            }
+            Extension::Data(data_format) => {
+                let magic = data_format.magic_bytes_meta();
+                Ok(match data::detect_file(canon_path) {
+                    Some(dataset) => {
+                        get_data_details(dataset, data_format.to_string(), magic, span)
+                    }
+                    None => get_magic_details(magic, "Data", data_format.to_string(), span, mime),
+                })
+            }
+            Extension::VirtualDisk(vm_format) => {
+                let magic = vm_format.magic_bytes_meta();
+                Ok(if let Some(image) = vm::detect_file(canon_path) {
+                    get_virtual_disk_details(image, magic, span)
+                } else if let Some(image) = disk::detect_file(canon_path) {
+                    get_disk_details(image, magic, span)
+                } else {
+                    get_magic_details(magic, "Virtual disk", vm_format.to_string(), span, mime)
+                })
+            }
+        },
+        None => {
+            #[cfg(feature = "executables")]
+            if let Some(container) = executable::Container::sniff(canon_path)
+                && let Some(details) =
+                    get_executable_details(canon_path, container, engine, call, span, mime)?
+            {
+                return Ok(details);
+            }
+            if let Some(dataset) = data::detect_file(canon_path) {
+                let format = dataset.format().to_string();
+                return Ok(get_data_details(dataset, format, Vec::new(), span));
+            }
+            if let Some(image) = vm::detect_file(canon_path) {
+                return Ok(get_virtual_disk_details(image, Vec::new(), span));
+            }
+            if let Some(image) = disk::detect_file(canon_path) {
+                return Ok(get_disk_details(image, Vec::new(), span));
+            }
+            let keys = key::detect_unknown_file(canon_path);
+            if !keys.is_empty() {
+                let format = keys[0].encoding.to_string();
+                return Ok(get_key_details(keys, format, span, mime));
+            }
+            let mut value = record!("mime" => Value::string(mime, call.head));
+            if let Some(text) = text::analyze_file(canon_path) {
+                value.push("text", text.into_value(call.head));
+                if let Some(language) = language::Language::detect_file(canon_path) {
+                    value.push("language", language.into_value(call.head));
+                }
+            }
+            let mut details = Value::record(value, call.head);
+            if mime.starts_with("text/") {
+                push_secrets(&mut details, canon_path, call, span)?;
+            }
+            Ok(details)
+        }
+    }
+}
+
+struct Strings;
+
+impl SimplePluginCommand for Strings {
+    type Plugin = FilePlugin;
+
+    fn name(&self) -> &str {
+        "file strings"
+    }
+
+    fn description(&self) -> &str {
+        "Extract printable ASCII and UTF-16LE strings from a file"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required(
+                "filename",
+                SyntaxShape::Filepath,
+                "full path to file name to extract strings from",
+            )
+            .named(
+                "min-length",
+                SyntaxShape::Int,
+                "minimum number of characters in a string, 4 by default",
+                Some('n'),
+            )
+            .named(
+                "encoding",
+                SyntaxShape::String,
+                "only extract strings in this encoding, ascii or utf-16le",
+                Some('e'),
+            )
+            .category(Category::Experimental)
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Get the wide strings of at least 8 characters in a Windows binary",
+            example: "file strings some.exe --min-length 8 --encoding utf-16le",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        _plugin: &FilePlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: &Value,
+    ) -> Result<Value, LabeledError> {
+        let filename: Spanned<String> = call.req(0)?;
+        let span = filename.span;
+        let canon_path = resolve_path(engine, call, filename)?;
+
+        let min_length = match call.get_flag::<Spanned<i64>>("min-length")? {
+            Some(length) if length.item < 1 => {
+                return Err(LabeledError::new("Invalid minimum length")
+                    .with_label("must be at least 1", length.span));
+            }
+            Some(length) => length.item as usize,
+            None => strings::DEFAULT_MIN_LENGTH,
+        };
+        let encoding = match call.get_flag::<Spanned<String>>("encoding")? {
+            Some(name) => Some(strings::Encoding::parse(&name.item).ok_or_else(|| {
+                LabeledError::new("Unknown encoding")
+                    .with_label("expected ascii or utf-16le", name.span)
+            })?),
+            None => None,
+        };
+
+        let data = std::fs::read(&canon_path)
+            .map_err(|e| LabeledError::new(e.to_string()).with_label(e.to_string(), span))?;
+        #[cfg(feature = "executables")]
+        let sections = executable::section::Section::from_image(&data);
+        Ok(Value::list(
+            strings::extract(&data, min_length, encoding)
+                .into_iter()
+                .map(|found| {
+                    #[allow(unused_mut)]
+                    let mut value = record!(
+                        "offset" => Value::int(found.offset as _, span),
+                        "encoding" => Value::string(found.encoding.to_string(), span),
+                        "string" => Value::string(found.value, span),
+                    );
+                    #[cfg(feature = "executables")]
+                    if !sections.is_empty() {
+                        value.push(
+                            "section",
+                            executable::section::containing(&sections, found.offset)
+                                .map_or(Value::nothing(span), |s| Value::string(&s.name, span)),
+                        );
+                    }
+                    Value::record(value, span)
+                })
+                .collect(),
+            span,
+        ))
+    }
+}
+
+struct Carve;
+
+impl SimplePluginCommand for Carve {
+    type Plugin = FilePlugin;
+
+    fn name(&self) -> &str {
+        "file carve"
+    }
+
+    fn description(&self) -> &str {
+        "Find files embedded anywhere in a file by their magic bytes"
+    }
+
+    fn extra_description(&self) -> &str {
+        "The length is given for formats whose structure says where they end. Short signatures, like MZ, are only reported when that structure checks out."
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required(
+                "filename",
+                SyntaxShape::Filepath,
+                "full path to file name to search",
+            )
+            .named(
+                "extract",
+                SyntaxShape::Filepath,
+                "directory to write each embedded file to",
+                None,
+            )
+            .category(Category::Experimental)
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Extract the images embedded in a firmware blob",
+            example: "file carve firmware.bin --extract carved",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        _plugin: &FilePlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: &Value,
+    ) -> Result<Value, LabeledError> {
+        let filename: Spanned<String> = call.req(0)?;
+        let span = filename.span;
+        let canon_path = resolve_path(engine, call, filename)?;
+        let to_error =
+            |e: std::io::Error| LabeledError::new(e.to_string()).with_label(e.to_string(), span);
+        let file = std::fs::File::open(&canon_path).map_err(to_error)?;
+        // SAFETY: the map must not change while it is borrowed. Nothing here writes to it,
+        // but another process can: a write changes the bytes under the carver, and a
+        // truncation makes reading past the new end raise SIGBUS. That risk is accepted,
+        // as disk images are too big to read into memory and carving a file that's still
+        // being written is already unreliable.
+        let data = unsafe { memmap2::Mmap::map(&file) }.map_err(to_error)?;
+        let carved = carve::carve(&data);
+
+        let paths = match call.get_flag::<Spanned<String>>("extract")? {
+            Some(dir) => {
+                let dir_span = dir.span;
+                // the directory doesn't have to exist yet, so it can't be canonicalized
+                let dir = match engine.get_current_dir() {
+                    Ok(cwd) => Path::new(&cwd).join(nu_path::expand_tilde(&dir.item)),
+                    Err(e) => {
+                        return Err(
+                            LabeledError::new(e.to_string()).with_label(e.to_string(), dir_span)
+                        );
+                    }
+                };
+                carve::extract(&data, &carved, &dir)
+                    .map_err(|e| {
+                        LabeledError::new("Cannot extract files")
+                            .with_label(e.to_string(), dir_span)
+                    })?
+                    .into_iter()
+                    .map(Some)
+                    .collect()
+            }
+            None => vec![None; carved.len()],
+        };
+
+        Ok(Value::list(
+            carved
+                .into_iter()
+                .zip(paths)
+                .map(|(object, path)| {
+                    let mut value = record!(
+                        "offset" => Value::int(object.offset as _, span),
+                        "formats" => Value::list(
+                            object.formats.into_iter().map(|f| Value::string(f, span)).collect(),
+                            span,
+                        ),
+                        "length" => object
+                            .length
+                            .map_or(Value::nothing(span), |l| Value::int(l as _, span)),
+                    );
+                    if let Some(path) = path {
+                        value.push("path", Value::string(path.to_string_lossy(), span));
+                    }
+                    Value::record(value, span)
+                })
+                .collect(),
+            span,
+        ))
+    }
+}
+
+struct Similar;
+
+impl SimplePluginCommand for Similar {
+    type Plugin = FilePlugin;
+
+    fn name(&self) -> &str {
+        "file similar"
+    }
+
+    fn description(&self) -> &str {
+        "Compare two files by their ssdeep and TLSH similarity digests"
+    }
+
+    fn extra_description(&self) -> &str {
+        "ssdeep is a match score from 0 for unrelated to 100 for a near identical file. TLSH is a distance, 0 for identical and growing as the files differ, null when a file is too small or too uniform to digest."
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("a", SyntaxShape::Filepath, "first file to compare")
+            .required("b", SyntaxShape::Filepath, "second file to compare")
+            .category(Category::Experimental)
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Compare two samples",
+            example: "file similar sample1.exe sample2.exe",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        _plugin: &FilePlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: &Value,
+    ) -> Result<Value, LabeledError> {
+        let mut digests = Vec::new();
+        for index in 0..2 {
+            let filename: Spanned<String> = call.req(index)?;
+            let span = filename.span;
+            let canon_path = resolve_path(engine, call, filename)?;
+            let to_error = |e: std::io::Error| {
+                LabeledError::new(e.to_string()).with_label(e.to_string(), span)
+            };
+            let mut hasher = hash::Hasher::new(&[hash::Algorithm::Ssdeep, hash::Algorithm::Tlsh]);
+            hasher
+                .update_from(File::open(&canon_path).map_err(to_error)?)
+                .map_err(to_error)?;
+            digests.push(hasher.finish());
         }
+        let digest = |index: usize, algorithm| {
+            digests[index]
+                .iter()
+                .find(|(a, _)| *a == algorithm)
+                .map(|(_, digest)| digest.as_str())
+                .unwrap_or_default()
+        };
+        let score = |score: Option<u32>| {
+            score.map_or(Value::nothing(call.head), |s| Value::int(s as _, call.head))
+        };
+        Ok(Value::record(
+            record!(
+                "ssdeep" => score(hash::ssdeep::compare(
+                    digest(0, hash::Algorithm::Ssdeep),
+                    digest(1, hash::Algorithm::Ssdeep),
+                )),
+                "tlsh" => score(hash::tlsh::distance(
+                    digest(0, hash::Algorithm::Tlsh),
+                    digest(1, hash::Algorithm::Tlsh),
+                )),
+            ),
+            call.head,
+        ))
     }
 }
 
-fn infer_mime(path: &Path) -> String {
+/// Absolute, canonical form of a path argument, relative paths being
+/// relative to the current directory of the shell.
+fn resolve_path(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    filename: Spanned<String>,
+) -> Result<PathBuf, LabeledError> {
+    let span = filename.span;
+    let filename = if filename.item.starts_with('~') {
+        let home_dir = match home_dir() {
+            Some(path) => path,
+            None => {
+                return Err(LabeledError::new("Cannot find home directory")
+                    .with_label("Cannot find home directory", call.head));
+            }
+        };
+        let Some(home_dir) = home_dir.to_str() else {
+            return Err(
+                LabeledError::new("Cannot convert home directory to valid UTF-8")
+                    .with_label("Cannot convert home directory to valid UTF-8", span),
+            );
+        };
+        filename.item.replace('~', home_dir)
+    } else if (cfg!(target_family = "unix") && filename.item.starts_with('/'))
+        || (cfg!(target_family = "windows") && is_windows_absolute_path(&filename.item))
+    {
+        filename.item
+    } else {
+        match engine.get_current_dir() {
+            Ok(dir) => dir.to_string() + std::path::MAIN_SEPARATOR_STR + &filename.item,
+            Err(e) => {
+                return Err(LabeledError::new(e.to_string()).with_label(e.to_string(), span));
+            }
+        }
+    };
+
+    Path::new(&filename)
+        .canonicalize()
+        .map_err(|e| LabeledError::new(e.to_string()).with_label(e.to_string(), span))
+}
+
+/// Mime type from the first bytes of the file. `hasher` is fed those bytes
+/// and then the rest of the file, so hashing doesn't read it a second time.
+fn infer_mime(path: &Path, hasher: Option<&mut hash::Hasher>) -> String {
     if path.is_dir() {
         return "inode/directory".to_string();
     }
 
-    let mut info = infer::Infer::new();
-    info.add("text/plain", "txt", |buf| std::str::from_utf8(buf).is_ok());
-
     let Ok(mut file) = File::open(path) else {
         return "application/octet-stream".to_string();
     };
-    let mut data = vec![0; 8192];
-    let n = file.read(&mut data).unwrap_or(0);
-    data.truncate(n);
+    // a single read can come back short, on pipes and network filesystems
+    let mut data = Vec::with_capacity(8192);
+    let _ = (&mut file).take(8192).read_to_end(&mut data);
+    if let Some(hasher) = hasher {
+        hasher.update(&data);
+        // an error leaves the hasher incomplete, which the caller reports
+        let _ = hasher.update_from(&mut file);
+    }
 
-    info.get(&data)
+    // text is the fallback, infer's own formats come first
+    infer::get(&data)
         .map(|t| t.mime_type().to_string())
+        .or_else(|| text::detect(&data).map(|_| "text/plain".to_string()))
         .unwrap_or_else(|| "application/octet-stream".to_string())
 }
 
 #[cfg(feature = "executables")]
-fn get_executable_format_details(bin: executable::Binary, span: Span, mime: &str) -> Value {
-    let magics = std::iter::once(bin.magic_bytes.as_ref())
-        .flatten()
-        .chain(
-            bin.arches
-                .iter()
-                .map(|BinaryArch { magic_bytes, .. }| magic_bytes),
-        )
+fn get_executable_details(
+    path: &Path,
+    container: executable::Container,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    span: Span,
+    mime: &str,
+) -> Result<Option<Value>, LabeledError> {
+    // sniffing only looks at the first bytes, so the rest may not parse
+    let Ok(mut bin) = executable::Executable::parse(path, container) else {
+        return Ok(None);
+    };
+    if call.has_flag("resolve")? {
+        bin.resolve_dependencies(path, ld_library_path(engine))
+            .map_err(|e| LabeledError::new(e.clone()).with_label(e, span))?;
+    }
+    Ok(Some(get_executable_format_details(bin, span, mime)))
+}
+
+/// `LD_LIBRARY_PATH` from the nushell environment, which may have been
+/// converted to a list by `ENV_CONVERSIONS`.
+#[cfg(feature = "executables")]
+fn ld_library_path(engine: &EngineInterface) -> Vec<String> {
+    let Ok(Some(value)) = engine.get_env_var("LD_LIBRARY_PATH") else {
+        return Vec::new();
+    };
+    match (value.as_str(), value.as_list()) {
+        (Ok(paths), _) => paths
+            .split(':')
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect(),
+        (_, Ok(paths)) => paths
+            .iter()
+            .filter_map(|p| p.as_str().ok())
+            .map(String::from)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(feature = "executables")]
+fn get_executable_format_details(bin: executable::Executable, span: Span, mime: &str) -> Value {
+    let magics = bin
+        .magic_bytes()
+        .into_iter()
         .map(|magic_bytes| {
             Value::record(
                 record!(
                     "offset" => Value::int(magic_bytes.offset as _, span),
                     "length" => Value::int(magic_bytes.length as _, span),
-                    "bytes" => Value::binary(&magic_bytes.bytes[..], span),
+                    "bytes" => Value::binary(magic_bytes.bytes, span),
                 ),
                 span,
             )
@@ -324,6 +867,7 @@ fn get_executable_format_details(bin: executable::Binary, span: Span, mime: &str
         "mime" => Value::string(mime, span),
         "magics" => Value::list(magics, span),
         "details" => bin.into_value(span),
+        "packing" => bin.packing_value(span),
         ),
         span,
     )
@@ -360,7 +904,110 @@ fn get_magic_details(
     )
 }
 
-fn get_text_format_details(format: &str, text_format: String, span: Span) -> Value {
+/// `--hash` names, `all` standing for every algorithm.
+fn hash_algorithms(names: Vec<Spanned<String>>) -> Result<Vec<hash::Algorithm>, LabeledError> {
+    let mut algorithms = Vec::new();
+    for name in names {
+        if name.item.eq_ignore_ascii_case("all") {
+            algorithms.extend(hash::Algorithm::ALL);
+            continue;
+        }
+        algorithms.push(hash::Algorithm::parse(&name.item).ok_or_else(|| {
+            LabeledError::new("Unknown hash algorithm").with_label(
+                "expected sha256, sha1, md5, blake3, xxh3, ssdeep, tlsh or all",
+                name.span,
+            )
+        })?);
+    }
+    Ok(algorithms)
+}
+
+/// With `--secrets`, add what `secrets::scan` finds in a text file.
+fn push_secrets(
+    details: &mut Value,
+    path: &Path,
+    call: &EvaluatedCall,
+    span: Span,
+) -> Result<(), LabeledError> {
+    if call.has_flag("secrets")?
+        && let Some(text) = text::read_file(path)
+        && let Value::Record { val, .. } = details
+    {
+        let found = secrets::scan(&text)
+            .into_iter()
+            .map(|finding| finding.into_value(span))
+            .collect();
+        val.to_mut().push("secrets", Value::list(found, span));
+    }
+    Ok(())
+}
+
+/// Keys are reported by what is in the file, the extension only names the
+/// format. `mime` is the fallback when nothing was recognized.
+fn get_key_details(
+    keys: Vec<key::KeyMaterial>,
+    key_format: String,
+    span: Span,
+    mime: &str,
+) -> Value {
+    let mime = if keys.is_empty() {
+        mime
+    } else {
+        key::mime(&keys)
+    };
+    Value::record(
+        record!(
+        "description" => Value::string("Key", span),
+        "format" => Value::string(key_format, span),
+        "mime" => Value::string(mime, span),
+        "magics" => Value::nothing(span),
+        "keys" => Value::list(keys.into_iter().map(|k| k.into_value(span)).collect(), span),
+        ),
+        span,
+    )
+}
+
+fn get_disk_details(image: disk::DiskImage, magic: Vec<MagicBytesMeta>, span: Span) -> Value {
+    let format = image.format().to_string();
+    let mut details = get_magic_details(magic, "Disk image", format, span, image.mime());
+    if let Value::Record { val, .. } = &mut details {
+        val.to_mut().extend(image.into_record(span));
+    }
+    details
+}
+
+fn get_virtual_disk_details(
+    image: vm::VirtualDisk,
+    magic: Vec<MagicBytesMeta>,
+    span: Span,
+) -> Value {
+    let mut details = get_magic_details(
+        magic,
+        "Virtual disk",
+        image.format.to_string(),
+        span,
+        image.mime(),
+    );
+    if let Value::Record { val, .. } = &mut details {
+        val.to_mut().extend(image.into_record(span));
+    }
+    details
+}
+
+fn get_data_details(
+    dataset: data::Dataset,
+    format: String,
+    magic: Vec<MagicBytesMeta>,
+    span: Span,
+) -> Value {
+    let mut details = get_magic_details(magic, "Data", format, span, dataset.mime());
+    if let Value::Record { val, .. } = &mut details {
+        val.to_mut().extend(dataset.into_record(span));
+    }
+    details
+}
+
+fn get_text_format_details(path: &Path, format: &str, text_format: String, span: Span) -> Value {
     let mime = format!(
         "text/{}",
         if text_format == "txt" {
@@ -375,6 +1022,8 @@ fn get_text_format_details(format: &str, text_format: String, span: Span) -> Val
         "format" => Value::string(text_format, span),
         "mime" => Value::string(mime, span),
         "magics" => Value::nothing(span),
+        "text" => text::analyze_file(path).map_or(Value::nothing(span), |t| t.into_value(span)),
+        "language" => language::Language::detect_file(path).map_or(Value::nothing(span), |l| l.into_value(span)),
         ),
         span,
     )
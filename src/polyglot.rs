@@ -0,0 +1,585 @@
+//! Files that are valid as more than one format, and data appended past the
+//! logical end of a file's format.
+use crate::bytes::{u16_be, u16_le, u32_be, u32_le, u64_be, u64_le};
+use crate::magic::SIGNATURES;
+use aho_corasick::AhoCorasick;
+use nu_protocol::{Span, Value, record};
+use std::collections::{BTreeMap, HashMap};
+
+/// Readers accept a PDF header this far into the file.
+const PDF_HEADER_WINDOW: usize = 1024;
+/// End of central directory record and the longest comment it can have.
+const ZIP_EOCD_LENGTH: usize = 22;
+const ZIP_EOCD_WINDOW: usize = ZIP_EOCD_LENGTH + u16::MAX as usize;
+
+/// Formats whose signatures match an object starting at `offset`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hit {
+    pub offset: usize,
+    pub formats: Vec<String>,
+}
+
+impl Hit {
+    pub fn into_value(self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "offset" => Value::int(self.offset as _, span),
+                "formats" => formats_value(self.formats, span),
+            ),
+            span,
+        )
+    }
+}
+
+/// Bytes past the logical end of the file's format.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Overlay {
+    pub offset: usize,
+    pub size: usize,
+    pub formats: Vec<String>,
+}
+
+impl Overlay {
+    pub fn into_value(self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "offset" => Value::int(self.offset as _, span),
+                "size" => Value::int(self.size as _, span),
+                "formats" => formats_value(self.formats, span),
+            ),
+            span,
+        )
+    }
+}
+
+fn formats_value(formats: Vec<String>, span: Span) -> Value {
+    Value::list(
+        formats
+            .into_iter()
+            .map(|f| Value::string(f, span))
+            .collect(),
+        span,
+    )
+}
+
+/// Every format the file satisfies, tried at the start, where readers look
+/// for formats that don't have to start the file, and at the end of the
+/// format at the start.
+pub fn scan(data: &[u8]) -> (Vec<Hit>, Option<Overlay>) {
+    let mut offsets = vec![0];
+    if let Some(pdf) = find(&data[..data.len().min(PDF_HEADER_WINDOW)], b"%PDF-") {
+        offsets.push(pdf);
+    }
+    if let Some(zip) = zip_start(data) {
+        offsets.push(zip);
+    }
+    let overlay = length(data)
+        .filter(|&end| end < data.len() && data[end..].iter().any(|&b| b != 0))
+        .map(|end| Overlay {
+            offset: end,
+            size: data.len() - end,
+            formats: formats_at(data, end),
+        });
+    if let Some(overlay) = &overlay {
+        offsets.push(overlay.offset);
+    }
+    offsets.sort_unstable();
+    offsets.dedup();
+    let hits = offsets
+        .into_iter()
+        .map(|offset| Hit {
+            offset,
+            formats: formats_at(data, offset),
+        })
+        .filter(|hit| !hit.formats.is_empty())
+        .collect();
+    (hits, overlay)
+}
+
+/// Names of the formats with a signature matching at `base`.
+pub fn formats_at(data: &[u8], base: usize) -> Vec<String> {
+    let mut formats: Vec<String> = Vec::new();
+    for signature in SIGNATURES.iter().filter(|s| s.matches_at(data, base)) {
+        for format in &signature.formats {
+            let name = format.to_string();
+            if !formats.contains(&name) {
+                formats.push(name);
+            }
+        }
+    }
+    formats
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Where a zip archive starts according to its central directory, which
+/// readers find from the end of the file whatever comes before it.
+fn zip_start(data: &[u8]) -> Option<usize> {
+    let tail = data.len().saturating_sub(ZIP_EOCD_WINDOW);
+    let eocd = tail + data[tail..].windows(4).rposition(|w| w == b"PK\x05\x06")?;
+    let size = u32_le(data, eocd + 12)? as usize;
+    let offset = u32_le(data, eocd + 16)? as usize;
+    let start = eocd.checked_sub(size)?.checked_sub(offset)?;
+    data[start..].starts_with(b"PK\x03\x04").then_some(start)
+}
+
+/// Length of the object at the start of `data` according to its own
+/// structure, for the formats where that's cheap to work out. It can be
+/// longer than `data` when the object is truncated.
+pub fn length(data: &[u8]) -> Option<usize> {
+    match data {
+        [b'M', b'Z', ..] => pe_length(data),
+        [0x7F, b'E', b'L', b'F', ..] => elf_length(data),
+        [0xFF, 0xD8, 0xFF, ..] => jpeg_length(data),
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => png_length(data),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => gif_length(data),
+        [b'B', b'M', ..] => Some(u32_le(data, 2)? as usize).filter(|&size| size >= 26),
+        [b'R', b'I', b'F', b'F', ..] => Some(u32_le(data, 4)? as usize + 8),
+        [b'%', b'P', b'D', b'F', b'-', ..] => pdf_length(data),
+        [b'P', b'K', 0x03, 0x04, ..] => zip_length(data),
+        [b'P', b'A', b'R', b'1', ..] => (4..data.len())
+            .filter(|&i| data[i..].starts_with(b"PAR1"))
+            .filter_map(|magic| parquet_trailer(data, magic))
+            .filter(|&(footer, _)| footer >= 4)
+            .min()
+            .map(|(_, end)| end),
+        [b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C, ..] => {
+            let next_offset = u64_le(data, 12)?;
+            let next_size = u64_le(data, 20)?;
+            usize::try_from(next_offset.checked_add(next_size)?.checked_add(32)?).ok()
+        }
+        _ if data.starts_with(b"SQLite format 3\0") => {
+            let page_size = match u16_be(data, 16)? {
+                1 => 65536,
+                size => size as usize,
+            };
+            Some(page_size * u32_be(data, 28)? as usize).filter(|&size| size > 0)
+        }
+        _ => None,
+    }
+}
+
+/// Headers, sections and a certificate table right after them. Anything
+/// else is an overlay, which is where installers keep their payload.
+fn pe_length(data: &[u8]) -> Option<usize> {
+    let pe = u32_le(data, 0x3C)? as usize;
+    if data.get(pe..pe + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let sections = u16_le(data, pe + 6)? as usize;
+    let optional_size = u16_le(data, pe + 20)? as usize;
+    let optional = pe + 24;
+    let mut end = u32_le(data, optional + 60)? as usize;
+    let table = optional + optional_size;
+    for i in 0..sections {
+        let header = table + i * 40;
+        let size = u32_le(data, header + 16)? as usize;
+        let pointer = u32_le(data, header + 20)? as usize;
+        if size > 0 {
+            end = end.max(pointer + size);
+        }
+    }
+    let directories = match u16_le(data, optional)? {
+        0x20B => optional + 112,
+        _ => optional + 96,
+    };
+    let certificates = u32_le(data, directories + 4 * 8)? as usize;
+    let certificates_size = u32_le(data, directories + 4 * 8 + 4)? as usize;
+    // the table is 8 byte aligned after the last section
+    if certificates_size > 0 && certificates >= end && certificates - end < 8 {
+        end = certificates + certificates_size;
+    }
+    Some(end)
+}
+
+fn elf_length(data: &[u8]) -> Option<usize> {
+    let wide = match data.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let big_endian = *data.get(5)? == 2;
+    let half = |offset| {
+        if big_endian {
+            u16_be(data, offset)
+        } else {
+            u16_le(data, offset)
+        }
+        .map(|v| v as usize)
+    };
+    let word = |offset| {
+        if big_endian {
+            u32_be(data, offset)
+        } else {
+            u32_le(data, offset)
+        }
+        .map(|v| v as usize)
+    };
+    let address = |offset| {
+        if !wide {
+            word(offset)
+        } else if big_endian {
+            u64_be(data, offset).and_then(|v| usize::try_from(v).ok())
+        } else {
+            u64_le(data, offset).and_then(|v| usize::try_from(v).ok())
+        }
+    };
+    let (phoff, shoff, phentsize, phnum, shentsize, shnum) = if wide {
+        (
+            address(32)?,
+            address(40)?,
+            half(54)?,
+            half(56)?,
+            half(58)?,
+            half(60)?,
+        )
+    } else {
+        (
+            address(28)?,
+            address(32)?,
+            half(42)?,
+            half(44)?,
+            half(46)?,
+            half(48)?,
+        )
+    };
+    let mut end = phoff
+        .checked_add(phentsize.checked_mul(phnum)?)?
+        .max(shoff.checked_add(shentsize.checked_mul(shnum)?)?);
+    for i in 0..phnum {
+        let header = phoff.checked_add(i * phentsize)?;
+        let (offset, size) = if wide {
+            (
+                address(header.checked_add(8)?)?,
+                address(header.checked_add(32)?)?,
+            )
+        } else {
+            (
+                address(header.checked_add(4)?)?,
+                address(header.checked_add(16)?)?,
+            )
+        };
+        end = end.max(offset.checked_add(size)?);
+    }
+    for i in 0..shnum {
+        let header = shoff.checked_add(i * shentsize)?;
+        // SHT_NOBITS sections take no space in the file
+        if word(header.checked_add(4)?)? == 8 {
+            continue;
+        }
+        let (offset, size) = if wide {
+            (
+                address(header.checked_add(24)?)?,
+                address(header.checked_add(32)?)?,
+            )
+        } else {
+            (
+                address(header.checked_add(16)?)?,
+                address(header.checked_add(20)?)?,
+            )
+        };
+        end = end.max(offset.checked_add(size)?);
+    }
+    Some(end)
+}
+
+/// Segments up to the end of image marker, skipping over entropy coded
+/// scan data. Thumbnails inside APP segments are skipped with them.
+fn jpeg_length(data: &[u8]) -> Option<usize> {
+    let mut i = 2;
+    loop {
+        while *data.get(i + 1)? == 0xFF {
+            i += 1;
+        }
+        if *data.get(i)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(i + 1)?;
+        match marker {
+            0xD9 => return Some(i + 2),
+            0x01 | 0xD0..=0xD7 => i += 2,
+            _ => {
+                i += 2 + u16_be(data, i + 2)? as usize;
+                if marker == 0xDA {
+                    while *data.get(i)? != 0xFF || matches!(data.get(i + 1)?, 0x00 | 0xD0..=0xD7) {
+                        i += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn png_length(data: &[u8]) -> Option<usize> {
+    let mut i = 8;
+    loop {
+        let length = u32_be(data, i)? as usize;
+        let kind = data.get(i + 4..i + 8)?;
+        i += 12 + length;
+        if kind == b"IEND" {
+            return Some(i);
+        }
+    }
+}
+
+fn gif_length(data: &[u8]) -> Option<usize> {
+    let color_table = |flags: u8| {
+        if flags & 0x80 != 0 {
+            3 << ((flags & 0x07) + 1)
+        } else {
+            0
+        }
+    };
+    let sub_blocks = |mut i: usize| loop {
+        let size = *data.get(i)? as usize;
+        i += 1 + size;
+        if size == 0 {
+            return Some(i);
+        }
+    };
+    let mut i = 13 + color_table(*data.get(10)?);
+    loop {
+        match *data.get(i)? {
+            0x3B => return Some(i + 1),
+            0x21 => i = sub_blocks(i + 2)?,
+            0x2C => {
+                i += 10 + color_table(*data.get(i + 9)?);
+                i = sub_blocks(i + 1)?;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Up to the last `%%EOF` and its line ending, incremental updates
+/// included.
+fn pdf_length(data: &[u8]) -> Option<usize> {
+    pdf_end(data, data.windows(5).rposition(|w| w == b"%%EOF")?)
+}
+
+/// The end of the `%%EOF` marker at `eof` and its line ending.
+fn pdf_end(data: &[u8], eof: usize) -> Option<usize> {
+    let eof = eof + 5;
+    let eol = data
+        .get(eof..)?
+        .iter()
+        .take(2)
+        .take_while(|&&b| b == b'\r' || b == b'\n')
+        .count();
+    Some(eof + eol)
+}
+
+/// Up to the end of the central directory record whose offsets put the
+/// archive at the start of `data`.
+fn zip_length(data: &[u8]) -> Option<usize> {
+    let mut from = 0;
+    while let Some(found) = find(&data[from..], b"PK\x05\x06") {
+        let eocd = from + found;
+        if let Some((0, end)) = zip_span(data, eocd) {
+            return Some(end);
+        }
+        from = eocd + 4;
+    }
+    None
+}
+
+/// Where the archive of the end of central directory record at `eocd`
+/// starts according to its offsets, and where the record ends.
+fn zip_span(data: &[u8], eocd: usize) -> Option<(usize, usize)> {
+    let size = u32_le(data, eocd + 12)? as usize;
+    let offset = u32_le(data, eocd + 16)? as usize;
+    let end = eocd + ZIP_EOCD_LENGTH + u16_le(data, eocd + 20)? as usize;
+    Some((eocd.checked_sub(offset.checked_add(size)?)?, end))
+}
+
+/// Where the footer before a Parquet trailer's `PAR1` at `magic` starts,
+/// and where the file ends. The nearest footer after a header is its own.
+fn parquet_trailer(data: &[u8], magic: usize) -> Option<(usize, usize)> {
+    let footer = u32_le(data, magic.checked_sub(4)?)? as usize;
+    if footer == 0 {
+        return None;
+    }
+    Some((magic.checked_sub(4 + footer)?, magic + 4))
+}
+
+/// The ends of zip archives, PDFs and Parquet files, found in one pass so
+/// that measuring objects all over the data doesn't search the rest of it
+/// every time.
+pub struct Ends {
+    /// End of the first central directory pointing back to each start.
+    zips: HashMap<usize, usize>,
+    last_pdf_eof: Option<usize>,
+    /// End of the first trailer after each Parquet footer start.
+    parquets: BTreeMap<usize, usize>,
+}
+
+impl Ends {
+    pub fn new(data: &[u8]) -> Self {
+        let mut ends = Ends {
+            zips: HashMap::new(),
+            last_pdf_eof: None,
+            parquets: BTreeMap::new(),
+        };
+        let markers =
+            AhoCorasick::new([&b"PK\x05\x06"[..], b"%%EOF", b"PAR1"]).expect("valid markers");
+        for found in markers.find_iter(data) {
+            match found.pattern().as_usize() {
+                0 => {
+                    if let Some((start, end)) = zip_span(data, found.start()) {
+                        ends.zips.entry(start).or_insert(end);
+                    }
+                }
+                1 => ends.last_pdf_eof = Some(found.start()),
+                _ => {
+                    if let Some((footer, end)) = parquet_trailer(data, found.start()) {
+                        ends.parquets.entry(footer).or_insert(end);
+                    }
+                }
+            }
+        }
+        ends
+    }
+
+    /// The same as `length(&data[offset..])`.
+    pub fn length(&self, data: &[u8], offset: usize) -> Option<usize> {
+        let rest = &data[offset..];
+        if rest.starts_with(b"PK\x03\x04") {
+            return self.zips.get(&offset).map(|end| end - offset);
+        }
+        if rest.starts_with(b"%PDF-") {
+            let eof = self.last_pdf_eof.filter(|&eof| eof >= offset)?;
+            return pdf_end(data, eof).map(|end| end - offset);
+        }
+        if rest.starts_with(b"PAR1") {
+            let (_, end) = self.parquets.range(offset + 4..).next()?;
+            return Some(end - offset);
+        }
+        length(rest)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn zip(prefix: &[u8]) -> Vec<u8> {
+        let mut data = prefix.to_vec();
+        let start = data.len() as u32;
+        data.extend_from_slice(b"PK\x03\x04");
+        data.extend_from_slice(&[0; 26]);
+        let directory = data.len() as u32;
+        data.extend_from_slice(b"PK\x01\x02");
+        data.extend_from_slice(&[0; 42]);
+        let size = data.len() as u32 - directory;
+        data.extend_from_slice(b"PK\x05\x06");
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(&(directory - start).to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data
+    }
+
+    #[test]
+    fn appended_zip() {
+        let pdf = b"%PDF-1.4\n1 0 obj\n<<>>\nendobj\ntrailer\n<<>>\n%%EOF\n";
+        let data = zip(pdf);
+        let (hits, overlay) = scan(&data);
+        assert_eq!(hits[0].offset, 0);
+        assert_eq!(hits[0].formats, ["pdf"]);
+        assert_eq!(hits[1].offset, pdf.len());
+        assert!(hits[1].formats.contains(&"zip".to_string()));
+        assert_eq!(
+            overlay.map(|o| (o.offset, o.size)),
+            Some((pdf.len(), data.len() - pdf.len()))
+        );
+        assert_eq!(length(&data[pdf.len()..]), Some(data.len() - pdf.len()));
+        let ends = Ends::new(&data);
+        assert_eq!(ends.length(&data, 0), length(&data));
+        assert_eq!(ends.length(&data, pdf.len()), length(&data[pdf.len()..]));
+    }
+
+    #[test]
+    fn image_lengths() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 0]);
+        png.extend_from_slice(b"IEND");
+        png.extend_from_slice(&[0xAE, 0x42, 0x60, 0x82]);
+        assert_eq!(length(&png), Some(png.len()));
+
+        let jpeg =
+            b"\xFF\xD8\xFF\xE0\x00\x04JF\xFF\xDA\x00\x02\x12\xFF\x00\x34\xFF\xD0\x56\xFF\xD9";
+        let mut data = jpeg.to_vec();
+        data.extend_from_slice(b"hidden payload");
+        assert_eq!(length(&data), Some(jpeg.len()));
+        let (_, overlay) = scan(&data);
+        assert_eq!(
+            overlay,
+            Some(Overlay {
+                offset: jpeg.len(),
+                size: 14,
+                formats: Vec::new()
+            })
+        );
+
+        let gif = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff\
+                    \x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00\x3b";
+        assert_eq!(length(gif), Some(gif.len()));
+    }
+
+    #[test]
+    fn parquet_trailers() {
+        let parquet = |footer: &[u8]| {
+            let mut data = b"PAR1 column chunks ".to_vec();
+            data.extend_from_slice(footer);
+            data.extend_from_slice(&(footer.len() as u32).to_le_bytes());
+            data.extend_from_slice(b"PAR1");
+            data
+        };
+        let first = parquet(b"footer");
+        let data = [first.clone(), parquet(b"another footer")].concat();
+        assert_eq!(length(&data), Some(first.len()));
+        assert_eq!(length(&data[first.len()..]), Some(data.len() - first.len()));
+        let ends = Ends::new(&data);
+        assert_eq!(ends.length(&data, 0), length(&data));
+        assert_eq!(
+            ends.length(&data, first.len()),
+            length(&data[first.len()..])
+        );
+        // the trailer comes out with the file rather than as a file of its own
+        let carved = crate::carve::carve(&data);
+        assert_eq!(
+            carved.iter().map(|c| c.offset).collect::<Vec<_>>(),
+            [0, first.len()]
+        );
+    }
+
+    #[test]
+    fn short_signatures() {
+        // a pickle opcode and a boot sector signature, both two bytes
+        let mut data = b"\x80\x04 plain data".to_vec();
+        data.resize(512, b' ');
+        data[510..].copy_from_slice(&[0x55, 0xAA]);
+        let (hits, _) = scan(&data);
+        assert!(hits.is_empty());
+        // while an ISO 9660 volume descriptor still makes a hybrid
+        data.resize(0x8006, 0);
+        data[0x8001..].copy_from_slice(b"CD001");
+        let (hits, _) = scan(&data);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].offset, 0);
+        assert!(hits[0].formats.iter().any(|f| f == "iso"));
+    }
+
+    #[test]
+    fn elf_overflow() {
+        // a section header table at the very end of the address space
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        elf.resize(64, 0);
+        elf[40..48].copy_from_slice(&u64::MAX.to_le_bytes());
+        elf[58..60].copy_from_slice(&64u16.to_le_bytes());
+        elf[60..62].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(length(&elf), None);
+    }
+}
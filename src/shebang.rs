@@ -0,0 +1,38 @@
+/// A parsed `#!` interpreter line.
+pub struct Shebang {
+    /// The interpreter as written, e.g. `/usr/bin/env`.
+    pub path: String,
+    /// The program that actually runs the script, with `env` and its options
+    /// looked through, e.g. `python3`.
+    pub interpreter: String,
+    pub args: Vec<String>,
+}
+
+impl Shebang {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let line = buf.strip_prefix(b"#!")?;
+        let end = line.iter().position(|b| *b == b'\n').unwrap_or(line.len());
+        let line = std::str::from_utf8(&line[..end]).ok()?.trim();
+        let mut words = line.split_whitespace();
+        let path = words.next()?.to_string();
+        let mut args = words.map(String::from).collect::<Vec<_>>();
+        let mut interpreter = basename(&path).to_string();
+        if interpreter == "env" {
+            // skip `env -S`, `env -i`, `env VAR=value` and friends
+            let position = args
+                .iter()
+                .position(|a| !a.starts_with('-') && !a.contains('='))?;
+            interpreter = basename(&args[position]).to_string();
+            args.drain(..=position);
+        }
+        Some(Shebang {
+            path,
+            interpreter,
+            args,
+        })
+    }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
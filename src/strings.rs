@@ -0,0 +1,128 @@
+//! Printable string extraction, along the lines of binutils `strings`.
+use std::fmt;
+
+/// Same default as `strings`.
+pub const DEFAULT_MIN_LENGTH: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Ascii,
+    Utf16Le,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "ascii" => Some(Encoding::Ascii),
+            "utf-16le" | "utf16le" | "utf-16" | "utf16" => Some(Encoding::Utf16Le),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Ascii => "ascii",
+            Encoding::Utf16Le => "utf-16le",
+        })
+    }
+}
+
+pub struct FoundString {
+    pub offset: usize,
+    pub encoding: Encoding,
+    pub value: String,
+}
+
+fn is_printable(byte: u8) -> bool {
+    byte == b'\t' || (0x20..=0x7E).contains(&byte)
+}
+
+/// Strings of at least `min_length` characters, ordered by offset.
+/// `encoding` limits the search to one encoding.
+pub fn extract(data: &[u8], min_length: usize, encoding: Option<Encoding>) -> Vec<FoundString> {
+    let mut found = Vec::new();
+    if encoding.is_none_or(|e| e == Encoding::Ascii) {
+        ascii(data, min_length, &mut found);
+    }
+    if encoding.is_none_or(|e| e == Encoding::Utf16Le) {
+        // a string can start on either byte
+        utf16le(data, 0, min_length, &mut found);
+        utf16le(data, 1, min_length, &mut found);
+    }
+    found.sort_by_key(|s| s.offset);
+    found
+}
+
+fn ascii(data: &[u8], min_length: usize, found: &mut Vec<FoundString>) {
+    let mut start = 0;
+    for (pos, byte) in data.iter().chain([&0]).enumerate() {
+        if is_printable(*byte) {
+            continue;
+        }
+        if pos - start >= min_length {
+            found.push(FoundString {
+                offset: start,
+                encoding: Encoding::Ascii,
+                value: String::from_utf8_lossy(&data[start..pos]).into_owned(),
+            });
+        }
+        start = pos + 1;
+    }
+}
+
+/// Only the Latin subset, which is what shows up in Windows binaries.
+fn utf16le(data: &[u8], alignment: usize, min_length: usize, found: &mut Vec<FoundString>) {
+    let mut start = alignment;
+    let mut value = String::new();
+    let mut pos = alignment;
+    loop {
+        let unit = data.get(pos..pos + 2);
+        if let Some(&[byte, 0]) = unit
+            && is_printable(byte)
+        {
+            value.push(byte as char);
+        } else {
+            if value.len() >= min_length {
+                found.push(FoundString {
+                    offset: start,
+                    encoding: Encoding::Utf16Le,
+                    value: std::mem::take(&mut value),
+                });
+            }
+            value.clear();
+            start = pos + 2;
+        }
+        if unit.is_none() {
+            break;
+        }
+        pos += 2;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Encoding, extract};
+
+    #[test]
+    fn both_encodings() {
+        let mut data = b"\x00\x01hello world\x00ab\x00".to_vec();
+        data.extend(b"\xffw\0i\0d\0e\0\0\0");
+        let found = extract(&data, 4, None);
+        let found = found
+            .iter()
+            .map(|s| (s.offset, s.encoding, s.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (2, Encoding::Ascii, "hello world"),
+                (18, Encoding::Utf16Le, "wide")
+            ]
+        );
+        assert!(extract(&data, 4, Some(Encoding::Ascii)).len() == 1);
+        assert!(extract(b"abc", 4, None).is_empty());
+        assert_eq!(extract(b"abcd", 4, None)[0].value, "abcd");
+    }
+}
@@ -0,0 +1,390 @@
+//! Encoding and layout of text files: which Unicode or legacy encoding the
+//! bytes are in, what ends the lines, and anything that doesn't belong.
+use nu_protocol::{Span, Value, record};
+use std::{fmt, fs::File, io::Read, path::Path};
+
+/// Enough for line statistics of anything that is reasonably a text file.
+const MAX_TEXT_SIZE: u64 = 16 * 1024 * 1024;
+/// Share of NUL and control characters above which data is binary.
+const MAX_CONTROL_RATIO: f64 = 0.05;
+/// Legacy 8-bit text is still mostly ASCII.
+const MIN_ASCII_RATIO: f64 = 0.7;
+/// Share of code units that need a zero byte where Latin text in UTF-16 or
+/// UTF-32 without a byte order mark has one.
+const MIN_WIDE_RATIO: f64 = 0.5;
+
+/// What windows-1252 puts in the C1 range, `None` for the five holes.
+const WINDOWS_1252: [Option<char>; 32] = [
+    Some('€'),
+    None,
+    Some('‚'),
+    Some('ƒ'),
+    Some('„'),
+    Some('…'),
+    Some('†'),
+    Some('‡'),
+    Some('ˆ'),
+    Some('‰'),
+    Some('Š'),
+    Some('‹'),
+    Some('Œ'),
+    None,
+    Some('Ž'),
+    None,
+    None,
+    Some('‘'),
+    Some('’'),
+    Some('“'),
+    Some('”'),
+    Some('•'),
+    Some('–'),
+    Some('—'),
+    Some('˜'),
+    Some('™'),
+    Some('š'),
+    Some('›'),
+    Some('œ'),
+    None,
+    Some('ž'),
+    Some('Ÿ'),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Ascii,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+    /// 8-bit text with nothing in the C1 range.
+    Latin1,
+    /// 8-bit text using the C1 range the way Windows does.
+    Windows1252,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Ascii => "ascii",
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Utf32Le => "utf-32le",
+            Encoding::Utf32Be => "utf-32be",
+            Encoding::Latin1 => "iso-8859-1",
+            Encoding::Windows1252 => "windows-1252",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEndings {
+    Lf,
+    Crlf,
+    Cr,
+    Mixed,
+}
+
+impl fmt::Display for LineEndings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LineEndings::Lf => "lf",
+            LineEndings::Crlf => "crlf",
+            LineEndings::Cr => "cr",
+            LineEndings::Mixed => "mixed",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct TextInfo {
+    pub encoding: Encoding,
+    pub bom: bool,
+    /// `None` when there is a single line.
+    pub line_endings: Option<LineEndings>,
+    pub lines: usize,
+    /// `None` when only the start of a large file was looked at.
+    pub trailing_newline: Option<bool>,
+    /// In characters, without the line ending.
+    pub longest_line: usize,
+    pub nul: bool,
+    /// Control characters other than NUL and whitespace.
+    pub control: bool,
+}
+
+/// The encoding of `buf` and whether it starts with a byte order mark,
+/// `None` for binary data. `buf` may be cut anywhere, even mid-character.
+pub fn detect(buf: &[u8]) -> Option<(Encoding, bool)> {
+    decode(buf).map(|(encoding, bom, _)| (encoding, bom))
+}
+
+/// Analyze the text file at `path`, `None` if it isn't one.
+pub fn analyze_file(path: &Path) -> Option<TextInfo> {
+    let (data, complete) = read_start(path)?;
+    analyze(&data, complete)
+}
+
+/// The decoded text of the file at `path`, as far as we look into it.
+pub fn read_file(path: &Path) -> Option<String> {
+    let (data, _) = read_start(path)?;
+    decode(&data).map(|(_, _, text)| text)
+}
+
+/// Up to `MAX_TEXT_SIZE` bytes and whether that was the whole file.
+fn read_start(path: &Path) -> Option<(Vec<u8>, bool)> {
+    let mut data = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(MAX_TEXT_SIZE + 1)
+        .read_to_end(&mut data)
+        .ok()?;
+    let complete = data.len() as u64 <= MAX_TEXT_SIZE;
+    data.truncate(MAX_TEXT_SIZE as usize);
+    Some((data, complete))
+}
+
+/// `complete` tells whether `data` is the whole file.
+pub fn analyze(data: &[u8], complete: bool) -> Option<TextInfo> {
+    let (encoding, bom, text) = decode(data)?;
+    let mut info = TextInfo {
+        encoding,
+        bom,
+        line_endings: None,
+        lines: 0,
+        trailing_newline: complete.then(|| text.ends_with(['\n', '\r'])),
+        longest_line: 0,
+        nul: text.contains('\0'),
+        control: text.chars().any(|c| c != '\0' && is_odd_control(c)),
+    };
+    let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+    let mut line = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                crlf += 1;
+            }
+            '\r' => cr += 1,
+            '\n' => lf += 1,
+            _ => {
+                line += 1;
+                continue;
+            }
+        }
+        info.longest_line = info.longest_line.max(line);
+        info.lines += 1;
+        line = 0;
+    }
+    if line > 0 {
+        info.longest_line = info.longest_line.max(line);
+        info.lines += 1;
+    }
+    info.line_endings = match (lf > 0, crlf > 0, cr > 0) {
+        (false, false, false) => None,
+        (true, false, false) => Some(LineEndings::Lf),
+        (false, true, false) => Some(LineEndings::Crlf),
+        (false, false, true) => Some(LineEndings::Cr),
+        _ => Some(LineEndings::Mixed),
+    };
+    Some(info)
+}
+
+impl TextInfo {
+    pub fn into_value(&self, span: Span) -> Value {
+        Value::record(
+            record!(
+                "encoding" => Value::string(self.encoding.to_string(), span),
+                "bom" => Value::bool(self.bom, span),
+                "line_endings" => self.line_endings.map_or(Value::nothing(span), |l| Value::string(l.to_string(), span)),
+                "lines" => Value::int(self.lines as _, span),
+                "trailing_newline" => self.trailing_newline.map_or(Value::nothing(span), |t| Value::bool(t, span)),
+                "longest_line" => Value::int(self.longest_line as _, span),
+                "nul" => Value::bool(self.nul, span),
+                "control_characters" => Value::bool(self.control, span),
+            ),
+            span,
+        )
+    }
+}
+
+fn is_odd_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0b' | '\x0c')
+}
+
+fn looks_like_text(text: &str) -> bool {
+    let (mut total, mut odd) = (0usize, 0usize);
+    for c in text.chars() {
+        total += 1;
+        odd += usize::from(is_odd_control(c));
+    }
+    odd as f64 <= total as f64 * MAX_CONTROL_RATIO
+}
+
+fn decode(buf: &[u8]) -> Option<(Encoding, bool, String)> {
+    let boms: [(&[u8], Encoding); 5] = [
+        // UTF-32LE before UTF-16LE, they start alike
+        (b"\xFF\xFE\x00\x00", Encoding::Utf32Le),
+        (b"\x00\x00\xFE\xFF", Encoding::Utf32Be),
+        (b"\xEF\xBB\xBF", Encoding::Utf8),
+        (b"\xFF\xFE", Encoding::Utf16Le),
+        (b"\xFE\xFF", Encoding::Utf16Be),
+    ];
+    if let Some((bom, encoding)) = boms.iter().find(|(bom, _)| buf.starts_with(bom)) {
+        let text = decode_as(&buf[bom.len()..], *encoding)?;
+        return looks_like_text(&text).then_some((*encoding, true, text));
+    }
+    let encoding = if buf.is_ascii() {
+        Encoding::Ascii
+    } else if utf8_prefix(buf).is_some() {
+        Encoding::Utf8
+    } else if let Some(wide) = wide_encoding(buf) {
+        wide
+    } else {
+        let ascii = buf.iter().filter(|b| b.is_ascii()).count();
+        if buf.contains(&0) || (ascii as f64 / buf.len() as f64) < MIN_ASCII_RATIO {
+            return None;
+        }
+        let mut c1 = buf.iter().filter(|b| (0x80..0xA0).contains(*b)).peekable();
+        if c1.peek().is_some() && c1.all(|&b| WINDOWS_1252[b as usize - 0x80].is_some()) {
+            Encoding::Windows1252
+        } else {
+            Encoding::Latin1
+        }
+    };
+    // ASCII with a zero every other byte is likely wide
+    let encoding = match encoding {
+        Encoding::Ascii | Encoding::Utf8 => wide_encoding(buf).unwrap_or(encoding),
+        _ => encoding,
+    };
+    let text = decode_as(buf, encoding)?;
+    looks_like_text(&text).then_some((encoding, false, text))
+}
+
+/// The valid UTF-8 in `buf`, allowing for a character cut off at the end.
+fn utf8_prefix(buf: &[u8]) -> Option<&str> {
+    match std::str::from_utf8(buf) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&buf[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    }
+}
+
+/// UTF-16 and UTF-32 without a BOM, told apart by where the zero bytes of
+/// Latin characters go.
+fn wide_encoding(buf: &[u8]) -> Option<Encoding> {
+    [
+        Encoding::Utf32Le,
+        Encoding::Utf32Be,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+    ]
+    .into_iter()
+    .find(|&encoding| {
+        let width = if matches!(encoding, Encoding::Utf32Le | Encoding::Utf32Be) {
+            4
+        } else {
+            2
+        };
+        let units = buf.chunks_exact(width);
+        let count = units.len();
+        let latin = units
+            .filter(|u| match encoding {
+                Encoding::Utf32Le => u[0] != 0 && u[1..] == [0, 0, 0],
+                Encoding::Utf32Be => u[3] != 0 && u[..3] == [0, 0, 0],
+                Encoding::Utf16Le => u[0] != 0 && u[1] == 0,
+                _ => u[0] == 0 && u[1] != 0,
+            })
+            .count();
+        count > 0
+            && latin as f64 >= count as f64 * MIN_WIDE_RATIO
+            && decode_as(buf, encoding).is_some()
+    })
+}
+
+fn decode_as(buf: &[u8], encoding: Encoding) -> Option<String> {
+    match encoding {
+        Encoding::Ascii | Encoding::Utf8 => utf8_prefix(buf).map(String::from),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let units = buf.chunks_exact(2).map(|u| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([u[0], u[1]]),
+                _ => u16::from_be_bytes([u[0], u[1]]),
+            });
+            let mut text = String::new();
+            let mut decoded = char::decode_utf16(units).peekable();
+            while let Some(c) = decoded.next() {
+                match c {
+                    Ok(c) => text.push(c),
+                    // half a surrogate pair at the very end was cut off
+                    Err(_) if decoded.peek().is_none() => {}
+                    Err(_) => return None,
+                }
+            }
+            Some(text)
+        }
+        Encoding::Utf32Le | Encoding::Utf32Be => buf
+            .chunks_exact(4)
+            .map(|u| {
+                let u = [u[0], u[1], u[2], u[3]];
+                char::from_u32(match encoding {
+                    Encoding::Utf32Le => u32::from_le_bytes(u),
+                    _ => u32::from_be_bytes(u),
+                })
+            })
+            .collect(),
+        Encoding::Latin1 => Some(buf.iter().map(|&b| b as char).collect()),
+        Encoding::Windows1252 => buf
+            .iter()
+            .map(|&b| match b {
+                0x80..0xA0 => WINDOWS_1252[b as usize - 0x80],
+                _ => Some(b as char),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Encoding, LineEndings, analyze, detect};
+
+    #[test]
+    fn encodings() {
+        assert_eq!(detect(b"plain"), Some((Encoding::Ascii, false)));
+        assert_eq!(detect(b"caf\xC3\xA9"), Some((Encoding::Utf8, false)));
+        // cut in the middle of the é
+        assert_eq!(detect(b"caf\xC3"), Some((Encoding::Utf8, false)));
+        assert_eq!(detect(b"caf\xE9 cr\xE8me"), Some((Encoding::Latin1, false)));
+        assert_eq!(
+            detect(b"\x93quoted\x94"),
+            Some((Encoding::Windows1252, false))
+        );
+        assert_eq!(detect(b"\xFF\xFEh\0i\0"), Some((Encoding::Utf16Le, true)));
+        assert_eq!(detect(b"h\0i\0!\0"), Some((Encoding::Utf16Le, false)));
+        assert_eq!(detect(b"\0h\0i"), Some((Encoding::Utf16Be, false)));
+        assert_eq!(detect(b"h\0\0\0i\0\0\0"), Some((Encoding::Utf32Le, false)));
+        assert_eq!(detect(b"\xEF\xBB\xBFbom"), Some((Encoding::Utf8, true)));
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\x01"), None);
+    }
+
+    #[test]
+    fn lines() {
+        let info = analyze(b"one\r\ntwo\r\nthree", true).unwrap();
+        assert_eq!(info.line_endings, Some(LineEndings::Crlf));
+        assert_eq!((info.lines, info.longest_line), (3, 5));
+        assert_eq!(info.trailing_newline, Some(false));
+
+        let info = analyze(b"one\ntwo\r\n", true).unwrap();
+        assert_eq!(info.line_endings, Some(LineEndings::Mixed));
+        assert_eq!(info.trailing_newline, Some(true));
+        assert_eq!(info.lines, 2);
+
+        let info = analyze(
+            b"\x1b[1mbold\x1b[0m and a NUL\0 somewhere in a long enough line of text",
+            false,
+        )
+        .unwrap();
+        assert!(info.nul && info.control);
+        assert_eq!((info.line_endings, info.trailing_newline), (None, None));
+    }
+}
@@ -0,0 +1,468 @@
+//! Syntax checks for structured text formats, with a short summary of what
+//! a valid file holds.
+use crate::extensions::TextExtension;
+use nu_protocol::{
+    Span, Value,
+    ast::{Expr, Expression, ListItem, RecordItem},
+    engine::{EngineState, StateWorkingSet},
+    record,
+};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Delimiters tried on CSV files, the first one wins ties.
+const CSV_DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+/// Lines looked at to pick a delimiter.
+const CSV_SNIFF_LINES: usize = 20;
+
+pub enum Summary {
+    /// JSON, YAML, TOML and NUON, in nushell type names.
+    Tree {
+        kind: &'static str,
+        /// For records only.
+        keys: Option<usize>,
+        /// YAML streams can hold more than one document.
+        documents: Option<usize>,
+    },
+    Xml {
+        root: String,
+        namespace: Option<String>,
+    },
+    Csv {
+        delimiter: char,
+        header: Option<Vec<String>>,
+        columns: usize,
+        rows: usize,
+    },
+}
+
+pub struct SyntaxError {
+    pub message: String,
+    /// Both 1-based, `None` when the parser didn't say.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl SyntaxError {
+    fn at_offset(message: impl ToString, text: &str, offset: usize) -> Self {
+        let (line, column) = line_column(text, offset);
+        SyntaxError {
+            message: message.to_string(),
+            line: Some(line),
+            column: Some(column),
+        }
+    }
+}
+
+/// Parse `path` as `format`, `None` for formats that have no syntax to check.
+pub fn validate(path: &Path, format: TextExtension) -> Option<Result<Summary, SyntaxError>> {
+    let parse: fn(&str) -> Result<Summary, SyntaxError> = match format {
+        TextExtension::Json => json,
+        TextExtension::Yaml | TextExtension::Yml => yaml,
+        TextExtension::Toml => toml,
+        TextExtension::Xml => xml,
+        TextExtension::Csv => csv,
+        TextExtension::Nuon => nuon,
+        _ => return None,
+    };
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            return Some(Err(SyntaxError {
+                message: e.to_string(),
+                line: None,
+                column: None,
+            }));
+        }
+    };
+    Some(match std::str::from_utf8(&data) {
+        Ok(text) => parse(text.strip_prefix('\u{FEFF}').unwrap_or(text)),
+        Err(e) => {
+            let valid = std::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or_default();
+            Err(SyntaxError::at_offset(
+                "invalid UTF-8",
+                valid,
+                e.valid_up_to(),
+            ))
+        }
+    })
+}
+
+pub fn into_value(result: &Result<Summary, SyntaxError>, span: Span) -> Value {
+    let optional = |n: Option<usize>| n.map_or(Value::nothing(span), |n| Value::int(n as _, span));
+    let value = match result {
+        Ok(summary) => {
+            let mut value = record!("valid" => Value::bool(true, span));
+            match summary {
+                Summary::Tree {
+                    kind,
+                    keys,
+                    documents,
+                } => {
+                    value.push("type", Value::string(*kind, span));
+                    value.push("keys", optional(*keys));
+                    if let Some(documents) = documents {
+                        value.push("documents", Value::int(*documents as _, span));
+                    }
+                }
+                Summary::Xml { root, namespace } => {
+                    value.push("root", Value::string(root, span));
+                    value.push(
+                        "namespace",
+                        namespace
+                            .as_ref()
+                            .map_or(Value::nothing(span), |n| Value::string(n, span)),
+                    );
+                }
+                Summary::Csv {
+                    delimiter,
+                    header,
+                    columns,
+                    rows,
+                } => {
+                    value.push("delimiter", Value::string(delimiter.to_string(), span));
+                    value.push(
+                        "header",
+                        header.as_ref().map_or(Value::nothing(span), |header| {
+                            Value::list(
+                                header.iter().map(|h| Value::string(h, span)).collect(),
+                                span,
+                            )
+                        }),
+                    );
+                    value.push("columns", Value::int(*columns as _, span));
+                    value.push("rows", Value::int(*rows as _, span));
+                }
+            }
+            value
+        }
+        Err(error) => record!(
+            "valid" => Value::bool(false, span),
+            "error" => Value::string(&error.message, span),
+            "line" => optional(error.line),
+            "column" => optional(error.column),
+        ),
+    };
+    Value::record(value, span)
+}
+
+/// 1-based line and column, in characters, of a byte offset.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text.as_bytes()[..offset.min(text.len())];
+    let line_start = before
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |pos| pos + 1);
+    let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+    let column = String::from_utf8_lossy(&before[line_start..])
+        .chars()
+        .count()
+        + 1;
+    (line, column)
+}
+
+fn json(text: &str) -> Result<Summary, SyntaxError> {
+    let value: serde_json::Value = serde_json::from_str(text).map_err(|e| SyntaxError {
+        message: e.to_string(),
+        line: Some(e.line()),
+        column: Some(e.column()),
+    })?;
+    let (kind, keys) = match &value {
+        serde_json::Value::Object(map) => ("record", Some(map.len())),
+        serde_json::Value::Array(_) => ("list", None),
+        serde_json::Value::String(_) => ("string", None),
+        serde_json::Value::Number(n) if n.is_f64() => ("float", None),
+        serde_json::Value::Number(_) => ("int", None),
+        serde_json::Value::Bool(_) => ("bool", None),
+        serde_json::Value::Null => ("nothing", None),
+    };
+    Ok(Summary::Tree {
+        kind,
+        keys,
+        documents: None,
+    })
+}
+
+fn yaml(text: &str) -> Result<Summary, SyntaxError> {
+    let mut documents = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(text) {
+        documents.push(serde_yaml::Value::deserialize(document).map_err(|e| {
+            let location = e.location();
+            SyntaxError {
+                message: e.to_string(),
+                line: location.as_ref().map(|l| l.line()),
+                column: location.as_ref().map(|l| l.column()),
+            }
+        })?);
+    }
+    let (kind, keys) = match documents.first() {
+        Some(serde_yaml::Value::Mapping(map)) => ("record", Some(map.len())),
+        Some(serde_yaml::Value::Sequence(_)) => ("list", None),
+        Some(serde_yaml::Value::String(_)) => ("string", None),
+        Some(serde_yaml::Value::Number(n)) if n.is_f64() => ("float", None),
+        Some(serde_yaml::Value::Number(_)) => ("int", None),
+        Some(serde_yaml::Value::Bool(_)) => ("bool", None),
+        Some(serde_yaml::Value::Tagged(_)) => ("tagged", None),
+        Some(serde_yaml::Value::Null) | None => ("nothing", None),
+    };
+    Ok(Summary::Tree {
+        kind,
+        keys,
+        documents: Some(documents.len()),
+    })
+}
+
+fn toml(text: &str) -> Result<Summary, SyntaxError> {
+    let table: toml::Table = toml::from_str(text).map_err(|e| match e.span() {
+        Some(span) => SyntaxError::at_offset(e.message(), text, span.start),
+        None => SyntaxError {
+            message: e.message().to_string(),
+            line: None,
+            column: None,
+        },
+    })?;
+    Ok(Summary::Tree {
+        kind: "record",
+        keys: Some(table.len()),
+        documents: None,
+    })
+}
+
+fn xml(text: &str) -> Result<Summary, SyntaxError> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let document = roxmltree::Document::parse_with_options(text, options).map_err(|e| {
+        let pos = e.pos();
+        SyntaxError {
+            message: e.to_string(),
+            line: Some(pos.row as usize),
+            column: Some(pos.col as usize),
+        }
+    })?;
+    let root = document.root_element();
+    Ok(Summary::Xml {
+        root: root.tag_name().name().to_string(),
+        namespace: root.tag_name().namespace().map(String::from),
+    })
+}
+
+fn csv(text: &str) -> Result<Summary, SyntaxError> {
+    let delimiter = csv_delimiter(text);
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .from_reader(text.as_bytes());
+    let mut records = Vec::new();
+    for record in reader.records() {
+        records.push(record.map_err(|e| match e.position() {
+            Some(pos) => SyntaxError::at_offset(e.to_string(), text, pos.byte() as usize),
+            None => SyntaxError {
+                message: e.to_string(),
+                line: None,
+                column: None,
+            },
+        })?);
+    }
+    let header = records
+        .first()
+        .filter(|first| csv_has_header(first, &records[1..]))
+        .map(|first| first.iter().map(String::from).collect::<Vec<_>>());
+    Ok(Summary::Csv {
+        delimiter: delimiter as char,
+        columns: records.first().map_or(0, |r| r.len()),
+        rows: records.len() - usize::from(header.is_some()),
+        header,
+    })
+}
+
+/// The candidate that splits the first lines into the same, largest number
+/// of fields, ignoring anything quoted.
+fn csv_delimiter(text: &str) -> u8 {
+    let lines = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .take(CSV_SNIFF_LINES)
+        .collect::<Vec<_>>();
+    let count = |line: &str, delimiter: u8| {
+        let mut quoted = false;
+        line.bytes()
+            .filter(|b| {
+                if *b == b'"' {
+                    quoted = !quoted;
+                }
+                !quoted && *b == delimiter
+            })
+            .count()
+    };
+    CSV_DELIMITERS
+        .iter()
+        .filter_map(|&delimiter| {
+            let first = count(lines.first()?, delimiter);
+            (first > 0 && lines.iter().all(|l| count(l, delimiter) == first))
+                .then_some((delimiter, first))
+        })
+        // max_by_key keeps the last of equals, so go backwards
+        .rev()
+        .max_by_key(|(_, fields)| *fields)
+        .map_or(b',', |(delimiter, _)| delimiter)
+}
+
+/// Column names are distinct, non-empty and not numbers, and don't show up
+/// again below.
+fn csv_has_header(first: &csv::StringRecord, rows: &[csv::StringRecord]) -> bool {
+    let names = first.iter().collect::<Vec<_>>();
+    let distinct = names
+        .iter()
+        .enumerate()
+        .all(|(i, name)| !names[..i].contains(name));
+    distinct
+        && names
+            .iter()
+            .all(|name| !name.trim().is_empty() && name.trim().parse::<f64>().is_err())
+        && names
+            .iter()
+            .enumerate()
+            .all(|(column, name)| rows.iter().all(|row| row.get(column) != Some(name)))
+}
+
+fn nuon(text: &str) -> Result<Summary, SyntaxError> {
+    let engine_state = EngineState::new();
+    let mut working_set = StateWorkingSet::new(&engine_state);
+    let base = working_set.next_span_start();
+    let block = nu_parser::parse(&mut working_set, None, text.as_bytes(), false);
+    let error_at = |message: String, span: nu_protocol::Span| {
+        SyntaxError::at_offset(message, text, span.start.saturating_sub(base))
+    };
+    if let Some(error) = working_set.parse_errors.first() {
+        return Err(error_at(error.to_string(), error.span()));
+    }
+    let mut expressions = block
+        .pipelines
+        .iter()
+        .flat_map(|pipeline| &pipeline.elements)
+        .map(|element| &element.expr);
+    let Some(expression) = expressions.next() else {
+        return Ok(Summary::Tree {
+            kind: "nothing",
+            keys: None,
+            documents: None,
+        });
+    };
+    if let Some(extra) = expressions.next() {
+        return Err(error_at("more than one value".to_string(), extra.span));
+    }
+    nuon_constant(expression).map_err(|span| error_at("not a constant value".to_string(), span))?;
+    let expression = match &expression.expr {
+        Expr::FullCellPath(path) => &path.head,
+        _ => expression,
+    };
+    let (kind, keys) = match &expression.expr {
+        Expr::Record(items) => ("record", Some(items.len())),
+        Expr::List(_) => ("list", None),
+        Expr::Table(_) => ("table", None),
+        Expr::String(_) | Expr::RawString(_) => ("string", None),
+        Expr::Int(_) => ("int", None),
+        Expr::Float(_) => ("float", None),
+        Expr::Bool(_) => ("bool", None),
+        Expr::Binary(_) => ("binary", None),
+        Expr::DateTime(_) => ("datetime", None),
+        Expr::Range(_) => ("range", None),
+        Expr::ValueWithUnit(_) => ("quantity", None),
+        _ => ("nothing", None),
+    };
+    Ok(Summary::Tree {
+        kind,
+        keys,
+        documents: None,
+    })
+}
+
+/// NUON is data only: the span of the first expression that would need
+/// evaluating, if any.
+fn nuon_constant(expression: &Expression) -> Result<(), nu_protocol::Span> {
+    match &expression.expr {
+        Expr::Bool(_)
+        | Expr::Int(_)
+        | Expr::Float(_)
+        | Expr::Binary(_)
+        | Expr::String(_)
+        | Expr::RawString(_)
+        | Expr::Filepath(..)
+        | Expr::GlobPattern(..)
+        | Expr::Directory(..)
+        | Expr::DateTime(_)
+        | Expr::ValueWithUnit(_)
+        | Expr::Range(_)
+        | Expr::Nothing => Ok(()),
+        Expr::List(items) => items.iter().try_for_each(|item| match item {
+            ListItem::Item(expression) => nuon_constant(expression),
+            ListItem::Spread(span, _) => Err(*span),
+        }),
+        Expr::Record(items) => items.iter().try_for_each(|item| match item {
+            RecordItem::Pair(key, value) => nuon_constant(key).and_then(|_| nuon_constant(value)),
+            RecordItem::Spread(span, _) => Err(*span),
+        }),
+        // `[1 2]` on its own is a cell path without any members
+        Expr::FullCellPath(path) if path.tail.is_empty() => nuon_constant(&path.head),
+        Expr::Table(table) => table
+            .columns
+            .iter()
+            .chain(table.rows.iter().flatten())
+            .try_for_each(nuon_constant),
+        _ => Err(expression.span),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Summary, csv, json, nuon, toml, yaml};
+
+    #[test]
+    fn error_positions() {
+        let error = json("{\n  \"a\": 1,\n  \"b\" 2\n}").err().unwrap();
+        assert_eq!((error.line, error.column), (Some(3), Some(7)));
+        let error = toml("a = 1\nb = \n").err().unwrap();
+        assert_eq!(error.line, Some(2));
+        let error = yaml("a: 1\n b: 2\n").err().unwrap();
+        assert_eq!(error.line, Some(2));
+        let error = nuon("{a: 1}\n[1 (ls)]").err().unwrap();
+        assert_eq!(error.line, Some(2));
+    }
+
+    #[test]
+    fn summaries() {
+        assert!(matches!(
+            nuon("{a: 1, b: [1 2kb], c: [[x]; [1]]}"),
+            Ok(Summary::Tree {
+                kind: "record",
+                keys: Some(3),
+                ..
+            })
+        ));
+        assert!(matches!(
+            yaml("---\n- a\n---\nb: 1\n"),
+            Ok(Summary::Tree {
+                kind: "list",
+                documents: Some(2),
+                ..
+            })
+        ));
+        let Ok(Summary::Csv {
+            delimiter,
+            header,
+            columns,
+            rows,
+        }) = csv("name;size\n\"a;b\";1\nc;2\n")
+        else {
+            panic!("not a CSV summary");
+        };
+        assert_eq!((delimiter, columns, rows), (';', 2, 2));
+        assert_eq!(header, Some(vec!["name".to_string(), "size".to_string()]));
+        let Ok(Summary::Csv { header, rows, .. }) = csv("1,2\n3,4\n") else {
+            panic!("not a CSV summary");
+        };
+        assert_eq!((header, rows), (None, 2));
+    }
+}
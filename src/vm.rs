@@ -0,0 +1,674 @@
+//! Virtual machine disk images: qcow2, VMDK, VHD, VHDX and VDI headers.
+use crate::bytes::{padded_str, u16_le, u32_be, u32_le, u64_be, u64_le};
+use crate::disk::{self, DiskImage, guid, read_at};
+use nu_protocol::{Record, Span, Value, record};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const SECTOR: u64 = 512;
+const QCOW_MAGIC: &[u8] = b"QFI\xFB";
+const VMDK_SPARSE_MAGIC: &[u8] = b"KDMV";
+const VMDK_DESCRIPTOR: &[u8] = b"# Disk DescriptorFile";
+/// Descriptors are a few hundred bytes, this keeps a corrupt one cheap.
+const MAX_DESCRIPTOR: u64 = 64 * 1024;
+const VHD_COOKIE: &[u8] = b"conectix";
+const VHDX_SIGNATURE: &[u8] = b"vhdxfile";
+const VHDX_HEADER: u64 = 64 * 1024;
+const VHDX_REGION_TABLE: u64 = 192 * 1024;
+const VHDX_METADATA_REGION: &str = "8B7CA206-4790-4B9A-B8FE-575F050F886E";
+const VHDX_FILE_PARAMETERS: &str = "CAA16737-FA36-4D43-B3B6-33F0AA44E76B";
+const VHDX_VIRTUAL_DISK_SIZE: &str = "2FA54224-CD1B-4876-B211-5DBED83BF4B8";
+const VHDX_LOGICAL_SECTOR_SIZE: &str = "8141BF1D-A96F-4709-BA47-F233A8FAAB5F";
+const VHDX_PARENT_LOCATOR: &str = "A8D35F2D-B30B-454D-ABF7-D3D84834AB0C";
+const VDI_SIGNATURE: u32 = 0xBEDA107F;
+
+pub struct VirtualDisk {
+    pub format: &'static str,
+    pub version: String,
+    pub disk_type: String,
+    /// The size the guest sees, in bytes.
+    pub virtual_size: u64,
+    /// The image this one records changes against.
+    pub backing_file: Option<String>,
+    pub header: Header,
+    /// Partitions of images that store the disk as is.
+    pub contents: Option<DiskImage>,
+}
+
+pub enum Header {
+    Qcow {
+        cluster_size: u64,
+        encryption: &'static str,
+        backing_format: Option<String>,
+        compression: Option<&'static str>,
+        dirty: bool,
+    },
+    Vmdk {
+        cid: Option<String>,
+        parent_cid: Option<String>,
+        adapter: Option<String>,
+        hardware_version: Option<String>,
+        grain_size: Option<u64>,
+        compression: Option<&'static str>,
+        extents: Vec<Extent>,
+    },
+    Vhd {
+        creator: String,
+        creator_os: String,
+        uuid: String,
+        block_size: Option<u32>,
+    },
+    Vhdx {
+        creator: String,
+        block_size: u32,
+        logical_sector_size: u32,
+    },
+    Vdi {
+        uuid: String,
+        parent_uuid: Option<String>,
+        block_size: u32,
+        allocated: u64,
+        description: String,
+    },
+}
+
+pub struct Extent {
+    pub access: String,
+    pub size: u64,
+    pub kind: String,
+    pub file: Option<String>,
+}
+
+impl VirtualDisk {
+    pub fn mime(&self) -> &'static str {
+        match self.format {
+            "qcow" | "qcow2" => "application/x-qemu-disk",
+            "vmdk" => "application/x-virtualbox-vmdk",
+            "vhd" => "application/x-virtualbox-vhd",
+            "vhdx" => "application/x-virtualbox-vhdx",
+            _ => "application/x-virtualbox-vdi",
+        }
+    }
+
+    pub fn into_record(self, span: Span) -> Record {
+        let string = |s: String| Value::string(s, span);
+        let optional = |s: Option<String>| s.map_or(Value::nothing(span), string);
+        let mut value = record!(
+            "version" => string(self.version),
+            "disk_type" => string(self.disk_type),
+            "virtual_size" => Value::int(self.virtual_size as _, span),
+            "backing_file" => optional(self.backing_file),
+        );
+        match self.header {
+            Header::Qcow {
+                cluster_size,
+                encryption,
+                backing_format,
+                compression,
+                dirty,
+            } => {
+                value.push("cluster_size", Value::int(cluster_size as _, span));
+                value.push("encryption", Value::string(encryption, span));
+                value.push("backing_format", optional(backing_format));
+                value.push(
+                    "compression",
+                    compression.map_or(Value::nothing(span), |c| Value::string(c, span)),
+                );
+                value.push("dirty", Value::bool(dirty, span));
+            }
+            Header::Vmdk {
+                cid,
+                parent_cid,
+                adapter,
+                hardware_version,
+                grain_size,
+                compression,
+                extents,
+            } => {
+                value.push("cid", optional(cid));
+                value.push("parent_cid", optional(parent_cid));
+                value.push("adapter", optional(adapter));
+                value.push("hardware_version", optional(hardware_version));
+                value.push(
+                    "grain_size",
+                    grain_size.map_or(Value::nothing(span), |g| Value::int(g as _, span)),
+                );
+                value.push(
+                    "compression",
+                    compression.map_or(Value::nothing(span), |c| Value::string(c, span)),
+                );
+                let extents = extents
+                    .into_iter()
+                    .map(|e| {
+                        Value::record(
+                            record!(
+                                "access" => string(e.access),
+                                "size" => Value::int(e.size as _, span),
+                                "type" => string(e.kind),
+                                "file" => optional(e.file),
+                            ),
+                            span,
+                        )
+                    })
+                    .collect();
+                value.push("extents", Value::list(extents, span));
+            }
+            Header::Vhd {
+                creator,
+                creator_os,
+                uuid,
+                block_size,
+            } => {
+                value.push("creator", string(creator));
+                value.push("creator_os", string(creator_os));
+                value.push("uuid", string(uuid));
+                value.push(
+                    "block_size",
+                    block_size.map_or(Value::nothing(span), |b| Value::int(b as _, span)),
+                );
+            }
+            Header::Vhdx {
+                creator,
+                block_size,
+                logical_sector_size,
+            } => {
+                value.push("creator", string(creator));
+                value.push("block_size", Value::int(block_size as _, span));
+                value.push(
+                    "logical_sector_size",
+                    Value::int(logical_sector_size as _, span),
+                );
+            }
+            Header::Vdi {
+                uuid,
+                parent_uuid,
+                block_size,
+                allocated,
+                description,
+            } => {
+                value.push("uuid", string(uuid));
+                value.push("parent_uuid", optional(parent_uuid));
+                value.push("block_size", Value::int(block_size as _, span));
+                value.push("allocated", Value::int(allocated as _, span));
+                value.push("description", string(description));
+            }
+        }
+        if let Some(contents) = self.contents {
+            value.extend(contents.into_record(span));
+        }
+        value
+    }
+}
+
+pub fn detect_file(path: &Path) -> Option<VirtualDisk> {
+    detect(&mut File::open(path).ok()?)
+}
+
+pub fn detect<R: Read + Seek>(image: &mut R) -> Option<VirtualDisk> {
+    let start = read_at(image, 0, 0x48).unwrap_or_default();
+    if start.starts_with(QCOW_MAGIC) {
+        qcow(image)
+    } else if start.starts_with(VMDK_SPARSE_MAGIC) {
+        vmdk_sparse(image)
+    } else if start.starts_with(VMDK_DESCRIPTOR) {
+        let mut text = Vec::new();
+        image.seek(SeekFrom::Start(0)).ok()?;
+        image.take(MAX_DESCRIPTOR).read_to_end(&mut text).ok()?;
+        Some(vmdk(&String::from_utf8_lossy(&text), None))
+    } else if start.starts_with(VHDX_SIGNATURE) {
+        vhdx(image)
+    } else if u32_le(&start, 0x40) == Some(VDI_SIGNATURE) {
+        vdi(image)
+    } else {
+        vhd(image)
+    }
+}
+
+fn qcow<R: Read + Seek>(image: &mut R) -> Option<VirtualDisk> {
+    const DIRTY: u64 = 1 << 0;
+    const COMPRESSION_TYPE: u64 = 1 << 3;
+    const BACKING_FORMAT: u32 = 0xE2792ACA;
+
+    let header = read_at(image, 0, 112).or_else(|| read_at(image, 0, 72))?;
+    let version = u32_be(&header, 4)?;
+    let backing_offset = u64_be(&header, 8)?;
+    let backing_length = u32_be(&header, 16)?;
+    // version 1 packs an mtime in where later versions keep cluster bits
+    let (virtual_size, cluster_bits, crypt_method) = if version == 1 {
+        (
+            u64_be(&header, 24)?,
+            header[32] as u32,
+            u32_be(&header, 36)?,
+        )
+    } else {
+        (
+            u64_be(&header, 24)?,
+            u32_be(&header, 20)?,
+            u32_be(&header, 32)?,
+        )
+    };
+    let backing_file = (backing_offset != 0)
+        .then(|| read_at(image, backing_offset, backing_length.min(1023) as usize))
+        .flatten()
+        .map(|name| String::from_utf8_lossy(&name).into_owned());
+
+    let (incompatible, header_length) = if version >= 3 {
+        (u64_be(&header, 72)?, u32_be(&header, 100)? as u64)
+    } else {
+        (0, 72)
+    };
+    let compression = (version >= 3).then(|| {
+        if incompatible & COMPRESSION_TYPE != 0 && header.get(104) == Some(&1) {
+            "zstd"
+        } else {
+            "zlib"
+        }
+    });
+
+    // header extensions follow the header, ending with a zero type
+    let mut backing_format = None;
+    let mut offset = header_length;
+    while version >= 2 && offset < 1 << cluster_bits.min(21) {
+        let Some(extension) = read_at(image, offset, 8) else {
+            break;
+        };
+        let (kind, length) = (u32_be(&extension, 0)?, u32_be(&extension, 4)?);
+        if kind == 0 {
+            break;
+        }
+        if kind == BACKING_FORMAT {
+            backing_format =
+                read_at(image, offset + 8, length.min(64) as usize).map(|name| padded_str(&name));
+        }
+        offset += 8 + (length as u64).next_multiple_of(8);
+    }
+
+    Some(VirtualDisk {
+        format: if version == 1 { "qcow" } else { "qcow2" },
+        version: version.to_string(),
+        disk_type: if backing_file.is_some() {
+            "differencing"
+        } else {
+            "dynamic"
+        }
+        .to_string(),
+        virtual_size,
+        backing_file,
+        header: Header::Qcow {
+            cluster_size: 1 << cluster_bits.min(63),
+            encryption: match crypt_method {
+                0 => "none",
+                1 => "aes",
+                2 => "luks",
+                _ => "unknown",
+            },
+            backing_format,
+            compression,
+            dirty: incompatible & DIRTY != 0,
+        },
+        contents: None,
+    })
+}
+
+/// A hosted sparse extent, which embeds the descriptor.
+fn vmdk_sparse<R: Read + Seek>(image: &mut R) -> Option<VirtualDisk> {
+    let header = read_at(image, 0, 79)?;
+    let capacity = u64_le(&header, 12)?;
+    let grain_size = u64_le(&header, 20)?;
+    let descriptor_offset = u64_le(&header, 28)?;
+    let descriptor_size = u64_le(&header, 36)?;
+    let compression = match u16_le(&header, 77)? {
+        0 => None,
+        1 => Some("deflate"),
+        _ => Some("unknown"),
+    };
+    let text = read_at(
+        image,
+        descriptor_offset.saturating_mul(SECTOR),
+        descriptor_size.saturating_mul(SECTOR).min(MAX_DESCRIPTOR) as usize,
+    )
+    .filter(|_| descriptor_offset != 0)
+    .unwrap_or_default();
+    let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+    let mut disk = vmdk(
+        &String::from_utf8_lossy(&text[..end]),
+        Some(capacity.saturating_mul(SECTOR)),
+    );
+    if let Header::Vmdk {
+        grain_size: grain,
+        compression: compressed,
+        ..
+    } = &mut disk.header
+    {
+        *grain = Some(grain_size.saturating_mul(SECTOR));
+        *compressed = compression;
+    }
+    disk.version = u32_le(&header, 4)?.to_string();
+    Some(disk)
+}
+
+/// Fields of a VMDK text descriptor, `key = "value"` lines followed by
+/// one line per extent.
+fn vmdk(text: &str, capacity: Option<u64>) -> VirtualDisk {
+    let mut fields = std::collections::HashMap::new();
+    let mut extents = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            fields.insert(key.trim(), value.trim().trim_matches('"').to_string());
+            continue;
+        }
+        // RW 41943040 SPARSE "disk-s001.vmdk"
+        let mut parts = line.splitn(4, ' ');
+        if let (Some(access @ ("RW" | "RDONLY" | "NOACCESS")), Some(size), Some(kind)) =
+            (parts.next(), parts.next(), parts.next())
+            && let Ok(sectors) = size.parse::<u64>()
+        {
+            extents.push(Extent {
+                access: access.to_string(),
+                size: sectors.saturating_mul(SECTOR),
+                kind: kind.to_string(),
+                file: parts
+                    .next()
+                    .and_then(|rest| rest.split('"').nth(1))
+                    .map(String::from),
+            });
+        }
+    }
+    let create_type = fields
+        .remove("createType")
+        .unwrap_or_else(|| "unknown".to_string());
+    let parent_cid = fields
+        .remove("parentCID")
+        .filter(|cid| !cid.eq_ignore_ascii_case("ffffffff"));
+    VirtualDisk {
+        format: "vmdk",
+        version: fields.remove("version").unwrap_or_default(),
+        disk_type: create_type,
+        virtual_size: capacity.unwrap_or_else(|| extents.iter().map(|e| e.size).sum()),
+        backing_file: fields.remove("parentFileNameHint"),
+        header: Header::Vmdk {
+            cid: fields.remove("CID"),
+            parent_cid,
+            adapter: fields.remove("ddb.adapterType"),
+            hardware_version: fields.remove("ddb.virtualHWVersion"),
+            grain_size: None,
+            compression: None,
+            extents,
+        },
+        contents: None,
+    }
+}
+
+/// The footer is in the last sector, dynamic disks keep a copy in the first.
+fn vhd<R: Read + Seek>(image: &mut R) -> Option<VirtualDisk> {
+    let length = image.seek(SeekFrom::End(0)).ok()?;
+    // early Virtual PC versions wrote a 511 byte footer
+    let footer = [SECTOR, SECTOR - 1, length]
+        .into_iter()
+        .filter_map(|back| read_at(image, length.checked_sub(back)?, SECTOR as usize - 1))
+        .find(|footer| footer.starts_with(VHD_COOKIE))?;
+    let version = u32_be(&footer, 12)?;
+    let disk_type = u32_be(&footer, 60)?;
+    let (mut block_size, mut backing_file) = (None, None);
+    if disk_type != 2
+        && let Some(dynamic) = read_at(image, u64_be(&footer, 16)?, 1024)
+        && dynamic.starts_with(b"cxsparse")
+    {
+        block_size = u32_be(&dynamic, 32);
+        if disk_type == 4 {
+            let name = dynamic[64..576]
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .take_while(|&c| c != 0)
+                .collect::<Vec<_>>();
+            backing_file = Some(String::from_utf16_lossy(&name));
+        }
+    }
+    Some(VirtualDisk {
+        format: "vhd",
+        version: format!("{}.{}", version >> 16, version & 0xFFFF),
+        disk_type: match disk_type {
+            2 => "fixed",
+            3 => "dynamic",
+            4 => "differencing",
+            _ => "unknown",
+        }
+        .to_string(),
+        virtual_size: u64_be(&footer, 48)?,
+        backing_file,
+        header: Header::Vhd {
+            creator: padded_str(&footer[28..32]),
+            creator_os: match &footer[36..40] {
+                b"Wi2k" => "Windows".to_string(),
+                b"Mac " => "Macintosh".to_string(),
+                other => padded_str(other),
+            },
+            uuid: guid(&footer[68..84]),
+            block_size,
+        },
+        // a fixed disk is the raw disk with the footer appended
+        contents: (disk_type == 2).then(|| disk::detect(image)).flatten(),
+    })
+}
+
+fn vhdx<R: Read + Seek>(image: &mut R) -> Option<VirtualDisk> {
+    const LEAVE_BLOCKS_ALLOCATED: u32 = 1 << 0;
+    const HAS_PARENT: u32 = 1 << 1;
+
+    let identifier = read_at(image, 0, 520)?;
+    let creator = utf16_le(&identifier[8..520]);
+    let header = read_at(image, VHDX_HEADER, 68)?;
+    let regions = read_at(image, VHDX_REGION_TABLE, 16 + 32 * 32)?;
+    if !regions.starts_with(b"regi") {
+        return None;
+    }
+    let (metadata, _) = regions[16..]
+        .chunks_exact(32)
+        .take(u32_le(&regions, 8)?.min(32) as usize)
+        .map(|entry| (u64_le(entry, 16), guid(&entry[..16])))
+        .find(|(_, id)| id == VHDX_METADATA_REGION)?;
+    let metadata = metadata?;
+    let table = read_at(image, metadata, 32 + 32 * 64)?;
+    if !table.starts_with(b"metadata") {
+        return None;
+    }
+
+    let (mut flags, mut block_size, mut virtual_size, mut sector_size) = (0, 0, 0, 0);
+    let mut backing_file = None;
+    for entry in table[32..]
+        .chunks_exact(32)
+        .take(u16_le(&table, 10)?.min(64) as usize)
+    {
+        let item = metadata + u32_le(entry, 16)? as u64;
+        let length = u32_le(entry, 20)?;
+        match guid(&entry[..16]).as_str() {
+            VHDX_FILE_PARAMETERS => {
+                let parameters = read_at(image, item, 8)?;
+                block_size = u32_le(&parameters, 0)?;
+                flags = u32_le(&parameters, 4)?;
+            }
+            VHDX_VIRTUAL_DISK_SIZE => virtual_size = u64_le(&read_at(image, item, 8)?, 0)?,
+            VHDX_LOGICAL_SECTOR_SIZE => sector_size = u32_le(&read_at(image, item, 4)?, 0)?,
+            VHDX_PARENT_LOCATOR => {
+                let locator = read_at(image, item, length.min(64 * 1024) as usize)?;
+                backing_file = parent_path(&locator);
+            }
+            _ => {}
+        }
+    }
+    Some(VirtualDisk {
+        format: "vhdx",
+        version: u16_le(&header, 66)?.to_string(),
+        disk_type: if flags & HAS_PARENT != 0 {
+            "differencing"
+        } else if flags & LEAVE_BLOCKS_ALLOCATED != 0 {
+            "fixed"
+        } else {
+            "dynamic"
+        }
+        .to_string(),
+        virtual_size,
+        backing_file,
+        header: Header::Vhdx {
+            creator,
+            block_size,
+            logical_sector_size: sector_size,
+        },
+        contents: None,
+    })
+}
+
+/// The parent's path from a VHDX parent locator's key value pairs.
+fn parent_path(locator: &[u8]) -> Option<String> {
+    let mut paths = Vec::new();
+    for entry in locator
+        .get(20..)?
+        .chunks_exact(12)
+        .take(u16_le(locator, 18)? as usize)
+    {
+        let (key, value) = (u32_le(entry, 0)? as usize, u32_le(entry, 4)? as usize);
+        let (key_length, value_length) = (u16_le(entry, 8)? as usize, u16_le(entry, 10)? as usize);
+        let key = utf16_le(locator.get(key..key + key_length)?);
+        let value = utf16_le(locator.get(value..value + value_length)?);
+        paths.push((key, value));
+    }
+    ["relative_path", "absolute_win32_path", "volume_path"]
+        .iter()
+        .find_map(|wanted| paths.iter().find(|(key, _)| key == wanted))
+        .map(|(_, value)| value.clone())
+}
+
+fn utf16_le(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+fn vdi<R: Read + Seek>(image: &mut R) -> Option<VirtualDisk> {
+    let header = read_at(image, 0, 0x1C8)?;
+    let version = u32_le(&header, 0x44)?;
+    let disk_type = u32_le(&header, 0x4C)?;
+    let block_size = u32_le(&header, 0x178)?;
+    let parent = &header[0x1B8..0x1C8];
+    Some(VirtualDisk {
+        format: "vdi",
+        version: format!("{}.{}", version >> 16, version & 0xFFFF),
+        disk_type: match disk_type {
+            1 => "dynamic",
+            2 => "fixed",
+            3 => "undo",
+            4 => "differencing",
+            _ => "unknown",
+        }
+        .to_string(),
+        virtual_size: u64_le(&header, 0x170)?,
+        backing_file: None,
+        header: Header::Vdi {
+            uuid: guid(&header[0x188..0x198]),
+            parent_uuid: parent.iter().any(|&b| b != 0).then(|| guid(parent)),
+            block_size,
+            allocated: u32_le(&header, 0x184)? as u64 * block_size as u64,
+            description: padded_str(&header[0x54..0x154]),
+        },
+        contents: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn qcow2_header() {
+        let mut image = vec![0u8; 512];
+        image[..4].copy_from_slice(QCOW_MAGIC);
+        image[4..8].copy_from_slice(&3u32.to_be_bytes());
+        image[8..16].copy_from_slice(&400u64.to_be_bytes());
+        image[16..20].copy_from_slice(&10u32.to_be_bytes());
+        image[20..24].copy_from_slice(&16u32.to_be_bytes());
+        image[24..32].copy_from_slice(&(1u64 << 30).to_be_bytes());
+        image[32..36].copy_from_slice(&2u32.to_be_bytes());
+        image[100..104].copy_from_slice(&112u32.to_be_bytes());
+        image[112..116].copy_from_slice(&0xE2792ACAu32.to_be_bytes());
+        image[116..120].copy_from_slice(&5u32.to_be_bytes());
+        image[120..125].copy_from_slice(b"qcow2");
+        image[400..410].copy_from_slice(b"base.qcow2");
+        let disk = detect(&mut Cursor::new(&image)).unwrap();
+        assert_eq!((disk.format, disk.version.as_str()), ("qcow2", "3"));
+        assert_eq!(disk.disk_type, "differencing");
+        assert_eq!(disk.virtual_size, 1 << 30);
+        assert_eq!(disk.backing_file.as_deref(), Some("base.qcow2"));
+        let Header::Qcow {
+            cluster_size,
+            encryption,
+            backing_format,
+            compression,
+            dirty,
+        } = disk.header
+        else {
+            panic!("not qcow");
+        };
+        assert_eq!(
+            (
+                cluster_size,
+                encryption,
+                backing_format.as_deref(),
+                compression,
+                dirty
+            ),
+            (65536, "luks", Some("qcow2"), Some("zlib"), false)
+        );
+    }
+
+    #[test]
+    fn vmdk_descriptor() {
+        let text = "# Disk DescriptorFile\nversion=1\nCID=fffffffe\nparentCID=ffffffff\n\
+            createType=\"twoGbMaxExtentSparse\"\n\n# Extent description\n\
+            RW 4192256 SPARSE \"disk-s001.vmdk\"\nRW 2048 SPARSE \"disk-s002.vmdk\"\n\n\
+            ddb.adapterType = \"lsilogic\"\nddb.virtualHWVersion = \"8\"\n";
+        let disk = detect(&mut Cursor::new(text)).unwrap();
+        assert_eq!(disk.format, "vmdk");
+        assert_eq!(disk.disk_type, "twoGbMaxExtentSparse");
+        assert_eq!(disk.virtual_size, 2 << 30);
+        let Header::Vmdk {
+            parent_cid,
+            adapter,
+            extents,
+            ..
+        } = disk.header
+        else {
+            panic!("not vmdk");
+        };
+        assert_eq!((parent_cid, adapter.as_deref()), (None, Some("lsilogic")));
+        assert_eq!(extents[1].file.as_deref(), Some("disk-s002.vmdk"));
+    }
+
+    #[test]
+    fn vhd_footer() {
+        // a fixed disk: the raw data then the footer
+        let mut image = vec![0u8; 2048];
+        let footer = &mut image[1536..];
+        footer[..8].copy_from_slice(VHD_COOKIE);
+        footer[12..16].copy_from_slice(&0x10000u32.to_be_bytes());
+        footer[16..24].copy_from_slice(&u64::MAX.to_be_bytes());
+        footer[28..32].copy_from_slice(b"qemu");
+        footer[36..40].copy_from_slice(b"Wi2k");
+        footer[48..56].copy_from_slice(&1536u64.to_be_bytes());
+        footer[60..64].copy_from_slice(&2u32.to_be_bytes());
+        let disk = detect(&mut Cursor::new(&image)).unwrap();
+        assert_eq!((disk.format, disk.version.as_str()), ("vhd", "1.0"));
+        assert_eq!(
+            (disk.disk_type.as_str(), disk.virtual_size),
+            ("fixed", 1536)
+        );
+        assert!(detect(&mut Cursor::new(&image[..1536])).is_none());
+    }
+}
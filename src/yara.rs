@@ -0,0 +1,430 @@
+//! A subset of YARA: text, hex and regex strings, and conditions built from
+//! string matches, counts, offsets, `filesize`, integer reads, `of` and
+//! other rules. Modules, `for` loops and `entrypoint` are not supported.
+pub mod parser;
+
+use nu_protocol::{Record, Span, Value, record};
+use regex::bytes::Regex;
+use std::path::Path;
+
+/// Matches kept per string, so pathological patterns stay cheap.
+const MAX_MATCHES: usize = 1_000;
+
+pub struct Rule {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub meta: Vec<(String, Meta)>,
+    /// Usable by other rules but never reported.
+    pub private: bool,
+    /// Every other rule fails unless this one matches.
+    pub global: bool,
+    pub strings: Vec<StringDef>,
+    pub condition: Expr,
+}
+
+pub enum Meta {
+    Text(String),
+    Int(i64),
+    Bool(bool),
+}
+
+pub struct StringDef {
+    /// Without the `$`, empty for anonymous strings.
+    pub name: String,
+    pub pattern: Pattern,
+    pub private: bool,
+}
+
+pub struct Pattern {
+    pub regex: Regex,
+    pub fullword: bool,
+}
+
+pub enum Quantifier {
+    All,
+    Any,
+    None,
+    Count(Box<Expr>),
+    Percent(Box<Expr>),
+}
+
+/// Strings are indexes into the rule's strings, rules are indexes into all
+/// rules loaded before.
+pub enum Expr {
+    Bool(bool),
+    Int(i64),
+    Filesize,
+    String(usize),
+    StringAt(usize, Box<Expr>),
+    StringIn(usize, Box<Expr>, Box<Expr>),
+    Count(usize),
+    CountIn(usize, Box<Expr>, Box<Expr>),
+    /// `@a[i]`, 1-based like YARA.
+    Offset(usize, Box<Expr>),
+    /// `!a[i]`.
+    Length(usize, Box<Expr>),
+    Read {
+        size: usize,
+        signed: bool,
+        big_endian: bool,
+        offset: Box<Expr>,
+    },
+    Rule(usize),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(&'static str, Box<Expr>, Box<Expr>),
+    Arith(&'static str, Box<Expr>, Box<Expr>),
+    Of {
+        quantifier: Quantifier,
+        set: Vec<usize>,
+        range: Option<(Box<Expr>, Box<Expr>)>,
+    },
+}
+
+/// Rules from every `.yar` and `.yara` file in a directory, in file name
+/// order so rules can refer to the ones in earlier files.
+pub fn load(dir: &Path) -> Result<Vec<Rule>, String> {
+    let mut files = if dir.is_dir() {
+        std::fs::read_dir(dir)
+            .map_err(|e| format!("{}: {e}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "yar" || ext == "yara")
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![dir.to_path_buf()]
+    };
+    files.sort();
+    let mut rules = Vec::new();
+    for file in files {
+        let source =
+            std::fs::read_to_string(&file).map_err(|e| format!("{}: {e}", file.display()))?;
+        let parsed = parser::Parser::parse(&source, &rules)
+            .map_err(|e| format!("{}: {e}", file.display()))?;
+        rules.extend(parsed);
+    }
+    Ok(rules)
+}
+
+pub struct Match<'r> {
+    pub rule: &'r Rule,
+    /// `(identifier, offset, length)` for every reported string match.
+    pub strings: Vec<(String, usize, usize)>,
+}
+
+impl Match<'_> {
+    pub fn into_value(self, span: Span) -> Value {
+        let meta = self
+            .rule
+            .meta
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Meta::Text(text) => Value::string(text, span),
+                    Meta::Int(int) => Value::int(*int, span),
+                    Meta::Bool(b) => Value::bool(*b, span),
+                };
+                (key.clone(), value)
+            })
+            .collect::<Record>();
+        let strings = self
+            .strings
+            .into_iter()
+            .map(|(identifier, offset, length)| {
+                Value::record(
+                    record!(
+                        "identifier" => Value::string(identifier, span),
+                        "offset" => Value::int(offset as _, span),
+                        "length" => Value::int(length as _, span),
+                    ),
+                    span,
+                )
+            })
+            .collect();
+        Value::record(
+            record!(
+                "rule" => Value::string(&self.rule.name, span),
+                "tags" => Value::list(
+                    self.rule.tags.iter().map(|t| Value::string(t, span)).collect(),
+                    span,
+                ),
+                "meta" => Value::record(meta, span),
+                "strings" => Value::list(strings, span),
+            ),
+            span,
+        )
+    }
+}
+
+/// Matching, non-private rules in load order.
+pub fn scan<'r>(rules: &'r [Rule], data: &[u8]) -> Vec<Match<'r>> {
+    let mut results = Vec::with_capacity(rules.len());
+    let mut all_matches = Vec::with_capacity(rules.len());
+    for rule in rules {
+        let matches = rule
+            .strings
+            .iter()
+            .map(|s| find_all(&s.pattern, data))
+            .collect::<Vec<_>>();
+        let scan = Scan {
+            data,
+            matches: &matches,
+            results: &results,
+        };
+        results.push(scan.truthy(&rule.condition));
+        all_matches.push(matches);
+    }
+    let globals_pass = rules.iter().zip(&results).all(|(r, &ok)| !r.global || ok);
+    rules
+        .iter()
+        .zip(results)
+        .zip(all_matches)
+        .filter(|((rule, ok), _)| *ok && globals_pass && !rule.private)
+        .map(|((rule, _), matches)| Match {
+            rule,
+            strings: rule
+                .strings
+                .iter()
+                .zip(matches)
+                .filter(|(s, _)| !s.private)
+                .flat_map(|(s, found)| {
+                    found
+                        .into_iter()
+                        .map(|(offset, length)| (format!("${}", s.name), offset, length))
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Overlapping matches as `(offset, length)`, like YARA reports them.
+fn find_all(pattern: &Pattern, data: &[u8]) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    while found.len() < MAX_MATCHES && start < data.len() {
+        let Some(m) = pattern.regex.find_at(data, start) else {
+            break;
+        };
+        start = m.start() + 1;
+        let boundary = |i: Option<&u8>| !i.is_some_and(|b| b.is_ascii_alphanumeric());
+        if m.is_empty()
+            || (pattern.fullword
+                && !(boundary(m.start().checked_sub(1).and_then(|i| data.get(i)))
+                    && boundary(data.get(m.end()))))
+        {
+            continue;
+        }
+        found.push((m.start(), m.len()));
+    }
+    found
+}
+
+struct Scan<'a> {
+    data: &'a [u8],
+    /// Matches of each string of the rule being evaluated.
+    matches: &'a [Vec<(usize, usize)>],
+    /// Results of the rules before it.
+    results: &'a [bool],
+}
+
+impl Scan<'_> {
+    fn truthy(&self, expr: &Expr) -> bool {
+        self.eval(expr).is_some_and(|v| v != 0)
+    }
+
+    /// Integer value, booleans being 0 and 1. `None` is YARA's undefined,
+    /// from reading past the end of the file or a missing match.
+    fn eval(&self, expr: &Expr) -> Option<i64> {
+        let in_range = |offset: usize, low: &Expr, high: &Expr| -> Option<bool> {
+            let (low, high) = (self.eval(low)?, self.eval(high)?);
+            Some(low <= offset as i64 && offset as i64 <= high)
+        };
+        let nth = |string: usize, index: &Expr| -> Option<(usize, usize)> {
+            let index = usize::try_from(self.eval(index)?).ok()?;
+            self.matches[string].get(index.checked_sub(1)?).copied()
+        };
+        Some(match expr {
+            Expr::Bool(b) => *b as i64,
+            Expr::Int(value) => *value,
+            Expr::Filesize => self.data.len() as i64,
+            Expr::String(i) => !self.matches[*i].is_empty() as i64,
+            Expr::StringAt(i, at) => {
+                let at = self.eval(at)?;
+                self.matches[*i].iter().any(|(o, _)| *o as i64 == at) as i64
+            }
+            Expr::StringIn(i, low, high) => {
+                let mut found = false;
+                for (offset, _) in &self.matches[*i] {
+                    found |= in_range(*offset, low, high)?;
+                }
+                found as i64
+            }
+            Expr::Count(i) => self.matches[*i].len() as i64,
+            Expr::CountIn(i, low, high) => {
+                let mut count = 0;
+                for (offset, _) in &self.matches[*i] {
+                    count += in_range(*offset, low, high)? as i64;
+                }
+                count
+            }
+            Expr::Offset(i, index) => nth(*i, index)?.0 as i64,
+            Expr::Length(i, index) => nth(*i, index)?.1 as i64,
+            Expr::Read {
+                size,
+                signed,
+                big_endian,
+                offset,
+            } => {
+                let offset = usize::try_from(self.eval(offset)?).ok()?;
+                let bytes = self.data.get(offset..offset.checked_add(*size)?)?;
+                let mut value = 0u64;
+                for (i, &b) in bytes.iter().enumerate() {
+                    let shift = if *big_endian { size - 1 - i } else { i };
+                    value |= (b as u64) << (8 * shift);
+                }
+                if *signed {
+                    let unused = 64 - 8 * *size as u32;
+                    ((value << unused) as i64) >> unused
+                } else {
+                    value as i64
+                }
+            }
+            Expr::Rule(index) => *self.results.get(*index)? as i64,
+            Expr::Not(inner) => (self.eval(inner)? == 0) as i64,
+            Expr::And(a, b) => (self.truthy(a) && self.truthy(b)) as i64,
+            Expr::Or(a, b) => (self.truthy(a) || self.truthy(b)) as i64,
+            Expr::Compare(op, a, b) => {
+                let (a, b) = (self.eval(a)?, self.eval(b)?);
+                (match *op {
+                    "==" => a == b,
+                    "!=" => a != b,
+                    "<" => a < b,
+                    "<=" => a <= b,
+                    ">" => a > b,
+                    _ => a >= b,
+                }) as i64
+            }
+            Expr::Arith(op, a, b) => {
+                let (a, b) = (self.eval(a)?, self.eval(b)?);
+                match *op {
+                    "+" => a.wrapping_add(b),
+                    "-" => a.wrapping_sub(b),
+                    "*" => a.wrapping_mul(b),
+                    "\\" => a.checked_div(b)?,
+                    "%" => a.checked_rem(b)?,
+                    "&" => a & b,
+                    "|" => a | b,
+                    "^" => a ^ b,
+                    "<<" => a.checked_shl(u32::try_from(b).ok()?).unwrap_or(0),
+                    _ => a.checked_shr(u32::try_from(b).ok()?).unwrap_or(0),
+                }
+            }
+            Expr::Of {
+                quantifier,
+                set,
+                range,
+            } => {
+                let mut matched = 0;
+                for &i in set {
+                    let hit = match range {
+                        Some((low, high)) => {
+                            let mut hit = false;
+                            for (offset, _) in &self.matches[i] {
+                                hit |= in_range(*offset, low, high)?;
+                            }
+                            hit
+                        }
+                        None => !self.matches[i].is_empty(),
+                    };
+                    matched += hit as i64;
+                }
+                let needed = match quantifier {
+                    Quantifier::All => set.len() as i64,
+                    Quantifier::Any => 1,
+                    Quantifier::None => return Some((matched == 0) as i64),
+                    Quantifier::Count(n) => self.eval(n)?,
+                    Quantifier::Percent(p) => (self.eval(p)? * set.len() as i64 + 99) / 100,
+                };
+                (matched >= needed) as i64
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parser::Parser, scan};
+
+    fn matching(source: &str, data: &[u8]) -> Vec<String> {
+        let rules = Parser::parse(source, &[]).unwrap();
+        scan(&rules, data)
+            .into_iter()
+            .map(|m| m.rule.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn conditions() {
+        let data = b"MZ\x90\x00 This program cannot be run in DOS mode. Hello, World! hello";
+        let rules = r#"
+            rule mz : pe windows {
+                meta: author = "someone" score = -5 ok = true
+                condition: uint16(0) == 0x5A4D and filesize < 1KB
+            }
+            rule dos { strings: $a = "DOS mode" $b = { 54 68 69 ?? 20 [1-3] 6F 67 } condition: all of them }
+            rule hello { strings: $h = /hel+o/i fullword condition: #h == 2 and @h[2] > 50 }
+            rule anchored { strings: $a = "program" condition: $a at 10 and $a in (0..20) }
+            rule missing { strings: $a = "absent" $b = "World" condition: any of ($a, $b) and not $a }
+            private rule hidden { condition: uint32be(0) == 0x4D5A9000 }
+            rule uses_hidden { condition: hidden and 50% of them }
+            rule wide { strings: $w = "Hi" wide condition: $w }
+            rule undefined { condition: uint32(filesize) == 0 or int8(2) == -112 }
+        "#;
+        assert_eq!(
+            matching(rules, data),
+            [
+                "mz",
+                "dos",
+                "hello",
+                "anchored",
+                "missing",
+                "uses_hidden",
+                "undefined"
+            ]
+        );
+
+        let parsed = Parser::parse(rules, &[]).unwrap();
+        let found = scan(&parsed, data);
+        assert_eq!(
+            found[1].strings,
+            [("$a".to_string(), 35, 8), ("$b".to_string(), 5, 9),]
+        );
+        assert!(
+            matching(
+                "global rule g { condition: filesize > 100 } rule r { condition: true }",
+                data
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| Parser::parse(source, &[]).err().unwrap();
+        assert_eq!(
+            error("rule a {\n condition: $x }"),
+            "line 2: undefined string $x"
+        );
+        assert_eq!(
+            error("import \"pe\"\nrule a { condition: true }"),
+            "line 1: imports and includes are not supported"
+        );
+        assert_eq!(
+            error("rule a { strings: $a = { 4D 5 } condition: $a }"),
+            "line 1: odd number of hex digits"
+        );
+    }
+}
@@ -0,0 +1,834 @@
+//! Lexer and recursive descent parser for the supported subset of YARA.
+use super::{Expr, Meta, Pattern, Quantifier, Rule, StringDef};
+use regex::bytes::RegexBuilder;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    /// `$name`, `$name*` or a bare `$`.
+    StringId(String),
+    Count(String),
+    Offset(String),
+    Length(String),
+    Int(i64),
+    Text(Vec<u8>),
+    Regex(String, String),
+    Hex(String),
+    Punct(&'static str),
+}
+
+struct Lexer<'a> {
+    src: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+const PUNCTS: [&str; 27] = [
+    "..", "==", "!=", "<=", ">=", "<<", ">>", "{", "}", "(", ")", "[", "]", ":", ",", "=", "<",
+    ">", "+", "-", "*", "\\", "%", "&", "|", "^", "~",
+];
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+impl<'a> Lexer<'a> {
+    fn error(&self, message: impl std::fmt::Display) -> String {
+        format!("line {}: {message}", self.line)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn skip_space(&mut self) -> Result<(), String> {
+        while let Some(c) = self.peek() {
+            if c == b'\n' {
+                self.line += 1;
+                self.pos += 1;
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else if self.src[self.pos..].starts_with(b"//") {
+                while self.peek().is_some_and(|c| c != b'\n') {
+                    self.pos += 1;
+                }
+            } else if self.src[self.pos..].starts_with(b"/*") {
+                let end = self.src[self.pos + 2..]
+                    .windows(2)
+                    .position(|w| w == b"*/")
+                    .ok_or_else(|| self.error("unterminated comment"))?;
+                let comment = &self.src[self.pos..self.pos + 2 + end + 2];
+                self.line += comment.iter().filter(|&&c| c == b'\n').count();
+                self.pos += comment.len();
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(u8) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&predicate) {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.src[start..self.pos]).into_owned()
+    }
+
+    fn quoted(&mut self) -> Result<Vec<u8>, String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match c {
+                b'"' => return Ok(out),
+                b'\n' => return Err(self.error("unterminated string")),
+                b'\\' => {
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    out.push(match escaped {
+                        b'n' => b'\n',
+                        b't' => b'\t',
+                        b'r' => b'\r',
+                        b'"' => b'"',
+                        b'\\' => b'\\',
+                        b'x' => {
+                            let hex = self.src.get(self.pos..self.pos + 2).unwrap_or_default();
+                            self.pos += 2;
+                            std::str::from_utf8(hex)
+                                .ok()
+                                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                                .ok_or_else(|| self.error("bad \\x escape"))?
+                        }
+                        other => {
+                            return Err(self.error(format!("unknown escape \\{}", other as char)));
+                        }
+                    });
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn regex(&mut self) -> Result<Token, String> {
+        self.pos += 1;
+        let mut pattern = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated regular expression"))?;
+            self.pos += 1;
+            match c {
+                b'/' => break,
+                b'\n' => return Err(self.error("unterminated regular expression")),
+                b'\\' if self.peek() == Some(b'/') => {
+                    pattern.push('/');
+                    self.pos += 1;
+                }
+                b'\\' => {
+                    pattern.push('\\');
+                    if let Some(next) = self.peek() {
+                        pattern.push(next as char);
+                        self.pos += 1;
+                    }
+                }
+                c => pattern.push(c as char),
+            }
+        }
+        let flags = self.take_while(|c| c == b'i' || c == b's');
+        Ok(Token::Regex(pattern, flags))
+    }
+
+    fn hex(&mut self) -> Result<Token, String> {
+        self.pos += 1;
+        let start = self.pos;
+        let end = self.src[start..]
+            .iter()
+            .position(|&c| c == b'}')
+            .ok_or_else(|| self.error("unterminated hex string"))?;
+        let body = String::from_utf8_lossy(&self.src[start..start + end]).into_owned();
+        self.line += body.matches('\n').count();
+        self.pos = start + end + 1;
+        Ok(Token::Hex(body))
+    }
+
+    fn number(&mut self) -> Result<Token, String> {
+        let text = self.take_while(is_ident);
+        let (digits, multiplier) = if let Some(d) = text.strip_suffix("KB") {
+            (d, 1024)
+        } else if let Some(d) = text.strip_suffix("MB") {
+            (d, 1024 * 1024)
+        } else {
+            (text.as_str(), 1)
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(octal) = digits.strip_prefix("0o") {
+            i64::from_str_radix(octal, 8)
+        } else {
+            digits.parse()
+        }
+        .map_err(|_| self.error(format!("bad number {text}")))?;
+        Ok(Token::Int(value * multiplier))
+    }
+
+    /// All tokens with their line numbers. `previous` decides whether a
+    /// `{` opens a hex string, which only happens right after `=`.
+    fn tokens(mut self) -> Result<Vec<(Token, usize)>, String> {
+        let mut tokens: Vec<(Token, usize)> = Vec::new();
+        loop {
+            self.skip_space()?;
+            let Some(c) = self.peek() else {
+                return Ok(tokens);
+            };
+            let line = self.line;
+            let after_assign = matches!(tokens.last(), Some((Token::Punct("="), _)));
+            let token = match c {
+                b'{' if after_assign => self.hex()?,
+                b'"' => Token::Text(self.quoted()?),
+                b'/' => self.regex()?,
+                b'0'..=b'9' => self.number()?,
+                b'$' | b'#' | b'@' => {
+                    self.pos += 1;
+                    let mut name = self.take_while(is_ident);
+                    if c == b'$' && self.peek() == Some(b'*') {
+                        self.pos += 1;
+                        name.push('*');
+                    }
+                    match c {
+                        b'$' => Token::StringId(name),
+                        b'#' => Token::Count(name),
+                        _ => Token::Offset(name),
+                    }
+                }
+                b'!' if self.src.get(self.pos + 1).is_some_and(|&c| is_ident(c)) => {
+                    self.pos += 1;
+                    Token::Length(self.take_while(is_ident))
+                }
+                c if is_ident(c) => Token::Ident(self.take_while(is_ident)),
+                _ => {
+                    let rest = &self.src[self.pos..];
+                    let punct = PUNCTS
+                        .iter()
+                        .find(|p| rest.starts_with(p.as_bytes()))
+                        .ok_or_else(|| {
+                            self.error(format!("unexpected character {:?}", c as char))
+                        })?;
+                    self.pos += punct.len();
+                    Token::Punct(punct)
+                }
+            };
+            tokens.push((token, line));
+        }
+    }
+}
+
+pub struct Parser<'r> {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Rules parsed so far, across files, for rule references.
+    known_rules: &'r [Rule],
+    /// Rules parsed so far in this file.
+    rules: Vec<Rule>,
+}
+
+impl<'r> Parser<'r> {
+    pub fn parse(source: &str, known_rules: &'r [Rule]) -> Result<Vec<Rule>, String> {
+        let lexer = Lexer {
+            src: source.as_bytes(),
+            pos: 0,
+            line: 1,
+        };
+        let mut parser = Parser {
+            tokens: lexer.tokens()?,
+            pos: 0,
+            known_rules,
+            rules: Vec::new(),
+        };
+        while parser.peek().is_some() {
+            let rule = parser.rule()?;
+            parser.rules.push(rule);
+        }
+        Ok(parser.rules)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|(t, _)| t)
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> String {
+        let line = self
+            .tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line);
+        format!("line {line}: {message}")
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .map(|(t, _)| t.clone())
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i == keyword)
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), String> {
+        if self.is_punct(punct) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{punct}`")))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected an identifier"))
+            }
+        }
+    }
+
+    fn rule(&mut self) -> Result<Rule, String> {
+        let (mut private, mut global) = (false, false);
+        loop {
+            match self.ident()?.as_str() {
+                "private" => private = true,
+                "global" => global = true,
+                "rule" => break,
+                "import" | "include" => {
+                    self.pos -= 1;
+                    return Err(self.error("imports and includes are not supported"));
+                }
+                other => {
+                    self.pos -= 1;
+                    return Err(self.error(format!("expected `rule`, found `{other}`")));
+                }
+            }
+        }
+        let name = self.ident()?;
+        if self.rule_index(&name).is_some() {
+            return Err(self.error(format!("duplicate rule `{name}`")));
+        }
+        let mut tags = Vec::new();
+        if self.is_punct(":") {
+            self.pos += 1;
+            while let Some(Token::Ident(tag)) = self.peek() {
+                tags.push(tag.clone());
+                self.pos += 1;
+            }
+        }
+        self.expect_punct("{")?;
+        let mut meta = Vec::new();
+        let mut strings = Vec::new();
+        let mut condition = None;
+        while !self.is_punct("}") {
+            let section = self.ident()?;
+            self.expect_punct(":")?;
+            match section.as_str() {
+                "meta" => {
+                    while matches!(self.peek(), Some(Token::Ident(_)))
+                        && self.peek_at(1) == Some(&Token::Punct("="))
+                    {
+                        let key = self.ident()?;
+                        self.pos += 1;
+                        meta.push((key, self.meta_value()?));
+                    }
+                }
+                "strings" => {
+                    while let Some(Token::StringId(_)) = self.peek() {
+                        let string = self.string_def(&strings)?;
+                        strings.push(string);
+                    }
+                }
+                "condition" => condition = Some(self.expr(&strings)?),
+                other => return Err(self.error(format!("unknown section `{other}`"))),
+            }
+        }
+        self.pos += 1;
+        Ok(Rule {
+            name,
+            tags,
+            meta,
+            private,
+            global,
+            condition: condition.ok_or_else(|| self.error("rule without a condition"))?,
+            strings,
+        })
+    }
+
+    fn meta_value(&mut self) -> Result<Meta, String> {
+        Ok(match self.next()? {
+            Token::Text(text) => Meta::Text(String::from_utf8_lossy(&text).into_owned()),
+            Token::Int(value) => Meta::Int(value),
+            Token::Punct("-") => match self.next()? {
+                Token::Int(value) => Meta::Int(-value),
+                _ => return Err(self.error("expected a number")),
+            },
+            Token::Ident(b) if b == "true" || b == "false" => Meta::Bool(b == "true"),
+            _ => return Err(self.error("expected a string, number or boolean")),
+        })
+    }
+
+    fn string_def(&mut self, strings: &[StringDef]) -> Result<StringDef, String> {
+        let Token::StringId(name) = self.next()? else {
+            unreachable!("checked by the caller")
+        };
+        if name.ends_with('*') {
+            return Err(self.error("string identifiers can't contain `*`"));
+        }
+        // anonymous strings can only be used through `them` and `of`
+        if !name.is_empty() && strings.iter().any(|s| s.name == name) {
+            return Err(self.error(format!("duplicate string ${name}")));
+        }
+        self.expect_punct("=")?;
+        let value = self.next()?;
+        let mut modifiers = Vec::new();
+        while let Some(Token::Ident(modifier)) = self.peek() {
+            match modifier.as_str() {
+                "nocase" | "wide" | "ascii" | "fullword" | "private" => {
+                    modifiers.push(modifier.clone());
+                    self.pos += 1;
+                }
+                "xor" | "base64" | "base64wide" => {
+                    return Err(self.error(format!("the `{modifier}` modifier is not supported")));
+                }
+                _ => break,
+            }
+        }
+        let has = |m: &str| modifiers.iter().any(|x| x == m);
+        let (pattern, fullword) = match value {
+            Token::Text(text) => {
+                let wide = has("wide");
+                let ascii = has("ascii") || !wide;
+                let mut alternatives = Vec::new();
+                if ascii {
+                    alternatives.push(literal(&text));
+                }
+                if wide {
+                    let wide_text = text.iter().flat_map(|&b| [b, 0]).collect::<Vec<_>>();
+                    alternatives.push(literal(&wide_text));
+                }
+                let flags = if has("nocase") { "(?i)" } else { "" };
+                (
+                    format!("{flags}(?:{})", alternatives.join("|")),
+                    has("fullword"),
+                )
+            }
+            Token::Hex(body) => (hex_pattern(&body).map_err(|e| self.error(e))?, false),
+            Token::Regex(pattern, flags) => {
+                let mut prefix = String::new();
+                if flags.contains('i') || has("nocase") {
+                    prefix.push('i');
+                }
+                if flags.contains('s') {
+                    prefix.push('s');
+                }
+                let pattern = if prefix.is_empty() {
+                    pattern
+                } else {
+                    format!("(?{prefix}){pattern}")
+                };
+                (pattern, has("fullword"))
+            }
+            _ => return Err(self.error("expected a text, hex or regular expression string")),
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .unicode(false)
+            .build()
+            .map_err(|e| self.error(format!("bad pattern for ${name}: {e}")))?;
+        Ok(StringDef {
+            name,
+            pattern: Pattern { regex, fullword },
+            private: has("private"),
+        })
+    }
+
+    fn rule_index(&self, name: &str) -> Option<usize> {
+        self.known_rules
+            .iter()
+            .chain(&self.rules)
+            .position(|r| r.name == name)
+    }
+
+    fn string_index(&self, strings: &[StringDef], name: &str) -> Result<usize, String> {
+        strings
+            .iter()
+            .position(|s| s.name == name)
+            .ok_or_else(|| self.error(format!("undefined string ${name}")))
+    }
+
+    fn expr(&mut self, strings: &[StringDef]) -> Result<Expr, String> {
+        let mut lhs = self.and(strings)?;
+        while self.is_keyword("or") {
+            self.pos += 1;
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and(strings)?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self, strings: &[StringDef]) -> Result<Expr, String> {
+        let mut lhs = self.not(strings)?;
+        while self.is_keyword("and") {
+            self.pos += 1;
+            lhs = Expr::And(Box::new(lhs), Box::new(self.not(strings)?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self, strings: &[StringDef]) -> Result<Expr, String> {
+        if self.is_keyword("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not(strings)?)));
+        }
+        self.comparison(strings)
+    }
+
+    fn comparison(&mut self, strings: &[StringDef]) -> Result<Expr, String> {
+        let lhs = self.binary(strings, 0)?;
+        // `<expr> of` and `<expr>% of`
+        if self.is_keyword("of") {
+            return self.of(Quantifier::Count(Box::new(lhs)), strings);
+        }
+        if self.is_punct("%") && self.peek_at(1) == Some(&Token::Ident("of".into())) {
+            self.pos += 1;
+            return self.of(Quantifier::Percent(Box::new(lhs)), strings);
+        }
+        for op in ["==", "!=", "<=", ">=", "<", ">"] {
+            if self.is_punct(op) {
+                self.pos += 1;
+                let rhs = self.binary(strings, 0)?;
+                return Ok(Expr::Compare(op, Box::new(lhs), Box::new(rhs)));
+            }
+        }
+        Ok(lhs)
+    }
+
+    /// Arithmetic and bitwise operators by precedence, loosest first.
+    fn binary(&mut self, strings: &[StringDef], level: usize) -> Result<Expr, String> {
+        const LEVELS: [&[&str]; 6] = [
+            &["|"],
+            &["^"],
+            &["&"],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "\\", "%"],
+        ];
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary(strings);
+        };
+        let mut lhs = self.binary(strings, level + 1)?;
+        while let Some(Token::Punct(op)) = self.peek() {
+            let op = *op;
+            // `50% of them` is a quantifier, not a modulo
+            if !ops.contains(&op)
+                || (op == "%" && self.peek_at(1) == Some(&Token::Ident("of".into())))
+            {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(strings, level + 1)?;
+            lhs = Expr::Arith(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self, strings: &[StringDef]) -> Result<Expr, String> {
+        if self.is_punct("-") {
+            self.pos += 1;
+            return Ok(Expr::Arith(
+                "-",
+                Box::new(Expr::Int(0)),
+                Box::new(self.unary(strings)?),
+            ));
+        }
+        if self.is_punct("~") {
+            self.pos += 1;
+            return Ok(Expr::Arith(
+                "^",
+                Box::new(Expr::Int(-1)),
+                Box::new(self.unary(strings)?),
+            ));
+        }
+        self.primary(strings)
+    }
+
+    fn range(&mut self, strings: &[StringDef]) -> Result<(Expr, Expr), String> {
+        self.expect_punct("(")?;
+        let low = self.binary(strings, 0)?;
+        self.expect_punct("..")?;
+        let high = self.binary(strings, 0)?;
+        self.expect_punct(")")?;
+        Ok((low, high))
+    }
+
+    fn optional_index(&mut self, strings: &[StringDef]) -> Result<Expr, String> {
+        if self.is_punct("[") {
+            self.pos += 1;
+            let index = self.expr(strings)?;
+            self.expect_punct("]")?;
+            Ok(index)
+        } else {
+            Ok(Expr::Int(1))
+        }
+    }
+
+    fn primary(&mut self, strings: &[StringDef]) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Int(value) => Ok(Expr::Int(value)),
+            Token::Punct("(") => {
+                let inner = self.expr(strings)?;
+                self.expect_punct(")")?;
+                Ok(inner)
+            }
+            Token::StringId(name) => {
+                if name.is_empty() || name.ends_with('*') {
+                    self.pos -= 1;
+                    return Err(self.error("`$` on its own only works inside `for` loops"));
+                }
+                let index = self.string_index(strings, &name)?;
+                if self.is_keyword("at") {
+                    self.pos += 1;
+                    let at = self.binary(strings, 0)?;
+                    Ok(Expr::StringAt(index, Box::new(at)))
+                } else if self.is_keyword("in") {
+                    self.pos += 1;
+                    let (low, high) = self.range(strings)?;
+                    Ok(Expr::StringIn(index, Box::new(low), Box::new(high)))
+                } else {
+                    Ok(Expr::String(index))
+                }
+            }
+            Token::Count(name) => {
+                let index = self.string_index(strings, &name)?;
+                if self.is_keyword("in") {
+                    self.pos += 1;
+                    let (low, high) = self.range(strings)?;
+                    Ok(Expr::CountIn(index, Box::new(low), Box::new(high)))
+                } else {
+                    Ok(Expr::Count(index))
+                }
+            }
+            Token::Offset(name) => {
+                let index = self.string_index(strings, &name)?;
+                Ok(Expr::Offset(index, Box::new(self.optional_index(strings)?)))
+            }
+            Token::Length(name) => {
+                let index = self.string_index(strings, &name)?;
+                Ok(Expr::Length(index, Box::new(self.optional_index(strings)?)))
+            }
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                "filesize" => Ok(Expr::Filesize),
+                "all" | "any" | "none" if self.is_keyword("of") => {
+                    let quantifier = match name.as_str() {
+                        "all" => Quantifier::All,
+                        "any" => Quantifier::Any,
+                        _ => Quantifier::None,
+                    };
+                    self.of(quantifier, strings)
+                }
+                read if read.starts_with("int") || read.starts_with("uint") => {
+                    let signed = !read.starts_with('u');
+                    let digits = read.trim_start_matches('u').trim_start_matches("int");
+                    let (bits, big_endian) = match digits.strip_suffix("be") {
+                        Some(bits) => (bits, true),
+                        None => (digits, false),
+                    };
+                    let size = match bits {
+                        "8" => 1,
+                        "16" => 2,
+                        "32" => 4,
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.error(format!("unknown function `{read}`")));
+                        }
+                    };
+                    self.expect_punct("(")?;
+                    let offset = self.expr(strings)?;
+                    self.expect_punct(")")?;
+                    Ok(Expr::Read {
+                        size,
+                        signed,
+                        big_endian,
+                        offset: Box::new(offset),
+                    })
+                }
+                "entrypoint" | "for" | "matches" | "contains" => {
+                    self.pos -= 1;
+                    Err(self.error(format!("`{name}` is not supported")))
+                }
+                other => match self.rule_index(other) {
+                    Some(index) => Ok(Expr::Rule(index)),
+                    None => {
+                        self.pos -= 1;
+                        Err(self.error(format!("undefined identifier `{other}`")))
+                    }
+                },
+            },
+            Token::Text(_) => {
+                self.pos -= 1;
+                Err(self.error("text is only supported in the strings section"))
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected an expression"))
+            }
+        }
+    }
+
+    fn of(&mut self, quantifier: Quantifier, strings: &[StringDef]) -> Result<Expr, String> {
+        // the caller has seen `of`
+        self.pos += 1;
+        let mut set = Vec::new();
+        if self.is_keyword("them") {
+            self.pos += 1;
+            set.extend(0..strings.len());
+        } else {
+            self.expect_punct("(")?;
+            loop {
+                let Token::StringId(name) = self.next()? else {
+                    self.pos -= 1;
+                    return Err(self.error("expected a string identifier"));
+                };
+                let before = set.len();
+                match name.strip_suffix('*') {
+                    Some(prefix) => set.extend(
+                        strings
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, s)| s.name.starts_with(prefix))
+                            .map(|(i, _)| i),
+                    ),
+                    None => set.push(self.string_index(strings, &name)?),
+                }
+                if set.len() == before {
+                    return Err(self.error(format!("no strings match ${name}")));
+                }
+                if self.is_punct(",") {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+            self.expect_punct(")")?;
+        }
+        let range = if self.is_keyword("in") {
+            self.pos += 1;
+            let (low, high) = self.range(strings)?;
+            Some((Box::new(low), Box::new(high)))
+        } else {
+            None
+        };
+        Ok(Expr::Of {
+            quantifier,
+            set,
+            range,
+        })
+    }
+}
+
+fn literal(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("\\x{b:02X}")).collect()
+}
+
+/// Translate a hex string body into a byte regex.
+fn hex_pattern(body: &str) -> Result<String, String> {
+    let chars = body
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+    let mut out = String::from("(?s)");
+    let mut i = 0;
+    let mut bytes = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '(' => out.push_str("(?:"),
+            ')' => out.push(')'),
+            '|' => out.push('|'),
+            '[' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|&c| c == ']')
+                    .ok_or("unterminated jump in hex string")?;
+                let jump = chars[i + 1..i + end].iter().collect::<String>();
+                let parse = |s: &str| {
+                    s.parse::<u32>()
+                        .map_err(|_| format!("bad jump [{jump}] in hex string"))
+                };
+                out.push_str(&match jump.split_once('-') {
+                    None => format!(".{{{}}}", parse(&jump)?),
+                    Some(("", "")) => ".*?".to_string(),
+                    Some((low, "")) => format!(".{{{},}}?", parse(low)?),
+                    Some((low, high)) => {
+                        let low = if low.is_empty() { 0 } else { parse(low)? };
+                        format!(".{{{low},{}}}?", parse(high)?)
+                    }
+                });
+                i += end;
+            }
+            '~' => {
+                let byte = hex_byte(&chars, i + 1)?;
+                out.push_str(&format!("[^{byte}]"));
+                i += 2;
+                bytes += 1;
+            }
+            _ => {
+                out.push_str(&hex_byte(&chars, i)?);
+                i += 1;
+                bytes += 1;
+            }
+        }
+        i += 1;
+    }
+    if bytes == 0 {
+        return Err("empty hex string".to_string());
+    }
+    Ok(out)
+}
+
+/// One hex byte, possibly with `?` nibbles, as a regex atom.
+fn hex_byte(chars: &[char], i: usize) -> Result<String, String> {
+    let (Some(&high), Some(&low)) = (chars.get(i), chars.get(i + 1)) else {
+        return Err("odd number of hex digits".to_string());
+    };
+    let digit = |c: char| c.to_digit(16).ok_or_else(|| format!("bad hex digit {c:?}"));
+    Ok(match (high, low) {
+        ('?', '?') => ".".to_string(),
+        ('?', low) => {
+            let low = digit(low)?;
+            let options = (0..16)
+                .map(|h| format!("\\x{:02X}", h << 4 | low))
+                .collect::<String>();
+            format!("[{options}]")
+        }
+        (high, '?') => {
+            let high = digit(high)? << 4;
+            format!("[\\x{high:02X}-\\x{:02X}]", high | 0x0F)
+        }
+        (high, low) => format!("\\x{:02X}", digit(high)? << 4 | digit(low)?),
+    })
+}
@@ -0,0 +1,225 @@
+#![allow(dead_code)]
+
+//! Just enough of ZIP to list entries and read them back: the central
+//! directory (including ZIP64) and stored or deflated entries.
+use crate::bytes::{u16_le, u32_le, u64_le};
+use crate::disk::read_at;
+use flate2::read::DeflateDecoder;
+use std::io::{Read, Seek, SeekFrom};
+
+const LOCAL_HEADER: u32 = 0x0403_4B50;
+const CENTRAL_HEADER: u32 = 0x0201_4B50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4B50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4B50;
+const ZIP64_LOCATOR: u32 = 0x0706_4B50;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// Entries larger than this are not decompressed.
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+/// Largest central directory read from a file, some half a million entries.
+const MAX_CENTRAL_DIRECTORY: u64 = 64 * 1024 * 1024;
+const EOCD_LENGTH: u64 = 22;
+
+pub struct Entry {
+    pub name: String,
+    pub method: u16,
+    pub compressed_size: u64,
+    pub size: u64,
+    header_offset: u64,
+}
+
+pub struct ZipArchive<'a> {
+    data: &'a [u8],
+    pub entries: Vec<Entry>,
+}
+
+impl<'a> ZipArchive<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, String> {
+        let eocd = find_end_of_central_directory(data).ok_or("Not a ZIP archive")?;
+        let truncated = || "Truncated ZIP central directory".to_string();
+        let mut count = u16_le(data, eocd + 10).ok_or_else(truncated)? as u64;
+        let mut offset = u32_le(data, eocd + 16).ok_or_else(truncated)? as u64;
+        if (count == 0xFFFF || offset == 0xFFFF_FFFF)
+            && let Some(locator) = eocd.checked_sub(20)
+            && u32_le(data, locator) == Some(ZIP64_LOCATOR)
+        {
+            let zip64 = u64_le(data, locator + 8).ok_or_else(truncated)? as usize;
+            if u32_le(data, zip64) != Some(ZIP64_END_OF_CENTRAL_DIRECTORY) {
+                return Err(truncated());
+            }
+            count = u64_le(data, zip64 + 32).ok_or_else(truncated)?;
+            offset = u64_le(data, zip64 + 48).ok_or_else(truncated)?;
+        }
+
+        let directory = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| data.get(offset..))
+            .unwrap_or_default();
+        let entries = entries(directory, count).ok_or_else(truncated)?;
+        Ok(ZipArchive { data, entries })
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// The contents of `entry`, or `None` if it is encrypted, uses another
+    /// compression method, or is unreasonably large.
+    pub fn read(&self, entry: &Entry) -> Option<Vec<u8>> {
+        if entry.size > MAX_ENTRY_SIZE {
+            return None;
+        }
+        self.read_prefix(entry, entry.size as usize)
+    }
+
+    /// Up to `len` bytes from the start of `entry`.
+    pub fn read_prefix(&self, entry: &Entry, len: usize) -> Option<Vec<u8>> {
+        let header = entry.header_offset as usize;
+        if u32_le(self.data, header)? != LOCAL_HEADER {
+            return None;
+        }
+        let start = header
+            + 30
+            + u16_le(self.data, header + 26)? as usize
+            + u16_le(self.data, header + 28)? as usize;
+        let compressed = self
+            .data
+            .get(start..start.checked_add(entry.compressed_size as usize)?)?;
+        let len = len.min(entry.size as usize);
+        if entry.method == METHOD_STORED && compressed.len() < len {
+            return None;
+        }
+        decode(entry, compressed, len)
+    }
+}
+
+/// The entries of the zip in `file`, reading only its central directory.
+pub fn read_entries<R: Read + Seek>(file: &mut R) -> Option<Vec<Entry>> {
+    let length = file.seek(SeekFrom::End(0)).ok()?;
+    let tail_start = length.saturating_sub(EOCD_LENGTH + 0xFFFF);
+    let tail = read_at(file, tail_start, (length - tail_start) as usize)?;
+    let eocd = find_end_of_central_directory(&tail)?;
+    let mut count = u16_le(&tail, eocd + 10)? as u64;
+    let mut size = u32_le(&tail, eocd + 12)? as u64;
+    let mut offset = u32_le(&tail, eocd + 16)? as u64;
+    if (count == 0xFFFF || size == 0xFFFF_FFFF || offset == 0xFFFF_FFFF)
+        && let Some(locator) = eocd.checked_sub(20)
+        && u32_le(&tail, locator) == Some(ZIP64_LOCATOR)
+    {
+        let zip64 = read_at(file, u64_le(&tail, locator + 8)?, 56)?;
+        if u32_le(&zip64, 0)? != ZIP64_END_OF_CENTRAL_DIRECTORY {
+            return None;
+        }
+        count = u64_le(&zip64, 32)?;
+        size = u64_le(&zip64, 40)?;
+        offset = u64_le(&zip64, 48)?;
+    }
+    if size > MAX_CENTRAL_DIRECTORY || offset.checked_add(size)? > length {
+        return None;
+    }
+    let directory = read_at(file, offset, size as usize)?;
+    entries(&directory, count)
+}
+
+/// Up to `len` bytes from the start of `entry` in `file`.
+pub fn read_prefix_from<R: Read + Seek>(
+    file: &mut R,
+    entry: &Entry,
+    len: usize,
+) -> Option<Vec<u8>> {
+    let header = read_at(file, entry.header_offset, 30)?;
+    if u32_le(&header, 0)? != LOCAL_HEADER {
+        return None;
+    }
+    let start =
+        entry.header_offset + 30 + u16_le(&header, 26)? as u64 + u16_le(&header, 28)? as u64;
+    file.seek(SeekFrom::Start(start)).ok()?;
+    let len = len.min(entry.size as usize);
+    let out = decode(entry, file.take(entry.compressed_size), len)?;
+    (entry.method != METHOD_STORED || out.len() == len).then_some(out)
+}
+
+/// The first `len` bytes of an entry from its compressed data.
+fn decode(entry: &Entry, compressed: impl Read, len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    match entry.method {
+        METHOD_STORED => compressed.take(len as u64).read_to_end(&mut out).ok()?,
+        METHOD_DEFLATED => DeflateDecoder::new(compressed)
+            .take(len as u64)
+            .read_to_end(&mut out)
+            .ok()?,
+        _ => return None,
+    };
+    Some(out)
+}
+
+/// `count` central directory headers from the start of `directory`.
+fn entries(directory: &[u8], count: u64) -> Option<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    for _ in 0..count {
+        if u32_le(directory, pos) != Some(CENTRAL_HEADER) {
+            break;
+        }
+        let name_len = u16_le(directory, pos + 28)? as usize;
+        let extra_len = u16_le(directory, pos + 30)? as usize;
+        let comment_len = u16_le(directory, pos + 32)? as usize;
+        let name = directory.get(pos + 46..pos + 46 + name_len)?;
+        let mut entry = Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_le(directory, pos + 10)?,
+            compressed_size: u32_le(directory, pos + 20)? as u64,
+            size: u32_le(directory, pos + 24)? as u64,
+            header_offset: u32_le(directory, pos + 42)? as u64,
+        };
+        let extra_start = pos + 46 + name_len;
+        if let Some(extra) = directory.get(extra_start..extra_start + extra_len) {
+            entry.apply_zip64_extra(extra);
+        }
+        entries.push(entry);
+        pos = extra_start + extra_len + comment_len;
+    }
+    Some(entries)
+}
+
+impl Entry {
+    /// ZIP64 extended information replaces the fields saturated at 0xFFFFFFFF,
+    /// in a fixed order.
+    fn apply_zip64_extra(&mut self, mut extra: &[u8]) {
+        while let (Some(id), Some(len)) = (u16_le(extra, 0), u16_le(extra, 2)) {
+            let Some(body) = extra.get(4..4 + len as usize) else {
+                return;
+            };
+            if id == 0x0001 {
+                let mut pos = 0;
+                for field in [
+                    &mut self.size,
+                    &mut self.compressed_size,
+                    &mut self.header_offset,
+                ] {
+                    if *field == 0xFFFF_FFFF {
+                        let Some(value) = u64_le(body, pos) else {
+                            return;
+                        };
+                        *field = value;
+                        pos += 8;
+                    }
+                }
+                return;
+            }
+            extra = &extra[4 + len as usize..];
+        }
+    }
+}
+
+/// The end of central directory record sits at the end of the file,
+/// followed only by a comment of up to 64KiB.
+fn find_end_of_central_directory(data: &[u8]) -> Option<usize> {
+    let last = data.len().checked_sub(22)?;
+    let first = last.saturating_sub(0xFFFF);
+    (first..=last)
+        .rev()
+        .find(|&pos| u32_le(data, pos) == Some(END_OF_CENTRAL_DIRECTORY))
+}